@@ -0,0 +1,392 @@
+//! Assertions over verified, disclosed data.
+//!
+//! Assertions only see what the presentation disclosed: a value inside a
+//! redacted region can't be checked and is reported as a failure.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::http;
+use crate::jsonpath::JsonPath;
+
+/// Asserts that the value(s) at a JSONPath in the response body equal an
+/// expected JSON value. Parsed from `<jsonpath>=<json value>`.
+#[derive(Debug, Clone)]
+pub struct ExpectJson {
+    pub path: JsonPath,
+    pub expected: Value,
+}
+
+impl FromStr for ExpectJson {
+    type Err = anyhow::Error;
+
+    /// The value is parsed as JSON; if that fails it is taken as a plain
+    /// string, so `$.status=ok` and `$.status="ok"` are equivalent.
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, value) = s.split_once('=').context("expected <jsonpath>=<value>")?;
+        let expected = serde_json::from_str(value.trim())
+            .unwrap_or_else(|_| Value::String(value.trim().to_string()));
+        Ok(Self {
+            path: path.parse()?,
+            expected,
+        })
+    }
+}
+
+impl ExpectJson {
+    /// Checks the assertion against a parsed body, describing the mismatch on
+    /// failure. Every selected value must equal the expected one.
+    pub fn check(&self, body: &Value) -> Result<(), String> {
+        let actual = self.path.select(body);
+        if actual.is_empty() {
+            return Err(format!("{}: no value at path", self.path));
+        }
+        for value in actual {
+            if !json_eq(value, &self.expected) {
+                return Err(format!(
+                    "{}: expected {}, got {}",
+                    self.path, self.expected, value
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Asserts that a query parameter of the attested request has an expected
+/// value. Parsed from `<name>=<value>`, compared with the URL-decoded
+/// parameter, so `q=a b` matches both `q=a%20b` and `q=a+b`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectQuery {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for ExpectQuery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s.split_once('=').context("expected <name>=<value>")?;
+        anyhow::ensure!(!name.is_empty(), "expected <name>=<value>");
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ExpectQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// Checks query parameter assertions against a request head, returning one
+/// message per failure. `revealed` tells whether a byte range of the head
+/// was disclosed.
+///
+/// A parameter sent several times must be asserted once per value: the
+/// values sent and the values asserted for a name must be the same, in any
+/// order. A parameter whose value is redacted can't be evaluated, nor can
+/// any parameter while some parameter's name is redacted, since the hidden
+/// one might be a repeat of it.
+pub fn check_query(
+    expected: &[ExpectQuery],
+    head: &str,
+    revealed: impl Fn(&Range<usize>) -> bool,
+) -> Vec<String> {
+    let mut names: Vec<&str> = Vec::new();
+    for assertion in expected {
+        if !names.contains(&assertion.name.as_str()) {
+            names.push(&assertion.name);
+        }
+    }
+    if names.is_empty() {
+        return Vec::new();
+    }
+
+    let decode = |bytes: &[u8]| -> String {
+        url::form_urlencoded::parse(bytes)
+            .next()
+            .map(|(name, _)| name.into_owned())
+            .unwrap_or_default()
+    };
+    let query = http::query_range(head).unwrap_or(0..0);
+    let fields: Vec<_> = http::form_field_ranges(&head.as_bytes()[query.clone()])
+        .into_iter()
+        .map(|(name, value)| {
+            let offset = |r: Range<usize>| query.start + r.start..query.start + r.end;
+            (offset(name), offset(value))
+        })
+        .collect();
+    let names_hidden = fields.iter().any(|(name, _)| !revealed(name));
+
+    let mut failures = Vec::new();
+    for name in names {
+        let mut want: Vec<&str> = expected
+            .iter()
+            .filter(|a| a.name == name)
+            .map(|a| a.value.as_str())
+            .collect();
+        if names_hidden {
+            failures.push(format!(
+                "cannot evaluate --expect-query {name}: a query parameter name is redacted"
+            ));
+            continue;
+        }
+        let mut sent = Vec::new();
+        let mut redacted = false;
+        for (name_range, value_range) in &fields {
+            if decode(&head.as_bytes()[name_range.clone()]) != name {
+                continue;
+            }
+            if revealed(value_range) {
+                sent.push(decode(&head.as_bytes()[value_range.clone()]));
+            } else {
+                redacted = true;
+            }
+        }
+        if redacted {
+            failures.push(format!(
+                "cannot evaluate --expect-query {name}: its value is redacted"
+            ));
+            continue;
+        }
+        let mut got: Vec<&str> = sent.iter().map(String::as_str).collect();
+        want.sort_unstable();
+        got.sort_unstable();
+        if want != got {
+            failures.push(match got.as_slice() {
+                [] => format!("query parameter {name:?} is not in the request"),
+                _ => format!("query parameter {name:?}: expected {want:?}, got {got:?}"),
+            });
+        }
+    }
+    failures
+}
+
+/// A comparison operator for [`Comparison`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Operators in match order: two-character operators come first so `<=`
+    /// isn't read as `<`.
+    const ALL: [CompareOp; 6] = [
+        CompareOp::Le,
+        CompareOp::Ge,
+        CompareOp::Eq,
+        CompareOp::Ne,
+        CompareOp::Lt,
+        CompareOp::Gt,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+impl FromStr for CompareOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|op| op.as_str() == s.trim())
+            .with_context(|| format!("unknown comparison operator: {s}"))
+    }
+}
+
+/// Asserts that the value at a JSONPath compares to a constant, parsed from
+/// `<jsonpath> <op> <json value>` (e.g. `$.odds.home > 1.5`).
+///
+/// `==` and `!=` compare any JSON values; ordering operators require numbers
+/// on both sides.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub path: JsonPath,
+    pub op: CompareOp,
+    pub value: Value,
+}
+
+impl FromStr for Comparison {
+    type Err = anyhow::Error;
+
+    /// As with [`ExpectJson`], a value that isn't valid JSON is taken as a
+    /// plain string.
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, op) = CompareOp::ALL
+            .into_iter()
+            .filter_map(|op| s.find(op.as_str()).map(|i| (i, op)))
+            .min_by_key(|(i, _)| *i)
+            .context("expected <jsonpath> <op> <value> with op one of == != < <= > >=")?;
+        let value = s[start + op.as_str().len()..].trim();
+        Ok(Self {
+            path: s[..start].parse()?,
+            op,
+            value: serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.path, self.op.as_str(), self.value)
+    }
+}
+
+impl Comparison {
+    /// Checks `actual`, the value found at the path, against the comparison.
+    pub fn check(&self, actual: &Value) -> Result<(), String> {
+        let holds = match self.op {
+            CompareOp::Eq => json_eq(actual, &self.value),
+            CompareOp::Ne => !json_eq(actual, &self.value),
+            op => {
+                let (Some(a), Some(b)) = (actual.as_f64(), self.value.as_f64()) else {
+                    return Err(format!("{self}: got {actual}, which is not a number"));
+                };
+                match op {
+                    CompareOp::Lt => a < b,
+                    CompareOp::Le => a <= b,
+                    CompareOp::Gt => a > b,
+                    _ => a >= b,
+                }
+            }
+        };
+        if holds {
+            Ok(())
+        } else {
+            Err(format!("{self}: got {actual}"))
+        }
+    }
+}
+
+/// Asserts a relationship between two values in the same body, parsed from
+/// `<jsonpath> <op> <jsonpath>` (e.g. `$.home_odds > $.away_odds`).
+///
+/// Each path must select exactly one value. Numbers and numeric strings are
+/// compared as numbers, so `"1.85"` and `1.9` are ordered; `==` and `!=`
+/// fall back to JSON equality otherwise.
+#[derive(Debug, Clone)]
+pub struct FieldComparison {
+    pub left: JsonPath,
+    pub op: CompareOp,
+    pub right: JsonPath,
+}
+
+impl FromStr for FieldComparison {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, op) = CompareOp::ALL
+            .into_iter()
+            .filter_map(|op| s.find(op.as_str()).map(|i| (i, op)))
+            .min_by_key(|(i, _)| *i)
+            .context("expected <jsonpath> <op> <jsonpath> with op one of == != < <= > >=")?;
+        Ok(Self {
+            left: s[..start].parse()?,
+            op,
+            right: s[start + op.as_str().len()..].parse()?,
+        })
+    }
+}
+
+impl fmt::Display for FieldComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op.as_str(), self.right)
+    }
+}
+
+impl FieldComparison {
+    /// Checks the comparison against a parsed body, reporting both values on
+    /// failure.
+    pub fn check(&self, body: &Value) -> Result<(), String> {
+        let select = |path: &JsonPath| match path.select(body).as_slice() {
+            [value] => Ok((*value).clone()),
+            [] => Err(format!("{self}: no value at {path}")),
+            values => Err(format!(
+                "{self}: {path} selects {} values, expected one",
+                values.len()
+            )),
+        };
+        let (a, b) = (select(&self.left)?, select(&self.right)?);
+
+        let numbers = as_number(&a).zip(as_number(&b));
+        let holds = match (self.op, numbers) {
+            (CompareOp::Eq, Some((x, y))) => x == y,
+            (CompareOp::Ne, Some((x, y))) => x != y,
+            (CompareOp::Eq, None) => json_eq(&a, &b),
+            (CompareOp::Ne, None) => !json_eq(&a, &b),
+            (CompareOp::Lt, Some((x, y))) => x < y,
+            (CompareOp::Le, Some((x, y))) => x <= y,
+            (CompareOp::Gt, Some((x, y))) => x > y,
+            (CompareOp::Ge, Some((x, y))) => x >= y,
+            (_, None) => {
+                return Err(format!(
+                    "{self}: cannot order {} = {a} and {} = {b}, both must be numbers",
+                    self.left, self.right
+                ))
+            }
+        };
+        if holds {
+            Ok(())
+        } else {
+            Err(format!(
+                "{self}: got {} = {a}, {} = {b}",
+                self.left, self.right
+            ))
+        }
+    }
+}
+
+/// A JSON number, or a string holding one.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses a revealed JSON value from its transcript bytes. A string value
+/// whose span excludes the quotes is taken as the string itself.
+pub fn parse_revealed_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+/// Type-aware equality: numbers compare numerically (`1` equals `1.0`),
+/// everything else structurally.
+pub fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| json_eq(x, y))
+        }
+        (Value::Object(xs), Value::Object(ys)) => {
+            xs.len() == ys.len()
+                && xs
+                    .iter()
+                    .all(|(k, x)| ys.get(k).is_some_and(|y| json_eq(x, y)))
+        }
+        _ => a == b,
+    }
+}
@@ -0,0 +1,146 @@
+//! Batch proving with bounded concurrency.
+//!
+//! A [`ProverPool`] runs at most `concurrency` proofs at once through a shared
+//! [`Djinn`]. [`ProverPool::run_batch`] pulls the next item only once a slot
+//! is free, so items are taken at the rate proofs complete rather than all
+//! at once, and a failing item only costs its own slot. An optional [`RateLimiter`] additionally spaces out proof starts to
+//! stay within the target API's quota.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::{
+    sync::Semaphore,
+    task::{JoinError, JoinSet},
+};
+use tracing::warn;
+
+use crate::{
+    prove::{ProveOptions, ProveResult},
+    rate::{Rate, RateLimiter},
+    Djinn,
+};
+
+/// A joined batch task: the item's index and its result.
+type Finished = Result<(usize, Result<ProveResult>), JoinError>;
+
+/// Throughput of a finished batch.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchStats {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub elapsed_secs: f64,
+    /// Completed proofs (successful or not) per second.
+    pub proofs_per_sec: f64,
+    /// Proofs delayed by the rate limit, and the total delay.
+    pub throttled: usize,
+    pub throttled_secs: f64,
+}
+
+/// Proves requests through one [`Djinn`] with at most `concurrency` in flight.
+#[derive(Clone)]
+pub struct ProverPool {
+    djinn: Djinn,
+    permits: Arc<Semaphore>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl ProverPool {
+    pub fn new(djinn: Djinn, concurrency: usize) -> Self {
+        Self {
+            djinn,
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            limiter: None,
+        }
+    }
+
+    /// Starts at most `rate` proofs, shared by all clones of the pool.
+    pub fn with_rate_limit(mut self, rate: Rate) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(rate)));
+        self
+    }
+
+    /// Waits for the rate limit, if any, returning how long it throttled.
+    async fn throttle(&self) -> Duration {
+        match &self.limiter {
+            Some(limiter) => limiter.acquire().await,
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Proves one request, waiting for a free slot first.
+    pub async fn prove_one(&self, opts: &ProveOptions) -> Result<ProveResult> {
+        let _permit = self.permits.acquire().await?;
+        self.throttle().await;
+        self.djinn.prove(opts).await
+    }
+
+    /// Proves every item, calling `on_done` with each item's index and result
+    /// as it completes (not necessarily in order).
+    pub async fn run_batch<I>(
+        &self,
+        items: I,
+        mut on_done: impl FnMut(usize, Result<ProveResult>),
+    ) -> BatchStats
+    where
+        I: IntoIterator<Item = ProveOptions>,
+    {
+        let start = Instant::now();
+        let mut stats = BatchStats::default();
+        let mut tasks = JoinSet::new();
+        let mut record = |done: Finished, stats: &mut BatchStats| match done {
+            Ok((index, result)) => {
+                if result.is_ok() {
+                    stats.succeeded += 1;
+                } else {
+                    stats.failed += 1;
+                }
+                on_done(index, result);
+            }
+            Err(e) => {
+                warn!("batch proof task failed: {e}");
+                stats.failed += 1;
+            }
+        };
+
+        for (index, opts) in items.into_iter().enumerate() {
+            // Backpressure: take the next item only once a slot is free.
+            let permit = self
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("pool semaphore is never closed");
+            while let Some(done) = tasks.try_join_next() {
+                record(done, &mut stats);
+            }
+            let throttled = self.throttle().await;
+            if !throttled.is_zero() {
+                stats.throttled += 1;
+                stats.throttled_secs += throttled.as_secs_f64();
+            }
+            stats.total += 1;
+            let djinn = self.djinn.clone();
+            tasks.spawn(async move {
+                let result = djinn.prove(&opts).await;
+                drop(permit);
+                (index, result)
+            });
+        }
+        while let Some(done) = tasks.join_next().await {
+            record(done, &mut stats);
+        }
+
+        let elapsed = start.elapsed();
+        stats.elapsed_secs = elapsed.as_secs_f64();
+        stats.proofs_per_sec = if elapsed > Duration::ZERO {
+            stats.total as f64 / stats.elapsed_secs
+        } else {
+            0.0
+        };
+        stats
+    }
+}
@@ -13,46 +13,137 @@
 //! The output file contains a bincode-serialized `Presentation` that any
 //! verifier with the Notary's public key can independently check.
 
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write as _};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use futures::io::{AsyncReadExt as _, AsyncWriteExt as _};
-use http_body_util::Empty;
-use hyper::{body::Bytes, Request, StatusCode};
-use hyper_util::rt::TokioIo;
-use tokio_util::compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
-use tracing::info;
-
-use tlsn::{
-    attestation::{
-        presentation::Presentation,
-        request::{Request as AttestationRequest, RequestConfig},
-        Attestation, CryptoProvider,
+use hyper::body::Bytes;
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "grpc-web")]
+use djinn_tlsn_tools::grpc_web;
+use djinn_tlsn_tools::{
+    assertions::Comparison,
+    batch::{BatchStats, ProverPool},
+    bundle::Bundle,
+    digest::DigestCredentials,
+    disclosure::{DisclosureThreshold, HeaderPrefix, RevealSelector},
+    envelope::Envelope,
+    http,
+    jsonpath::JsonPath,
+    manifest::Manifest,
+    metrics::RunMetrics,
+    notary::{self, NotaryError},
+    output::{ensure_parent_dir, write_atomic},
+    prove::{
+        self, JsonKeyOrder, ProveOptions, ProveResult, RequestHeader, RequestSpec,
+        SavedAttestation, Transport, UserAgentDisclosure,
     },
-    config::{
-        prove::ProveConfig,
-        prover::ProverConfig,
-        tls::TlsClientConfig,
-        tls_commit::{mpc::MpcTlsConfig, TlsCommitConfig},
-    },
-    connection::{HandshakeData, ServerName},
-    prover::ProverOutput,
-    transcript::TranscriptCommitConfig,
-    Session,
+    proxy::{HttpProxy, Socks5Proxy, PROXY_AUTHORIZATION_ENV},
+    rate::Rate,
+    resolve::EncryptedResolver,
+    status, Djinn, DjinnConfig,
 };
-use tlsn_formats::http::{DefaultHttpCommitter, HttpCommit, HttpTranscript};
-
-use djinn_tlsn_tools::{MAX_RECV_DATA, MAX_SENT_DATA};
-
-const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
 
 #[derive(Parser, Debug)]
-#[command(name = "djinn-tlsn-prover", about = "Generate TLSNotary proof for an HTTPS request")]
+#[command(
+    name = "djinn-tlsn-prover",
+    about = "Generate TLSNotary proof for an HTTPS request",
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Full URL to fetch (including query params)
+    #[arg(long, required_unless_present = "batch", conflicts_with = "batch")]
+    url: Option<String>,
+
+    /// Prove every URL in this file (one per line; blank lines and `#`
+    /// comments skipped) into --output-dir, with all other options shared.
+    /// Prints one JSON line per URL as it completes, then batch stats
+    #[arg(long, requires = "output_dir")]
+    batch: Option<PathBuf>,
+
+    /// Maximum number of --batch proofs in flight at once
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+
+    /// Limit --batch proofs to a request quota, <n>/<duration> (e.g. 10/m,
+    /// 500/1h). Bursts of up to n are allowed; further proofs wait
+    #[arg(long, requires = "batch")]
+    rate: Option<Rate>,
+
+    /// HTTP method to send. HEAD responses are attested without a body
+    #[arg(long, default_value = "GET", value_parser = ["GET", "HEAD", "POST", "PUT"])]
+    method: String,
+
+    /// Request body to send with --method POST or PUT
+    #[arg(long, conflicts_with = "body_file")]
+    body: Option<String>,
+
+    /// Read the POST or PUT request body from this file
+    #[arg(long)]
+    body_file: Option<PathBuf>,
+
+    /// Key order of a JSON request body on the wire: `verbatim` sends the
+    /// body exactly as given; `sorted` re-serializes it compactly with every
+    /// object's keys sorted, for servers that sign or hash the body
+    #[arg(long, default_value = "verbatim")]
+    json_key_order: JsonKeyOrder,
+
+    /// Send a URL-encoded form body built from these fields (`name=value`;
+    /// repeatable, in order) with the form Content-Type. The form is
+    /// revealed in the presentation except for --redact-form-field values
+    #[arg(long, conflicts_with_all = ["body", "body_file", "content_type"])]
+    form: Vec<FormField>,
+
+    /// Form field whose value is hidden in the presentation (repeatable,
+    /// case-insensitive). The field name stays revealed
+    #[arg(long, requires = "form")]
+    redact_form_field: Vec<String>,
+
+    /// Content-Type of the POST or PUT request body
+    #[arg(long, default_value = "application/json")]
+    content_type: String,
+
+    /// Send the POST body as a gRPC-Web unary request: the body (e.g. from
+    /// --body-file) is the serialized protobuf message, framed and sent with
+    /// the gRPC-Web content type. `text` base64-encodes it. Needs --method POST
+    #[cfg(feature = "grpc-web")]
+    #[arg(long, value_enum, conflicts_with = "content_type")]
+    grpc_web: Option<GrpcWebMode>,
+
+    /// Send the requests in this JSON file one after the other over a single
+    /// connection, attesting all of them in one presentation: an array of
+    /// `{"method", "path", "headers", "body"}` objects (only `path` is
+    /// required). --url then only names the target
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "batch", "method", "body", "body_file", "form", "expect_continue", "digest_user",
+            "spool_body", "accept_compressed", "reveal_only", "reveal_json_path",
+            "reveal_and_assert",
+        ]
+    )]
+    requests_file: Option<PathBuf>,
+
+    /// Send the request body only after the server answers the request head
+    /// with `100 Continue` (`Expect: 100-continue`)
+    #[arg(long)]
+    expect_continue: bool,
+
+    /// Milliseconds to wait for `100 Continue` before sending the body anyway
+    #[arg(long, default_value_t = 1000)]
+    continue_timeout_ms: u64,
+
+    /// Advanced: flush the request head before writing the body, instead of
+    /// sending both in one write. Changes the on-wire bytes the notary
+    /// captures; the summary records it as `request_writes`
     #[arg(long)]
-    url: String,
+    flush_after_headers: bool,
 
     /// Notary server hostname
     #[arg(long, default_value = "127.0.0.1")]
@@ -62,233 +153,806 @@ struct Args {
     #[arg(long, default_value_t = 7047)]
     notary_port: u16,
 
-    /// Output file path for the serialized presentation
+    /// Connect to the notary over TLS, verifying its certificate against
+    /// --notary-host and the system roots (or --notary-ca)
+    #[arg(long)]
+    notary_tls: bool,
+
+    /// PEM file of CA certificates to trust for --notary-tls instead of the
+    /// system roots, e.g. a self-signed dev notary's certificate
+    #[arg(long, requires = "notary_tls")]
+    notary_ca: Option<PathBuf>,
+
+    /// Route the notary connection through this SOCKS5 proxy,
+    /// socks5://[user:pass@]host:port (socks5h:// to resolve at the proxy).
+    /// Pass the --proxy URL again to send both through the same proxy
     #[arg(long)]
-    output: PathBuf,
+    notary_proxy: Option<Socks5Proxy>,
+
+    /// Record the bytes exchanged with the notary to this file, for
+    /// reproducing a notary-protocol failure with --replay-notary
+    #[arg(long, conflicts_with = "batch")]
+    record_notary: Option<PathBuf>,
+
+    /// Debugging only: feed a --record-notary recording back in place of a
+    /// live notary. A replayed session never produces a valid proof
+    #[arg(long, conflicts_with_all = ["batch", "record_notary"])]
+    replay_notary: Option<PathBuf>,
+
+    /// Output file path for the serialized presentation. `-` writes it to
+    /// stdout, and the JSON summary to stderr
+    #[arg(
+        long,
+        required_unless_present_any = ["output_dir", "dry_run"],
+        conflicts_with = "output_dir"
+    )]
+    output: Option<PathBuf>,
+
+    /// Directory to write the presentation into, as `presentation.bin` or,
+    /// with --content-addressed, as `<sha256>.bin` (`.json` with --format
+    /// json)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// How to write the presentation: raw bincode, or a JSON envelope holding
+    /// it base64-encoded with unattested metadata. The verifier reads either
+    #[arg(long, value_enum, default_value_t = OutputFormat::Bincode, conflicts_with = "bundle")]
+    format: OutputFormat,
+
+    /// Create missing parent directories of the output path instead of
+    /// failing
+    #[arg(long)]
+    create_dirs: bool,
+
+    /// Write a single bundle file holding the presentation, its manifest and
+    /// this summary, instead of a presentation and manifest sidecar
+    #[arg(long)]
+    bundle: bool,
+
+    /// Name the output file by the SHA-256 of the serialized presentation
+    #[arg(long, requires = "output_dir")]
+    content_addressed: bool,
+
+    /// Authenticate with HTTP Digest auth as this user. The challenge is
+    /// fetched over a separate, unattested connection; only the authenticated
+    /// request is notarized, with its Authorization header redacted
+    #[arg(long, requires = "digest_password_file")]
+    digest_user: Option<String>,
+
+    /// File holding the --digest-user password (a trailing newline is ignored)
+    #[arg(long, requires = "digest_user")]
+    digest_password_file: Option<PathBuf>,
 
     /// Headers to redact from the presentation (comma-separated, case-insensitive)
     #[arg(long, default_value = "authorization,apikey,x-api-key")]
     redact_headers: String,
+
+    /// Reveal only the first N bytes of a request header's value, as
+    /// `<header>:<N>` (e.g. `authorization:7` for `Bearer `; repeatable).
+    /// Overrides --redact-headers for that header. The prefix is disclosed
+    /// in the clear, so it must never include secret material
+    #[arg(long)]
+    reveal_header_prefix: Vec<HeaderPrefix>,
+
+    /// Hide every match of this regex in the response body (repeatable), e.g.
+    /// session IDs or email addresses, while revealing the rest of it.
+    /// Matches are clamped to the body and overlapping ones merged
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "reveal_response_meta_only", "reveal_json_path", "reveal_only", "accept_compressed",
+        ]
+    )]
+    redact_body_regex: Vec<regex::bytes::Regex>,
+
+    /// Escape hatch for servers whose messages don't parse as HTTP: hide
+    /// every CRLF-delimited line of the raw request matching this regex
+    /// (repeatable). The rest of the raw transcript is revealed, except the
+    /// values of --redact-headers. Coarser and less safe than structured
+    /// redaction: a line the pattern misses is revealed
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "reveal_response_meta_only", "reveal_json_path", "reveal_only", "reveal_and_assert",
+            "redact_body_regex", "reveal_header_prefix", "form", "user_agent_disclosure",
+        ]
+    )]
+    redact_sent_line: Vec<regex::bytes::Regex>,
+
+    /// Like --redact-sent-line, for lines of the raw response
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "reveal_response_meta_only", "reveal_json_path", "reveal_only", "reveal_and_assert",
+            "redact_body_regex", "reveal_header_prefix", "form", "user_agent_disclosure",
+        ]
+    )]
+    redact_recv_line: Vec<regex::bytes::Regex>,
+
+    /// Reveal only the response status line and the headers listed in
+    /// --response-meta-headers; all other response headers and the body stay hidden
+    #[arg(long)]
+    reveal_response_meta_only: bool,
+
+    /// Response headers revealed in --reveal-response-meta-only mode (comma-separated, case-insensitive)
+    #[arg(long, default_value = "content-type")]
+    response_meta_headers: String,
+
+    /// Accept a 3xx response without following it, to prove where a URL
+    /// redirects. The Location header is always revealed
+    #[arg(long)]
+    accept_redirect: bool,
+
+    /// Send `If-None-Match` with this entity tag, quotes included (e.g.
+    /// '"33a64df5"'). A `304 Not Modified` answer is attested instead of
+    /// failing, proving the resource unchanged
+    #[arg(long, value_name = "ETAG", conflicts_with = "requests_file")]
+    if_none_match: Option<String>,
+
+    /// Send `If-Modified-Since` with this HTTP date (e.g. `Wed, 21 Oct 2015
+    /// 07:28:00 GMT`). A `304 Not Modified` answer is attested instead of
+    /// failing
+    #[arg(long, value_name = "DATE", conflicts_with = "requests_file")]
+    if_modified_since: Option<String>,
+
+    /// TLS server name (SNI) to send instead of the URL host. The server
+    /// certificate is verified against this name
+    #[arg(long)]
+    server_name: Option<String>,
+
+    /// `Host` header to send instead of the URL host. With --server-name,
+    /// SNI and Host can differ (e.g. to test domain fronting)
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Extra request header as `"Name: Value"` (repeatable), replacing a
+    /// default header of the same name. It is committed like the others:
+    /// add secret ones (e.g. X-RapidAPI-Key) to --redact-headers
+    #[arg(long = "header", value_name = "NAME: VALUE")]
+    headers: Vec<RequestHeader>,
+
+    /// Send the request to the target over plain TLS and print the status,
+    /// response headers and sizes against --max-sent-data and
+    /// --max-recv-data, without contacting the notary. Nothing is proved
+    #[arg(long, conflicts_with_all = ["batch", "output", "output_dir"])]
+    dry_run: bool,
+
+    /// `User-Agent` to send instead of the default Chrome one
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    /// How much of the User-Agent to disclose, whatever --redact-headers
+    /// says: `reveal`, `redact` (the header name only) or `prefix:<N>` (the
+    /// first N bytes). It is committed either way
+    #[arg(long, value_name = "MODE")]
+    user_agent_disclosure: Option<UserAgentDisclosure>,
+
+    /// Stream the received response body to this file while proving instead
+    /// of leaving it unread. The transcript stays in memory regardless
+    #[arg(long)]
+    spool_body: Option<PathBuf>,
+
+    /// Send `Accept-Encoding: gzip, deflate, br` instead of `identity`. A
+    /// compressed response is attested as compressed bytes and its body can
+    /// only be revealed whole; the verifier's --decompress inflates it
+    #[arg(long, conflicts_with_all = ["reveal_json_path", "reveal_and_assert"])]
+    accept_compressed: bool,
+
+    /// Most bytes the request may send in the MPC session, e.g. `4096` or
+    /// `8k` (256 bytes to 64 KiB). Larger limits make MPC setup slower
+    #[arg(long, default_value = "4k", value_parser = parse_data_size)]
+    max_sent_data: usize,
+
+    /// Most bytes the response may return in the MPC session, e.g. `256k` or
+    /// `2m` (1 KiB to 16 MiB). Larger limits make MPC setup slower
+    #[arg(long, default_value = "256k", value_parser = parse_data_size)]
+    max_recv_data: usize,
+
+    /// Read the response from the TLS connection in chunks of exactly this
+    /// many bytes. Default: hyper's adaptive buffer (8 KiB, growing as needed)
+    #[arg(long, value_parser = parse_chunk_size)]
+    target_read_chunk_size: Option<usize>,
+
+    /// Resolve the target host over encrypted DNS instead of the system
+    /// resolver: doh://<ip>[:port] or dot://<ip>[:port]
+    #[arg(long, requires = "resolver_tls_name")]
+    resolver: Option<String>,
+
+    /// Name to verify the --resolver certificate against (e.g. cloudflare-dns.com)
+    #[arg(long)]
+    resolver_tls_name: Option<String>,
+
+    /// Connect to the target through this SOCKS5 proxy,
+    /// socks5://[user:pass@]host:port. With socks5h:// the proxy resolves the
+    /// host name; with socks5:// it is resolved locally (via --resolver if
+    /// given)
+    #[arg(long, conflicts_with = "http_proxy")]
+    proxy: Option<Socks5Proxy>,
+
+    /// Tunnel the target connection through this HTTP proxy with CONNECT,
+    /// http://host:port. The Proxy-Authorization value, if needed, is read
+    /// from the DJINN_PROXY_AUTHORIZATION environment variable
+    #[arg(long)]
+    http_proxy: Option<HttpProxy>,
+
+    /// Debugging only: write the raw, unredacted TLS transcript to this file.
+    /// The dump contains every secret sent or received (API keys, tokens)
+    #[arg(long)]
+    dump_transcript: Option<PathBuf>,
+
+    /// Also write the attestation and the secrets that open it (the full,
+    /// unredacted transcript) to this file, readable by the owner only, for
+    /// building other presentations later with `rebuild`
+    #[arg(long, conflicts_with_all = ["batch", "dry_run"])]
+    attestation_output: Option<PathBuf>,
+
+    /// After proving, print the request as disclosed in the presentation (the
+    /// exact bytes sent, after header normalization, with redactions) to
+    /// stderr, or to this file if given
+    #[arg(long, num_args = 0..=1, value_name = "PATH")]
+    echo_request: Option<Option<PathBuf>>,
+
+    /// Retry a failed connection to the notary or the target this many times.
+    /// Only connecting is retried; a failed MPC session is not
+    #[arg(long, default_value_t = 0)]
+    connect_retries: u32,
+
+    /// Milliseconds before the first connection retry, doubled for each
+    /// further one, plus random jitter of up to half the delay
+    #[arg(long, default_value_t = 200)]
+    connect_backoff_ms: u64,
+
+    /// Seconds allowed for connecting to the notary
+    #[arg(long, default_value_t = 10)]
+    notary_handshake_timeout: u64,
+
+    /// Seconds allowed for MPC setup (preprocessing) with the notary
+    #[arg(long, default_value_t = 120)]
+    mpc_setup_timeout: u64,
+
+    /// Seconds allowed for the notary to sign and return the attestation
+    #[arg(long, default_value_t = 30)]
+    attestation_timeout: u64,
+
+    /// Seconds allowed for each proof as a whole, from connecting to the
+    /// notary to the finished presentation. On expiry the session is aborted
+    /// with status `timeout` and the phase it was in is reported
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Abort the whole run with status `deadline_exceeded` if it hasn't
+    /// finished by this RFC 3339 time, e.g. 2025-06-01T18:00:00Z
+    #[arg(long)]
+    deadline: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+    /// Warn when the presentation reveals more than this much of the
+    /// transcript: a percentage of all bytes (e.g. `50%`) or a byte count
+    #[arg(long)]
+    warn_on_large_disclosure: Option<DisclosureThreshold>,
+
+    /// Reveal only the values of the JSON response body selected by this
+    /// JSONPath (e.g. `$.bookmakers[*].markets[*].outcomes[*].price`;
+    /// repeatable) instead of the whole body. Everything else in the body is
+    /// committed but stays hidden
+    #[arg(long)]
+    reveal_json_path: Vec<JsonPath>,
+
+    /// Reveal a response JSON field and assert a comparison on it, as
+    /// `<jsonpath> <op> <value>` (e.g. `$.price > 100`; repeatable). Each
+    /// assertion is checked before proving and recorded in a
+    /// `.manifest.json` sidecar for the verifier's --against-manifest
+    #[arg(long)]
+    reveal_and_assert: Vec<Comparison>,
+
+    /// Reveal exactly these parts of the transcript and nothing else, not
+    /// even the request or status line: `sent:<start>..<end>`,
+    /// `recv:<start>..<end>` (transcript byte offsets) or `json:<jsonpath>`
+    /// (repeatable). Warns when verifiers won't be able to parse the result
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "reveal_and_assert",
+            "reveal_json_path",
+            "reveal_response_meta_only",
+            "reveal_header_prefix",
+        ]
+    )]
+    reveal_only: Vec<RevealSelector>,
+
+    /// Turn advisory checks (--warn-on-large-disclosure) into failures
+    #[arg(long)]
+    strict: bool,
+
+    /// On exit, write a one-shot OpenMetrics summary of the run (duration,
+    /// status, presentation size, disclosure ratio) to this file, atomically
+    #[arg(long)]
+    emit_openmetrics_on_exit: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Build a new presentation from an --attestation-output file, offline,
+    /// disclosing what the options given before `rebuild` select (e.g.
+    /// `--reveal-json-path '$.price' --output price.bin rebuild --attestation
+    /// run.attestation`)
+    Rebuild {
+        /// Attestation file written by --attestation-output
+        #[arg(long)]
+        attestation: PathBuf,
+    },
+}
+
+/// A `--form` field, parsed from `name=value`.
+#[derive(Clone, Debug)]
+struct FormField {
+    name: String,
+    value: String,
+}
+
+impl std::str::FromStr for FormField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s.split_once('=').context("expected name=value")?;
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// The bincode presentation
+    Bincode,
+    /// A JSON envelope; see `djinn_tlsn_tools::envelope`
+    Json,
+}
+
+#[cfg(feature = "grpc-web")]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GrpcWebMode {
+    /// `application/grpc-web+proto`
+    Binary,
+    /// `application/grpc-web-text+proto` (base64)
+    Text,
+}
 
+#[tokio::main]
+async fn main() {
     let args = Args::parse();
+    // With the presentation on stdout, everything else goes to stderr.
+    if writes_to_stdout(&args) {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
+    let started = Instant::now();
+
+    let result = match args.deadline {
+        Some(deadline) => status::with_deadline(deadline.into(), run(&args)).await,
+        None => run(&args).await,
+    };
+    if let Some(path) = &args.emit_openmetrics_on_exit {
+        let mut metrics = match &result {
+            Ok(metrics) => metrics.clone(),
+            Err(e) => RunMetrics::new(Duration::ZERO, status::status_of(e)),
+        };
+        metrics.duration = started.elapsed();
+        if let Err(e) = write_atomic(path, metrics.to_openmetrics().as_bytes()) {
+            eprintln!("warning: failed to write metrics: {e:#}");
+        }
+    }
 
-    // Parse the URL to extract host, port, path
-    let url: hyper::Uri = args.url.parse().context("invalid URL")?;
-    let host = url.host().context("URL must have a host")?.to_string();
-    let port = url.port_u16().unwrap_or(443);
-    let path = url
-        .path_and_query()
-        .map(|pq| pq.as_str())
-        .unwrap_or("/")
-        .to_string();
+    if let Err(e) = result {
+        // Report failures as JSON too, with a status callers can match on.
+        print_summary(&args, &failure_summary(&e));
+        eprintln!("Error: {e:?}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &Args) -> Result<RunMetrics> {
+    // Check the output location before spending time on a proof.
+    match (&args.output, &args.output_dir) {
+        (Some(_), _) if writes_to_stdout(args) => {
+            anyhow::ensure!(
+                args.bundle || args.reveal_and_assert.is_empty(),
+                "--output - has nowhere to write the --reveal-and-assert manifest; add --bundle"
+            );
+        }
+        (Some(path), _) => ensure_parent_dir(path, args.create_dirs)?,
+        (None, Some(dir)) => ensure_parent_dir(&dir.join("presentation.bin"), args.create_dirs)?,
+        (None, None) => {}
+    }
+
+    let transport = transport(args)?;
+    if let Some(Command::Rebuild { attestation }) = &args.command {
+        return rebuild(args, Djinn::new(DjinnConfig::new(transport)), attestation);
+    }
+    if args.dry_run {
+        let url = args.url.as_deref().expect("clap requires --url or --batch");
+        let report = prove::dry_run(&transport, &prove_options(args, url)?).await?;
+        let mut summary = serde_json::json!({ "status": "dry_run" });
+        if let (Some(summary), serde_json::Value::Object(report)) =
+            (summary.as_object_mut(), serde_json::to_value(&report)?)
+        {
+            summary.extend(report);
+        }
+        print_summary(args, &summary);
+        return Ok(RunMetrics::new(Duration::ZERO, "dry_run"));
+    }
+    let djinn = Djinn::new(DjinnConfig::new(transport));
 
-    let redact_set: Vec<String> = args
-        .redact_headers
-        .split(',')
-        .map(|s| s.trim().to_lowercase())
-        .collect();
-
-    info!("Connecting to notary at {}:{}", args.notary_host, args.notary_port);
-
-    // Connect to the Notary server via TCP.
-    let notary_socket =
-        tokio::net::TcpStream::connect((args.notary_host.as_str(), args.notary_port))
-            .await
-            .context("failed to connect to notary server")?;
-
-    // Create a session with the notary.
-    let session = Session::new(notary_socket.compat());
-    let (driver, mut handle) = session.split();
-    let driver_task = tokio::spawn(driver);
-
-    // Create a new prover.
-    let prover = handle
-        .new_prover(ProverConfig::builder().build()?)?
-        .commit(
-            TlsCommitConfig::builder()
-                .protocol(
-                    MpcTlsConfig::builder()
-                        .max_sent_data(MAX_SENT_DATA)
-                        .max_recv_data(MAX_RECV_DATA)
-                        .build()?,
-                )
-                .build()?,
-        )
-        .await?;
-
-    info!("Connecting to target server {}:{}", host, port);
-
-    // Open TCP connection to the target server.
-    let client_socket = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
-
-    // Bind prover to the server connection.
-    let (tls_connection, prover_fut) = prover.connect(
-        TlsClientConfig::builder()
-            .server_name(ServerName::Dns(host.clone().try_into()?))
-            .build()?,
-        client_socket.compat(),
-    ).await?;
-    let tls_connection = TokioIo::new(tls_connection.compat());
-
-    let prover_task = tokio::spawn(prover_fut);
-
-    // HTTP handshake over the TLS connection.
-    let (mut request_sender, connection): (
-        hyper::client::conn::http1::SendRequest<Empty<Bytes>>,
-        _,
-    ) = hyper::client::conn::http1::handshake(tls_connection).await?;
-    tokio::spawn(connection);
-
-    // Build the HTTP request.
-    let request = Request::builder()
-        .uri(&path)
-        .header("Host", &host)
-        .header("Accept", "application/json")
-        .header("Accept-Encoding", "identity")
-        .header("Connection", "close")
-        .header("User-Agent", USER_AGENT)
-        .body(Empty::<Bytes>::new())?;
-
-    info!("Sending request to {}", host);
-
-    let response: hyper::Response<hyper::body::Incoming> =
-        request_sender.send_request(request).await?;
-    let status = response.status();
-
-    info!("Response status: {}", status);
-
-    if status != StatusCode::OK {
-        anyhow::bail!("server returned non-200 status: {status}");
+    if let Some(batch_path) = &args.batch {
+        let stats = run_batch(args, djinn, batch_path).await?;
+        let mut metrics = RunMetrics::new(Duration::ZERO, "batch_complete");
+        metrics.batch = Some(stats);
+        return Ok(metrics);
     }
 
-    // Finalize prover.
-    let mut prover = prover_task.await??;
+    let url = args.url.as_deref().expect("clap requires --url or --batch");
+    let output = djinn.prove(&prove_options(args, url)?).await?;
+    let summary = write_proof(args, output, "presentation.bin")?;
+    print_summary(args, &summary);
 
-    // Parse HTTP transcript.
-    let transcript = HttpTranscript::parse(prover.transcript())?;
+    let mut metrics = RunMetrics::new(Duration::ZERO, "success");
+    metrics.presentation_bytes = summary["presentation_bytes"].as_u64();
+    metrics.disclosure_ratio = summary["disclosure"]["ratio"].as_f64();
+    Ok(metrics)
+}
 
-    // Commit to transcript segments.
-    let mut builder = TranscriptCommitConfig::builder(prover.transcript());
-    DefaultHttpCommitter::default().commit_transcript(&mut builder, &transcript)?;
-    let transcript_commit = builder.build()?;
+/// Builds a presentation from a saved attestation with this run's
+/// disclosure options and writes it like a proof.
+fn rebuild(args: &Args, djinn: Djinn, path: &Path) -> Result<RunMetrics> {
+    anyhow::ensure!(
+        args.output.is_some() || args.output_dir.is_some(),
+        "rebuild needs --output or --output-dir"
+    );
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let saved = SavedAttestation::from_bytes(&bytes)
+        .with_context(|| format!("invalid attestation file {}", path.display()))?;
+    let output = djinn.rebuild(&saved, &prove_options(args, "")?)?;
+    let mut summary = write_proof(args, output, "presentation.bin")?;
+    summary["rebuilt_from"] = path.to_string_lossy().into();
+    print_summary(args, &summary);
+
+    let mut metrics = RunMetrics::new(Duration::ZERO, "success");
+    metrics.presentation_bytes = summary["presentation_bytes"].as_u64();
+    metrics.disclosure_ratio = summary["disclosure"]["ratio"].as_f64();
+    Ok(metrics)
+}
 
-    // Build attestation request config.
-    let mut builder = RequestConfig::builder();
-    builder.transcript_commit(transcript_commit);
-    let request_config = builder.build()?;
+/// Proves each URL in the batch file through a bounded [`ProverPool`]. The
+/// URLs are read up front, so a file that can't be read fails before any
+/// proof starts; the proofs themselves are started as slots free up.
+async fn run_batch(args: &Args, djinn: Djinn, batch_path: &Path) -> Result<BatchStats> {
+    let file = std::fs::File::open(batch_path)
+        .with_context(|| format!("failed to open {}", batch_path.display()))?;
+    let lines = BufReader::new(file)
+        .lines()
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("failed to read {}", batch_path.display()))?;
+    let urls = lines
+        .into_iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let template = prove_options(args, "")?;
+    let items = urls.map(|url| ProveOptions {
+        url,
+        ..template.clone()
+    });
 
-    // Build prove config.
-    let mut builder = ProveConfig::builder(prover.transcript());
-    if let Some(config) = request_config.transcript_commit() {
-        builder.transcript_commit(config.clone());
+    let mut pool = ProverPool::new(djinn, args.concurrency);
+    if let Some(rate) = args.rate {
+        pool = pool.with_rate_limit(rate);
     }
-    let disclosure_config = builder.build()?;
-
-    let ProverOutput {
-        transcript_commitments,
-        transcript_secrets,
-        ..
-    } = prover.prove(&disclosure_config).await?;
-
-    let prover_transcript = prover.transcript().clone();
-    let tls_transcript = prover.tls_transcript().clone();
-    prover.close().await?;
-
-    // Build attestation request.
-    let mut builder = AttestationRequest::builder(&request_config);
-    builder
-        .server_name(ServerName::Dns(host.try_into()?))
-        .handshake_data(HandshakeData {
-            certs: tls_transcript
-                .server_cert_chain()
-                .expect("server cert chain is present")
-                .to_vec(),
-            sig: tls_transcript
-                .server_signature()
-                .expect("server signature is present")
-                .clone(),
-            binding: tls_transcript.certificate_binding().clone(),
+    let stats = pool
+        .run_batch(items, |index, result| {
+            let summary = result
+                .and_then(|output| write_proof(args, output, &format!("presentation-{index}.bin")))
+                .unwrap_or_else(|e| failure_summary(&e));
+            let mut line = serde_json::json!({ "index": index });
+            if let (Some(line), Some(summary)) = (line.as_object_mut(), summary.as_object()) {
+                line.extend(summary.clone());
+            }
+            println!("{line}");
         })
-        .transcript(prover_transcript.clone())
-        .transcript_commitments(transcript_secrets.clone(), transcript_commitments.clone());
-
-    let (request, secrets) = builder.build(&CryptoProvider::default())?;
-
-    // Close session and reclaim socket.
-    handle.close();
-    let mut socket = driver_task.await??;
-
-    // Send attestation request to notary.
-    let request_bytes = bincode::serialize(&request)?;
-    socket.write_all(&request_bytes).await?;
-    socket.close().await?;
-
-    // Receive attestation from notary.
-    let mut attestation_bytes = Vec::new();
-    socket.read_to_end(&mut attestation_bytes).await?;
-    let attestation: Attestation = bincode::deserialize(&attestation_bytes)?;
-
-    // Validate attestation.
-    let provider = CryptoProvider::default();
-    request.validate(&attestation, &provider)?;
-
-    info!("Attestation received and validated. Building presentation...");
-
-    // Build presentation with selective disclosure.
-    let http_transcript = HttpTranscript::parse(secrets.transcript())?;
-    let mut proof_builder = secrets.transcript_proof_builder();
-
-    let req = &http_transcript.requests[0];
-    // Reveal request structure and target.
-    proof_builder.reveal_sent(&req.without_data())?;
-    proof_builder.reveal_sent(&req.request.target)?;
-
-    // Reveal headers, redacting sensitive ones.
-    for header in &req.headers {
-        let name_lower = header.name.as_str().to_lowercase();
-        if redact_set.iter().any(|r| name_lower.contains(r)) {
-            // Redact the value but reveal the header name.
-            proof_builder.reveal_sent(&header.without_value())?;
-        } else {
-            proof_builder.reveal_sent(header)?;
-        }
+        .await;
+
+    let mut summary = serde_json::to_value(&stats)?;
+    summary["status"] = "batch_complete".into();
+    println!("{summary}");
+    Ok(stats)
+}
+
+/// Returns true for `--output -`, which writes the presentation to stdout.
+fn writes_to_stdout(args: &Args) -> bool {
+    args.output.as_deref() == Some(Path::new("-"))
+}
+
+/// Prints a JSON summary: to stdout, or to stderr when stdout carries the
+/// presentation.
+fn print_summary(args: &Args, summary: &serde_json::Value) {
+    if writes_to_stdout(args) {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
     }
+}
 
-    // Reveal full response (headers + body).
-    let resp = &http_transcript.responses[0];
-    proof_builder.reveal_recv(&resp.without_data())?;
-    for header in &resp.headers {
-        proof_builder.reveal_recv(header)?;
+/// Writes the presentation file, or to stdout for `--output -`.
+fn write_output(args: &Args, path: &Path, bytes: &[u8]) -> Result<()> {
+    if writes_to_stdout(args) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(bytes)?;
+        stdout
+            .flush()
+            .context("failed to write the presentation to stdout")
+    } else {
+        write_atomic(path, bytes)
     }
-    if let Some(body) = resp.body.as_ref() {
-        proof_builder.reveal_recv(body)?;
+}
+
+/// The JSON summary for a failed proof. Structured notary rejections are
+/// included as `notary_error`.
+fn failure_summary(e: &anyhow::Error) -> serde_json::Value {
+    let mut summary = serde_json::json!({
+        "status": status::status_of(e),
+        "error": format!("{e:#}"),
+    });
+    if let Some(rejection) = e.chain().find_map(|e| e.downcast_ref::<NotaryError>()) {
+        summary["notary_error"] = serde_json::json!(rejection);
     }
+    summary
+}
 
-    let transcript_proof = proof_builder.build()?;
+fn parse_chunk_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        Ok(_) => Err("chunk size must be at least 1 byte".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
 
-    let mut pres_builder = attestation.presentation_builder(&provider);
-    pres_builder
-        .identity_proof(secrets.identity_proof())
-        .transcript_proof(transcript_proof);
+/// Parses a byte count with an optional binary `k` or `m` suffix.
+fn parse_data_size(value: &str) -> Result<usize, String> {
+    let value = value.trim().to_ascii_lowercase();
+    let (digits, unit) = match value.strip_suffix(['k', 'm']) {
+        Some(digits) if value.ends_with('k') => (digits, 1024),
+        Some(digits) => (digits, 1024 * 1024),
+        None => (value.as_str(), 1),
+    };
+    let count: usize = digits.trim().parse().map_err(|e| format!("{value}: {e}"))?;
+    count
+        .checked_mul(unit)
+        .ok_or_else(|| format!("{value} is too large"))
+}
 
-    let presentation: Presentation = pres_builder.build()?;
+fn transport(args: &Args) -> Result<Transport> {
+    let mut transport = Transport::new(&args.notary_host, args.notary_port);
+    transport.resolver = match (&args.resolver, &args.resolver_tls_name) {
+        (Some(spec), Some(tls_name)) => Some(EncryptedResolver::parse(spec, tls_name)?),
+        _ => None,
+    };
+    if args.notary_tls {
+        transport.notary_tls = Some(notary::tls_config(args.notary_ca.as_deref())?);
+    }
+    transport.proxy = args.proxy.clone();
+    transport.http_proxy = args.http_proxy.clone().map(|mut proxy| {
+        proxy.authorization = std::env::var(PROXY_AUTHORIZATION_ENV)
+            .ok()
+            .filter(|value| !value.is_empty());
+        proxy
+    });
+    transport.notary_proxy = args.notary_proxy.clone();
+    transport.record_notary = args.record_notary.clone();
+    transport.replay_notary = args.replay_notary.clone();
+    transport.connect_retries = args.connect_retries;
+    transport.connect_backoff = Duration::from_millis(args.connect_backoff_ms);
+    transport.timeout = args.timeout.map(Duration::from_secs);
+    transport.notary_handshake_timeout = Duration::from_secs(args.notary_handshake_timeout);
+    transport.mpc_setup_timeout = Duration::from_secs(args.mpc_setup_timeout);
+    transport.attestation_timeout = Duration::from_secs(args.attestation_timeout);
+    Ok(transport)
+}
 
-    // Write presentation to output file.
-    tokio::fs::write(&args.output, bincode::serialize(&presentation)?).await?;
+fn prove_options(args: &Args, url: &str) -> Result<ProveOptions> {
+    let list = |value: &str| -> Vec<String> {
+        value.split(',').map(|s| s.trim().to_lowercase()).collect()
+    };
+
+    let mut opts = ProveOptions::new(url);
+    opts.method = args.method.clone();
+    opts.body = match (&args.body, &args.body_file) {
+        (Some(body), _) => Some(Bytes::from(body.clone())),
+        (None, Some(path)) => {
+            Some(Bytes::from(std::fs::read(path).with_context(|| {
+                format!("failed to read {}", path.display())
+            })?))
+        }
+        (None, None) if !args.form.is_empty() => {
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            for field in &args.form {
+                form.append_pair(&field.name, &field.value);
+            }
+            Some(Bytes::from(form.finish()))
+        }
+        (None, None) => None,
+    };
+    opts.content_type = args.content_type.clone();
+    opts.json_key_order = args.json_key_order;
+    if !args.form.is_empty() {
+        opts.content_type = http::FORM_CONTENT_TYPE.to_string();
+        opts.form_redactions = Some(args.redact_form_field.clone());
+    }
+    #[cfg(feature = "grpc-web")]
+    if let Some(mode) = args.grpc_web {
+        // The body is the protobuf request message; frame it as a unary call.
+        let text = mode == GrpcWebMode::Text;
+        let message = opts.body.take().unwrap_or_default();
+        opts.body = Some(Bytes::from(grpc_web::encode_request(&message, text)));
+        opts.content_type = if text {
+            grpc_web::CONTENT_TYPE_TEXT
+        } else {
+            grpc_web::CONTENT_TYPE
+        }
+        .to_string();
+    }
+    opts.expect_continue = args.expect_continue;
+    opts.continue_timeout = Duration::from_millis(args.continue_timeout_ms);
+    opts.flush_after_headers = args.flush_after_headers;
+    opts.redact_headers = list(&args.redact_headers);
+    opts.reveal_header_prefixes = args.reveal_header_prefix.clone();
+    opts.redact_body_patterns = args.redact_body_regex.clone();
+    opts.redact_sent_lines = args.redact_sent_line.clone();
+    opts.redact_recv_lines = args.redact_recv_line.clone();
+    if let (Some(username), Some(path)) = (&args.digest_user, &args.digest_password_file) {
+        let password = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        opts.digest_auth = Some(DigestCredentials {
+            username: username.clone(),
+            password: password.trim_end_matches(['\r', '\n']).to_string(),
+        });
+    }
+    opts.response_meta_headers = args
+        .reveal_response_meta_only
+        .then(|| list(&args.response_meta_headers));
+    opts.accept_redirect = args.accept_redirect;
+    opts.if_none_match = args.if_none_match.clone();
+    opts.if_modified_since = args.if_modified_since.clone();
+    opts.server_name = args.server_name.clone();
+    opts.host_header = args.host.clone();
+    opts.headers = args.headers.clone();
+    if let Some(user_agent) = &args.user_agent {
+        opts.user_agent = user_agent.clone();
+    }
+    opts.user_agent_disclosure = args.user_agent_disclosure;
+    opts.spool_body = args.spool_body.clone();
+    opts.accept_compressed = args.accept_compressed;
+    opts.max_sent_data = args.max_sent_data;
+    opts.max_recv_data = args.max_recv_data;
+    opts.read_chunk_size = args.target_read_chunk_size;
+    opts.dump_transcript = args.dump_transcript.clone();
+    opts.attestation_output = args.attestation_output.clone();
+    opts.echo_request = args.echo_request.is_some();
+    opts.reveal_json_paths = args.reveal_json_path.clone();
+    opts.reveal_and_assert = args.reveal_and_assert.clone();
+    opts.reveal_only = args.reveal_only.clone();
+    if let Some(path) = &args.requests_file {
+        let json =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        opts.requests = RequestSpec::parse_list(&json)
+            .with_context(|| format!("invalid request list in {}", path.display()))?;
+    }
+    opts.warn_on_large_disclosure = args.warn_on_large_disclosure;
+    opts.strict = args.strict;
+    Ok(opts)
+}
 
-    // Output JSON summary to stdout for the Python wrapper to parse.
-    let summary = serde_json::json!({
+/// Writes a proof's presentation (and manifest sidecar, if any) and returns
+/// its JSON summary. `default_name` is the file name used in --output-dir
+/// without --content-addressed.
+fn write_proof(args: &Args, output: ProveResult, default_name: &str) -> Result<serde_json::Value> {
+    let ProveResult {
+        presentation,
+        mut metadata,
+    } = output;
+    if let Some(request) = &metadata.revealed_request {
+        match args.echo_request.as_ref().and_then(Option::as_deref) {
+            Some(path) => std::fs::write(path, request)
+                .with_context(|| format!("failed to write {}", path.display()))?,
+            None => std::io::Write::write_all(&mut std::io::stderr(), request)?,
+        }
+    }
+
+    let presentation_bytes = bincode::serialize(&presentation)?;
+    let presentation_hash = hex::encode(Sha256::digest(&presentation_bytes));
+    let extension = match args.format {
+        _ if args.bundle => "bundle",
+        OutputFormat::Json => "json",
+        OutputFormat::Bincode => "bin",
+    };
+    let output_path = match (&args.output, &args.output_dir) {
+        (Some(path), _) => path.clone(),
+        (None, Some(dir)) if args.content_addressed => {
+            dir.join(format!("{presentation_hash}.{extension}"))
+        }
+        (None, Some(dir)) => dir.join(default_name).with_extension(extension),
+        (None, None) => unreachable!("clap requires --output or --output-dir"),
+    };
+
+    // Record the assertions alongside the presentation. The manifest is
+    // unattested metadata; verifiers re-check it against the disclosed data.
+    let manifest = (!metadata.assertions.is_empty()).then(|| {
+        let mut manifest = Manifest::new(presentation_hash.clone());
+        manifest.assertions = std::mem::take(&mut metadata.assertions);
+        manifest
+    });
+
+    // Write presentation to output file, with the manifest as a sidecar. A
+    // bundle carries both and is written once the summary is known.
+    let mut manifest_path = None;
+    if !args.bundle {
+        let file_bytes = match args.format {
+            OutputFormat::Json => {
+                let mut envelope = Envelope::new(&presentation_bytes);
+                envelope.server = Some(metadata.server.clone());
+                envelope.response_status = Some(metadata.response_status);
+                envelope.to_json()?
+            }
+            OutputFormat::Bincode => presentation_bytes.clone(),
+        };
+        write_output(args, &output_path, &file_bytes)?;
+        if let Some(manifest) = &manifest {
+            let path = Manifest::path_for(&output_path);
+            write_atomic(&path, &serde_json::to_vec_pretty(manifest)?)?;
+            manifest_path = Some(path);
+        }
+    }
+
+    // Output JSON summary to stdout for the Python wrapper to parse: the
+    // run's metadata plus where it was written.
+    let mut summary = serde_json::json!({
         "status": "success",
-        "output": args.output.to_string_lossy(),
-        "server": url.host().unwrap_or_default(),
-        "response_status": status.as_u16(),
+        "output": output_path.to_string_lossy(),
+        "sha256": presentation_hash,
+        "presentation_bytes": presentation_bytes.len(),
+        "method": args.method,
+        "response_meta_only": args.reveal_response_meta_only,
     });
-    println!("{}", serde_json::to_string(&summary)?);
+    if let (Some(summary), serde_json::Value::Object(metadata)) =
+        (summary.as_object_mut(), serde_json::to_value(&metadata)?)
+    {
+        summary.extend(metadata);
+    }
+    if let Some(path) = &args.spool_body {
+        summary["spooled_body"] = path.to_string_lossy().into();
+    }
+    if let Some(path) = &manifest_path {
+        summary["manifest"] = path.to_string_lossy().into();
+    }
+    if args.format == OutputFormat::Json {
+        summary["format"] = "json".into();
+    }
+    if args.requests_file.is_some() {
+        summary["requests"] = metadata.response_statuses.len().into();
+    }
+    if !args.reveal_only.is_empty() {
+        summary["reveal_only"] = true.into();
+        summary["disclosure_warnings"] = metadata.disclosure_warnings.into();
+    }
 
-    Ok(())
+    if args.bundle {
+        summary["bundle"] = true.into();
+        let mut bundle = Bundle::new(presentation_bytes);
+        bundle.manifest = manifest;
+        bundle.metadata = Some(summary.clone());
+        write_output(args, &output_path, &bundle.to_bytes()?)?;
+    }
+    Ok(summary)
 }
@@ -3,115 +3,838 @@
 //! Verifies a TLSNotary presentation file and outputs the disclosed data.
 //!
 //! Usage:
-//!   djinn-tlsn-verifier --presentation /tmp/proof.bin
+//!   djinn-tlsn-verifier verify --presentation /tmp/proof.bin
 //!
 //! Outputs JSON to stdout with: server_name, timestamp, disclosed request/response.
+//! Several requests sent over one connection are listed in order under
+//! `exchanges`, and the flat fields describe the first of them.
+//!
+//! Built with the `server` feature, `djinn-tlsn-verifier serve --listen <addr>`
+//! runs the same verification as an HTTP service instead.
+//!
+//! `djinn-tlsn-verifier jsonpath-test <expr> <sample.json>` tries a JSONPath
+//! expression against a sample body without any presentation.
+//!
+//! `djinn-tlsn-verifier fingerprint --presentation <file>` prints the notary
+//! key a presentation claims, without verifying it.
+//!
+//! The verification options given without the `verify` subcommand still
+//! verify, with a deprecation warning on stderr.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use regex::Regex;
+use sha2::{Digest, Sha256};
 
-use tlsn::attestation::{
-    presentation::{Presentation, PresentationOutput},
-    CryptoProvider,
+#[cfg(feature = "server")]
+use djinn_tlsn_tools::server::{self, ServerConfig};
+use djinn_tlsn_tools::{
+    assertions::{ExpectJson, ExpectQuery, FieldComparison},
+    bundle::Bundle,
+    cert::TrustBundle,
+    http, inspect,
+    jsonpath::{self, JsonPath},
+    jwt::{Claim, JwtSigner},
+    manifest::Manifest,
+    output::write_atomic,
+    policy::VerificationPolicy,
+    verify::{
+        self,
+        checks::Checks,
+        error::{error_code_of, ErrorCode, StaleSkipped},
+        exchange::Exchange,
+        VerificationReport,
+    },
+    MAX_PRESENTATION_BYTES,
 };
+use tlsn::attestation::CryptoProvider;
 
 #[derive(Parser, Debug)]
-#[command(name = "djinn-tlsn-verifier", about = "Verify a TLSNotary presentation")]
+#[command(
+    name = "djinn-tlsn-verifier",
+    about = "Verify a TLSNotary presentation",
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Verification options without the `verify` subcommand, a deprecated
+    /// form kept for existing scripts. `fingerprint` and `serve` read
+    /// --max-presentation-bytes and --output from here
+    #[command(flatten)]
+    args: Args,
+}
+
+/// Options for verifying a presentation.
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Path to the serialized presentation file
+    /// Path to the serialized presentation file: bincode, base64 or a JSON
+    /// envelope (detected automatically). `-` reads it from stdin
+    #[arg(long, required_unless_present = "bundle", conflicts_with = "bundle")]
+    presentation: Option<PathBuf>,
+
+    /// After a successful verification, write the presentation alone to this
+    /// file, re-serialized without any bundle manifest or metadata, and check
+    /// that the written copy verifies to the same disclosed data
+    #[arg(long)]
+    sanitize_out: Option<PathBuf>,
+
+    /// Write the JSON result to this file atomically (temp file + rename)
+    /// instead of stdout. Where it went is reported on stderr
     #[arg(long)]
-    presentation: PathBuf,
+    output: Option<PathBuf>,
+
+    /// Path to a prover bundle (--bundle) to verify instead: its presentation
+    /// is verified and its manifest, if any, re-checked
+    #[arg(long, conflicts_with = "against_manifest")]
+    bundle: Option<PathBuf>,
 
     /// Optional Notary public key (hex-encoded secp256k1). If not provided,
     /// accepts any valid signature (dev mode).
     #[arg(long)]
     notary_pubkey: Option<String>,
+
+    /// File of hex notary public keys to accept, one per line (blank lines
+    /// and `#` comments ignored). The presentation's key must be one of them,
+    /// or --notary-pubkey if also given
+    #[arg(long)]
+    notary_allowlist: Option<PathBuf>,
+
+    /// Require the notary signing algorithm to be this one (e.g. k256, p256)
+    #[arg(long)]
+    require_alg: Option<String>,
+
+    /// Write the revealed response body to this file instead of embedding it
+    /// in the JSON output. The body is still held in memory while verifying
+    #[arg(long)]
+    body_out: Option<PathBuf>,
+
+    /// Require the attested request's User-Agent header to equal this value
+    #[arg(long)]
+    expect_user_agent: Option<String>,
+
+    /// Require the attested request's User-Agent header to match this regex
+    #[arg(long)]
+    expect_user_agent_regex: Option<Regex>,
+
+    /// Sign the verification result as a JWT with this key file (raw secret
+    /// for HS* algorithms, PEM private key otherwise)
+    #[arg(long)]
+    jwt_key: Option<PathBuf>,
+
+    /// JWT signing algorithm
+    #[arg(long, default_value = "HS256")]
+    jwt_alg: String,
+
+    /// Claims to include in the JWT besides `iat` (comma-separated)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "server_name,connection_time,disclosed_digest"
+    )]
+    jwt_claims: Vec<Claim>,
+
+    /// Enable every safety check below; each can still be turned off
+    /// individually, e.g. --require-body=false
+    #[arg(long)]
+    strict: bool,
+
+    /// Fail (instead of warn) on an unexpected notary key algorithm
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    fail_on_unknown_alg: Option<bool>,
+
+    /// Fail unless --notary-pubkey pins the notary key
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    require_notary_key: Option<bool>,
+
+    /// Fail unless the presentation discloses the server name
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    require_server_name: Option<bool>,
+
+    /// Fail unless the attested server name is this one (case-insensitive),
+    /// e.g. api.the-odds-api.com. A presentation that doesn't disclose its
+    /// server name fails too
+    #[arg(long)]
+    expect_server_name: Option<String>,
+
+    /// Fail unless every attested connection used this TLS version. The
+    /// attested version is reported as `tls_version` either way
+    #[arg(long, value_name = "VERSION")]
+    require_tls_version: Option<TlsVersionArg>,
+
+    /// Fail unless at least part of the response body is disclosed
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    require_body: Option<bool>,
+
+    /// Decode the response body as gRPC-Web frames (base64 when the
+    /// Content-Type is grpc-web-text) and report the messages and trailers
+    #[cfg(feature = "grpc-web")]
+    #[arg(long)]
+    grpc_web: bool,
+
+    /// Inflate a compressed response body (per its disclosed
+    /// Content-Encoding: gzip, deflate or br) after verification, for the
+    /// output and the body checks. Fails if the body isn't fully disclosed
+    #[arg(long)]
+    decompress: bool,
+
+    /// Fail if the disclosed response body is not valid UTF-8, instead of
+    /// silently replacing invalid bytes in the output
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    require_utf8: Option<bool>,
+
+    /// Also emit the response body parsed as JSON under `response_json`. If it
+    /// doesn't parse (e.g. because parts are redacted), `response_json` is
+    /// null and `parse_error` says why; verification still succeeds
+    #[arg(long)]
+    parse_json: bool,
+
+    /// Require the JSON value at a path in the response body to equal a value:
+    /// <jsonpath>=<json value>. Repeatable; all mismatches are reported
+    #[arg(long)]
+    expect_json: Vec<ExpectJson>,
+
+    /// Require two values in the response body to compare as given:
+    /// `<jsonpath> <op> <jsonpath>` (e.g. `$.home_odds > $.away_odds`).
+    /// Repeatable; numeric strings compare as numbers
+    #[arg(long)]
+    assert_compare: Vec<FieldComparison>,
+
+    /// Require a JSONPath to select nothing in the response body (e.g.
+    /// `$.error`; repeatable). Absence can only be shown over disclosed
+    /// bytes, so this fails unless the whole body was revealed
+    #[arg(long)]
+    assert_absent: Vec<JsonPath>,
+
+    /// Convert CRLF to LF in the request/response string fields. Only the
+    /// displayed text changes; digests are still computed over attested bytes
+    #[arg(long)]
+    normalize_eol: bool,
+
+    /// Largest presentation file accepted, in bytes. Also bounds how much
+    /// memory decoding the presentation may allocate
+    #[arg(long, default_value_t = MAX_PRESENTATION_BYTES)]
+    max_presentation_bytes: u64,
+
+    /// Fail if the attested connection time is more than this many seconds
+    /// before now, or later than now
+    #[arg(long)]
+    max_age: Option<u64>,
+
+    /// Seconds of clock difference between this machine and the notary to
+    /// tolerate in --max-age checks
+    #[arg(long, default_value_t = 0, requires = "max_age")]
+    assume_clock_skew: u64,
+
+    /// Before verifying, read the presentation's claimed connection time and
+    /// skip one already over --max-age with status `stale_skipped`, without
+    /// the signature and transcript checks. The claimed time is untrusted,
+    /// so this only ever short-circuits a rejection, never an acceptance
+    #[arg(long, requires = "max_age")]
+    only_verify_if_fresh: bool,
+
+    /// Fail if the attested connection time is before this RFC 3339 time,
+    /// e.g. 2025-01-01T00:00:00Z
+    #[arg(long)]
+    not_before: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+    /// Fail if the attested connection time is after this RFC 3339 time
+    #[arg(long)]
+    not_after: Option<chrono::DateTime<chrono::FixedOffset>>,
+
+    /// Also check the presentation against a policy file (TOML, or JSON if
+    /// named `.json`): trusted notary keys, expected server, maximum age,
+    /// required headers and JSON assertions in one place. Each rule's outcome
+    /// is reported under `policy_rules`
+    #[arg(long)]
+    policy: Option<PathBuf>,
+
+    /// Fail unless the response is a 3xx redirect whose disclosed Location
+    /// resolves to this URL (relative values are resolved against the
+    /// attested request URL)
+    #[arg(long)]
+    expect_location: Option<String>,
+
+    /// Fail unless the attested request's query has this parameter with this
+    /// value, as `<name>=<value>` compared URL-decoded (repeatable; repeat a
+    /// name for each value of a parameter sent several times). Redacted
+    /// parameters can't be checked and fail
+    #[arg(long)]
+    expect_query: Vec<ExpectQuery>,
+
+    /// Fail unless the attested server name appears among the leaf
+    /// certificate's SubjectAltNames; reports the SANs and the matching entry
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    verify_name_in_cert: Option<bool>,
+
+    /// Require the SHA-256 (hex) of the leaf certificate's SubjectPublicKeyInfo
+    /// to equal this pin. Repeatable; any one pin matching is enough
+    #[arg(long)]
+    pin_spki: Vec<String>,
+
+    /// Validate the server certificate chain against only the root CAs in
+    /// this PEM file, instead of the built-in roots (e.g. for offline use)
+    #[arg(long)]
+    trust_bundle: Option<PathBuf>,
+
+    /// Re-check the assertions recorded in a prover manifest sidecar
+    /// (--reveal-and-assert) against the disclosed response
+    #[arg(long)]
+    against_manifest: Option<PathBuf>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Verify a presentation and print the disclosed data as JSON
+    Verify(Box<Args>),
+    /// List the supported JSONPath syntax, or test an expression against a
+    /// sample JSON file and print the matched values with their byte spans
+    JsonpathTest {
+        /// JSONPath expression, as used by --expect-json or the prover's
+        /// --reveal-json-path
+        expression: Option<JsonPath>,
+        /// Sample JSON file to evaluate the expression against
+        #[arg(requires = "expression")]
+        sample: Option<PathBuf>,
+    },
+    /// Print which notary signed a presentation, without verifying it: the
+    /// hex verifying key, its algorithm and the SHA-256 fingerprint of the
+    /// key bytes
+    Fingerprint {
+        /// Path to the serialized presentation file
+        #[arg(long)]
+        presentation: PathBuf,
+    },
+    #[cfg(feature = "server")]
+    /// Serve verification over HTTP (POST /verify, GET /health, GET /metrics)
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8090")]
+        listen: std::net::SocketAddr,
+
+        /// Hex-encoded notary public key to accept (repeatable). Without any,
+        /// presentations signed by any notary are reported as verified, and a
+        /// warning is logged at startup
+        #[arg(long)]
+        trusted_key: Vec<String>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TlsVersionArg {
+    /// TLS 1.2
+    #[value(name = "1.2", alias = "TLS1.2")]
+    Tls12,
+    /// TLS 1.3
+    #[value(name = "1.3", alias = "TLS1.3")]
+    Tls13,
+}
+
+impl TlsVersionArg {
+    /// The name the version is reported under, as in `tls_version`.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Tls12 => "TLS1.2",
+            Self::Tls13 => "TLS1.3",
+        }
+    }
+}
+
+impl Args {
+    /// Resolves an individual safety check against --strict.
+    fn check(&self, flag: Option<bool>) -> bool {
+        flag.unwrap_or(self.strict)
+    }
+
+    /// The checks to run on the verified data, from the flags.
+    fn checks(&self, policy: Option<VerificationPolicy>) -> Checks {
+        let unix = |t: &chrono::DateTime<chrono::FixedOffset>| t.timestamp().max(0) as u64;
+        Checks {
+            require_notary_alg: self.require_alg.clone(),
+            reject_unknown_notary_alg: self.check(self.fail_on_unknown_alg),
+            skip_if_claimed_stale: self.only_verify_if_fresh,
+            expect_user_agent: self.expect_user_agent.clone(),
+            expect_user_agent_regex: self.expect_user_agent_regex.clone(),
+            max_age: self.max_age,
+            clock_skew: self.assume_clock_skew,
+            not_before: self.not_before.as_ref().map(unix),
+            not_after: self.not_after.as_ref().map(unix),
+            expect_query: self.expect_query.clone(),
+            expect_location: self.expect_location.clone(),
+            require_server_name: self.check(self.require_server_name),
+            expect_server_name: self.expect_server_name.clone(),
+            require_tls_version: self.require_tls_version.map(|v| v.name().to_string()),
+            require_body: self.check(self.require_body),
+            decompress: self.decompress,
+            require_utf8: self.check(self.require_utf8),
+            #[cfg(feature = "grpc-web")]
+            grpc_web: self.grpc_web,
+            expect_json: self.expect_json.clone(),
+            assert_compare: self.assert_compare.clone(),
+            assert_absent: self.assert_absent.clone(),
+            verify_name_in_cert: self.check(self.verify_name_in_cert),
+            pin_spki: self.pin_spki.clone(),
+            policy,
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let args = match cli.command {
+        Some(Command::Verify(args)) => *args,
+        Some(Command::JsonpathTest { expression, sample }) => {
+            return jsonpath_test(expression.as_ref(), sample.as_deref());
+        }
+        Some(Command::Fingerprint { presentation }) => {
+            return fingerprint(&cli.args, &presentation);
+        }
+        #[cfg(feature = "server")]
+        Some(Command::Serve {
+            listen,
+            trusted_key,
+        }) => {
+            tracing_subscriber::fmt::init();
+            let config = ServerConfig {
+                trusted_keys: trusted_key,
+                max_presentation_bytes: cli.args.max_presentation_bytes,
+            };
+            return server::serve(listen, config).await;
+        }
+        None => {
+            eprintln!(
+                "warning: verifying without a subcommand is deprecated; \
+                 use `djinn-tlsn-verifier verify`"
+            );
+            cli.args
+        }
+    };
 
-    let presentation_bytes = std::fs::read(&args.presentation)
-        .with_context(|| format!("failed to read {}", args.presentation.display()))?;
+    if args.check(args.require_notary_key)
+        && args.notary_pubkey.is_none()
+        && args.notary_allowlist.is_none()
+    {
+        fail(
+            &args,
+            ErrorCode::InvalidInput,
+            "--notary-pubkey or --notary-allowlist is required \
+             (--require-notary-key/--strict)",
+        );
+    }
 
-    let presentation: Presentation = bincode::deserialize(&presentation_bytes)
-        .context("failed to deserialize presentation")?;
+    let mut bundle = match args
+        .bundle
+        .as_deref()
+        .map(|path| Bundle::read(path, args.max_presentation_bytes))
+        .transpose()
+    {
+        Ok(bundle) => bundle,
+        Err(e) => fail(&args, ErrorCode::InvalidInput, format!("{e:#}")),
+    };
+    let bundled_manifest = bundle.as_mut().and_then(|bundle| bundle.manifest.take());
 
-    // Use default crypto provider (accepts system root CAs).
-    let crypto_provider = CryptoProvider::default();
+    let jwt_signer = args
+        .jwt_key
+        .as_deref()
+        .map(|path| JwtSigner::from_key_file(path, &args.jwt_alg))
+        .transpose()?;
 
-    let verifying_key = presentation.verifying_key();
-    let notary_key_hex = hex::encode(&verifying_key.data);
-    let alg = verifying_key.alg.clone();
+    let policy = match args
+        .policy
+        .as_deref()
+        .map(VerificationPolicy::read)
+        .transpose()
+    {
+        Ok(policy) => policy,
+        Err(e) => fail(&args, ErrorCode::InvalidInput, format!("{e:#}")),
+    };
 
-    // If a notary pubkey was specified, verify it matches.
-    if let Some(expected_key) = &args.notary_pubkey {
-        if notary_key_hex != *expected_key {
-            let output = serde_json::json!({
-                "status": "failed",
-                "error": "notary public key mismatch",
-                "expected": expected_key,
-                "actual": notary_key_hex,
-            });
-            println!("{}", serde_json::to_string(&output)?);
-            std::process::exit(1);
+    let manifest = match (&args.against_manifest, bundled_manifest) {
+        (Some(path), _) => match Manifest::read(path) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => fail(&args, ErrorCode::InvalidInput, format!("{e:#}")),
+        },
+        (None, manifest) => manifest,
+    };
+
+    // Use default crypto provider (built-in root CAs) unless a trust bundle
+    // replaces them.
+    let trust_bundle = match args
+        .trust_bundle
+        .as_deref()
+        .map(TrustBundle::load)
+        .transpose()
+    {
+        Ok(bundle) => bundle,
+        Err(e) => fail(&args, ErrorCode::InvalidInput, format!("{e:#}")),
+    };
+    let crypto_provider = match &trust_bundle {
+        Some(bundle) => bundle.crypto_provider()?,
+        None => CryptoProvider::default(),
+    };
+
+    // The keys to trust, with where each came from to report which matched.
+    let mut trusted_keys = Vec::new();
+    let mut key_sources = Vec::new();
+    if let Some(key) = &args.notary_pubkey {
+        trusted_keys.push(key.clone());
+        key_sources.push(serde_json::json!({"source": "--notary-pubkey"}));
+    }
+    if let Some(path) = &args.notary_allowlist {
+        let allowlist = match verify::read_key_allowlist(path) {
+            Ok(allowlist) => allowlist,
+            Err(e) => fail(&args, ErrorCode::InvalidInput, format!("{e:#}")),
+        };
+        for (line, key) in allowlist {
+            trusted_keys.push(key);
+            key_sources.push(serde_json::json!({
+                "source": path.to_string_lossy(),
+                "line": line,
+            }));
         }
     }
-    // Release the borrow before consuming presentation
-    let _ = verifying_key;
 
-    // Verify the presentation.
-    let result = presentation.verify(&crypto_provider);
+    // Verify the presentation and run the checks on what it discloses. A
+    // presentation file or stdin is decoded as it is read, unless
+    // --sanitize-out needs its bytes again afterwards.
+    let checks = args.checks(policy);
+    let max_bytes = args.max_presentation_bytes;
+    let (result, presentation_bytes) = if let Some(bundle) = bundle {
+        let result = verify::verify_bytes(
+            &bundle.presentation,
+            max_bytes,
+            &trusted_keys,
+            &checks,
+            &crypto_provider,
+        );
+        (result, Some(bundle.presentation))
+    } else {
+        let presentation_path = args
+            .presentation
+            .as_deref()
+            .expect("clap requires --presentation or --bundle");
+        let input: Box<dyn std::io::Read> = if presentation_path == Path::new("-") {
+            Box::new(std::io::stdin().lock())
+        } else {
+            match std::fs::File::open(presentation_path) {
+                Ok(file) => Box::new(file),
+                Err(e) => fail(
+                    &args,
+                    ErrorCode::InvalidInput,
+                    format!("failed to read {}: {e}", presentation_path.display()),
+                ),
+            }
+        };
+        if args.sanitize_out.is_some() {
+            let bytes = match verify::read_presentation(input, max_bytes) {
+                Ok(bytes) => bytes,
+                Err(e) => fail(&args, error_code_of(&e), format!("{e:#}")),
+            };
+            let result =
+                verify::verify_bytes(&bytes, max_bytes, &trusted_keys, &checks, &crypto_provider);
+            (result, Some(bytes))
+        } else {
+            let result =
+                verify::verify_stream(input, max_bytes, &trusted_keys, &checks, &crypto_provider);
+            (result, None)
+        }
+    };
     let output = match result {
-        Ok(PresentationOutput {
-            server_name,
-            connection_info,
-            transcript,
-            ..
-        }) => {
-            let time =
-                chrono::DateTime::UNIX_EPOCH + Duration::from_secs(connection_info.time);
-            let server_name = server_name
-                .map(|s| s.to_string())
-                .unwrap_or_default();
-
-            let mut partial_transcript = transcript.unwrap();
-            partial_transcript.set_unauthed(b'X');
-
-            let sent = String::from_utf8_lossy(partial_transcript.sent_unsafe()).to_string();
-            let recv = String::from_utf8_lossy(partial_transcript.received_unsafe()).to_string();
-
-            // Extract just the response body (after \r\n\r\n in received data).
-            let body = recv
-                .split("\r\n\r\n")
-                .nth(1)
-                .unwrap_or("")
-                .to_string();
-
-            serde_json::json!({
+        Ok(report) => {
+            let VerificationReport {
+                notary_key_alg,
+                notary_key,
+                notary_key_fingerprint,
+                notary_key_match,
+                notary_key_alg_warning,
+                presentation_sha256,
+                data,
+                checks: mut outcome,
+            } = report;
+            if let Some(warning) = &notary_key_alg_warning {
+                eprintln!("warning: {warning}");
+            }
+            let time = chrono::DateTime::UNIX_EPOCH + Duration::from_secs(data.time);
+            let display = |bytes: &[u8]| {
+                let text = String::from_utf8_lossy(bytes);
+                if args.normalize_eol {
+                    text.replace("\r\n", "\n")
+                } else {
+                    text.into_owned()
+                }
+            };
+
+            // A connection may carry several requests sent one after the
+            // other. The fields below and the checks describe the first
+            // exchange; all of them are listed under `exchanges`.
+            let exchange = Exchange::first(&data);
+            let no_body = exchange.no_body();
+            let body = outcome
+                .decompressed_body
+                .as_deref()
+                .unwrap_or(exchange.body_bytes());
+
+            // Attested body sizes, known from the transcript even when the
+            // bodies themselves are redacted, and how much of each is shown.
+            let (request_end, response_end) = (exchange.request_end, exchange.response_end);
+            let request_body_bytes = http::body_len(exchange.request());
+            let response_body_bytes = if no_body.is_some() {
+                Some(0)
+            } else {
+                http::body_len(exchange.response())
+            };
+            let request_body_revealed_bytes = request_body_bytes
+                .map(|len| data.sent_revealed_len(&(request_end - len..request_end)));
+            let response_body_revealed_bytes = response_body_bytes
+                .map(|len| data.received_revealed_len(&(response_end - len..response_end)));
+
+            let mut output = serde_json::json!({
                 "status": "verified",
-                "server_name": server_name,
-                "notary_key_alg": alg.to_string(),
-                "notary_key": notary_key_hex,
+                "server_name": data.server_name.clone().unwrap_or_default(),
+                "notary_key_alg": notary_key_alg,
+                "notary_key_alg_warning": notary_key_alg_warning,
+                "notary_key": notary_key,
+                "notary_key_fingerprint": notary_key_fingerprint,
+                "notary_key_match": notary_key_match.map(|i| key_sources[i].clone()),
                 "connection_time": time.to_rfc3339(),
-                "request": sent,
-                "response_body": body,
-                "response_full": recv,
-            })
-        }
-        Err(e) => {
-            serde_json::json!({
-                "status": "failed",
-                "error": e.to_string(),
-            })
+                "tls_version": data.tls_version,
+                "disclosed_digest": hex::encode(verify::disclosed_digest(&data)),
+                "request": display(exchange.request()),
+                "method": exchange.method,
+                "user_agent": exchange.user_agent(),
+                "response_status": exchange.status,
+                "content_type": exchange.content_type(),
+                "location": exchange.location(),
+                "location_resolved": exchange.location_resolved(),
+                "request_body_bytes": request_body_bytes,
+                "request_body_revealed_bytes": request_body_revealed_bytes,
+                "response_body_bytes": response_body_bytes,
+                "response_body_revealed_bytes": response_body_revealed_bytes,
+                "if_none_match": exchange.sent_header("if-none-match"),
+                "if_modified_since": exchange.sent_header("if-modified-since"),
+                "etag": exchange.received_header("etag"),
+                "last_modified": exchange.received_header("last-modified"),
+                "cache_control": exchange.received_header("cache-control"),
+                "request_headers": headers_json(http::grouped_headers(
+                    &exchange.request_head,
+                    |range| exchange.is_request_head_revealed(range),
+                )),
+                "response_headers": headers_json(http::grouped_headers(
+                    &exchange.response_head,
+                    |range| exchange.is_response_head_revealed(range),
+                )),
+            });
+
+            let requests = http::message_ranges(&data.sent);
+            if requests.len() > 1 {
+                let responses = http::message_ranges(&data.received);
+                output["exchanges"] = requests
+                    .iter()
+                    .enumerate()
+                    .map(|(i, request)| {
+                        let request = &data.sent[request.clone()];
+                        let response = responses.get(i).map(|r| &data.received[r.clone()]);
+                        let request_head = String::from_utf8_lossy(request);
+                        let (request_head, _) = http::split_message(&request_head);
+                        let response_status = response.and_then(|response| {
+                            let (start, end) = http::final_response_bounds(response)?;
+                            http::status_code(&String::from_utf8_lossy(&response[start..end]))
+                        });
+                        serde_json::json!({
+                            "method": http::request_method(request_head),
+                            "target": http::request_target(request_head),
+                            "response_status": response_status,
+                            "request_body_bytes": http::body_len(request),
+                            "response_body_bytes": response.and_then(http::body_len),
+                            "request": display(request),
+                            "response": response.map(&display),
+                        })
+                    })
+                    .collect();
+            }
+
+            // A disclosed form body, structured like the headers.
+            let is_form =
+                http::header_value(&exchange.request_head, "content-type").is_some_and(|ct| {
+                    ct.trim()
+                        .to_ascii_lowercase()
+                        .starts_with(http::FORM_CONTENT_TYPE)
+                });
+            if let (true, Some(form)) = (is_form, exchange.request_body()) {
+                let fields = http::grouped_form_fields(&data.sent[form.clone()], |range| {
+                    data.is_sent_revealed(&(form.start + range.start..form.start + range.end))
+                });
+                let fields = fields
+                    .iter()
+                    .map(|(name, values)| {
+                        let values = values.iter().map(Option::as_deref).collect();
+                        (name.clone(), values)
+                    })
+                    .collect();
+                output["request_form"] = headers_json(fields);
+            }
+
+            if let Some(encoding) = exchange.content_encoding() {
+                output["content_encoding"] = encoding.into();
+                output["decompressed"] = outcome.decompressed_body.is_some().into();
+            }
+
+            if let Some(bundle) = &trust_bundle {
+                output["trust_roots"] = serde_json::to_value(&bundle.roots)?;
+            }
+
+            if let Some(signer) = &jwt_signer {
+                output["jwt"] = signer.sign(&data, &args.jwt_claims)?.into();
+            }
+
+            if no_body.is_some() {
+                output["response_body"] = serde_json::Value::Null;
+                output["response_full"] = display(exchange.response()).into();
+            } else if let Some(body_out) = &args.body_out {
+                // Stream the revealed body to disk instead of embedding it
+                // (and the full response) as strings in the JSON output.
+                tokio::fs::write(body_out, body)
+                    .await
+                    .with_context(|| format!("failed to write {}", body_out.display()))?;
+                output["response_body_file"] = body_out.to_string_lossy().into();
+                output["response_body_file_bytes"] = body.len().into();
+            } else {
+                output["response_body"] = display(body).into();
+                output["response_full"] = display(exchange.response()).into();
+            }
+
+            if args.parse_json {
+                let parsed = if let Some(no_body) = &no_body {
+                    Err(format!("{no_body} has no body"))
+                } else {
+                    serde_json::from_slice::<serde_json::Value>(body).map_err(|e| {
+                        if exchange.is_body_revealed() {
+                            format!("response body is not valid JSON: {e}")
+                        } else {
+                            format!("response body is partly redacted and does not parse: {e}")
+                        }
+                    })
+                };
+                match parsed {
+                    Ok(json) => output["response_json"] = json,
+                    Err(error) => {
+                        output["response_json"] = serde_json::Value::Null;
+                        output["parse_error"] = error.into();
+                    }
+                }
+            }
+
+            #[cfg(feature = "grpc-web")]
+            if let Some(response) = &outcome.grpc_web {
+                let messages: Vec<String> = response.messages.iter().map(hex::encode).collect();
+                output["grpc_web"] = serde_json::json!({
+                    "messages": messages,
+                    "grpc_status": response.status(),
+                    "grpc_message": response.trailer("grpc-message"),
+                    "trailers": response
+                        .trailers
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone().into()))
+                        .collect::<serde_json::Map<_, _>>(),
+                });
+            }
+            if let Some(cert) = &outcome.cert {
+                if args.check(args.verify_name_in_cert) {
+                    output["cert_sans"] = cert.sans.clone().into();
+                    output["cert_san_match"] = cert.san_match.clone().into();
+                }
+                if !args.pin_spki.is_empty() {
+                    output["cert_spki_sha256"] = cert.spki_sha256.clone().into();
+                }
+            }
+            if let Some(rules) = &outcome.policy_rules {
+                output["policy_rules"] = serde_json::to_value(rules)?;
+            }
+
+            if let Some(manifest) = &manifest {
+                let failures = manifest.check(&presentation_sha256, &data, body);
+                outcome.failures.extend(failures);
+            }
+
+            if let (true, Some(path), Some(bytes)) =
+                (outcome.passed(), &args.sanitize_out, &presentation_bytes)
+            {
+                match verify::write_sanitized(
+                    path,
+                    bytes,
+                    args.max_presentation_bytes,
+                    &data,
+                    &crypto_provider,
+                ) {
+                    Ok(written) => {
+                        output["sanitized"] = serde_json::json!({
+                            "path": path.to_string_lossy(),
+                            "sha256": hex::encode(Sha256::digest(&written)),
+                            "bytes": written.len(),
+                        })
+                    }
+                    Err(e) => outcome
+                        .failures
+                        .push(format!("cannot write sanitized presentation: {e:#}")),
+                }
+            }
+
+            match outcome.error_code() {
+                None => output,
+                Some(error_code) => {
+                    // The presentation verified but a check rejected it.
+                    // Report the data under `rejected` so operators can see
+                    // what failed, and drop anything that would vouch for it.
+                    if let Some(fields) = output.as_object_mut() {
+                        fields.remove("status");
+                        fields.remove("jwt");
+                    }
+                    let mut result = serde_json::json!({
+                        "status": "failed",
+                        "error_code": error_code,
+                        "error": outcome.failures.join("; "),
+                        "policy_failures": outcome.failures,
+                        "user_agent": exchange.user_agent(),
+                        "rejected": output,
+                    });
+                    if let Some(window) = outcome.time_out_of_range {
+                        let rfc3339 = |secs: u64| {
+                            (chrono::DateTime::UNIX_EPOCH + Duration::from_secs(secs)).to_rfc3339()
+                        };
+                        result["attested_time"] = rfc3339(data.time).into();
+                        result["allowed_window"] = serde_json::json!({
+                            "not_before": window.not_before.map(rfc3339),
+                            "not_after": window.not_after.map(rfc3339),
+                        });
+                    }
+                    result
+                }
+            }
         }
+        Err(e) => match e.downcast_ref::<StaleSkipped>() {
+            Some(skipped) => {
+                let claimed =
+                    chrono::DateTime::UNIX_EPOCH + Duration::from_secs(skipped.claimed_time);
+                fail_with(
+                    &args,
+                    error_code_of(&e),
+                    format!("{e:#}"),
+                    serde_json::json!({
+                        "status": "stale_skipped",
+                        "claimed_connection_time": claimed.to_rfc3339(),
+                    }),
+                )
+            }
+            None => fail(&args, error_code_of(&e), e),
+        },
     };
 
-    println!("{}", serde_json::to_string_pretty(&output)?);
+    emit(&args, &serde_json::to_string_pretty(&output)?)?;
 
     if output["status"] == "failed" {
         std::process::exit(1);
@@ -119,3 +842,108 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Reports the notary key of a presentation without verifying it. The key
+/// is only what the presentation claims; nothing here proves the notary
+/// signed anything.
+fn fingerprint(args: &Args, path: &std::path::Path) -> Result<()> {
+    let result = std::fs::File::open(path)
+        .with_context(|| format!("failed to read {}", path.display()))
+        .and_then(|file| verify::read_presentation(file, args.max_presentation_bytes))
+        .and_then(|bytes| inspect::deserialize_presentation(&bytes, args.max_presentation_bytes));
+    let presentation = match result {
+        Ok(presentation) => presentation,
+        Err(e) => fail(args, ErrorCode::MalformedPresentation, format!("{e:#}")),
+    };
+    let key = presentation.verifying_key();
+    let output = serde_json::json!({
+        "notary_key": hex::encode(&key.data),
+        "notary_key_alg": key.alg.to_string(),
+        "fingerprint": verify::key_fingerprint(&key.data),
+        "verified": false,
+    });
+    emit(args, &serde_json::to_string_pretty(&output)?)
+}
+
+/// Reports a failure with its error code and exits with status 1.
+fn fail(args: &Args, code: ErrorCode, message: impl std::fmt::Display) -> ! {
+    fail_with(args, code, message, serde_json::json!({}))
+}
+
+/// Like [`fail`], with `fields` added to (or replacing those of) the result.
+fn fail_with(
+    args: &Args,
+    code: ErrorCode,
+    message: impl std::fmt::Display,
+    fields: serde_json::Value,
+) -> ! {
+    let mut output = serde_json::json!({
+        "status": "failed",
+        "error_code": code,
+        "error": message.to_string(),
+    });
+    if let (Some(output), serde_json::Value::Object(fields)) = (output.as_object_mut(), fields) {
+        output.extend(fields);
+    }
+    if let Err(e) = emit(args, &output.to_string()) {
+        eprintln!("error: {e:#}");
+    }
+    std::process::exit(1)
+}
+
+/// Prints the result to stdout, or with --output writes it to that file
+/// atomically and reports where on stderr, keeping stdout free for logs.
+fn emit(args: &Args, json: &str) -> Result<()> {
+    match &args.output {
+        Some(path) => {
+            write_atomic(path, format!("{json}\n").as_bytes())?;
+            eprintln!("wrote verification result to {}", path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Prints the supported JSONPath syntax, or the values `expression` selects
+/// in `sample` and the byte span of each.
+fn jsonpath_test(expression: Option<&JsonPath>, sample: Option<&std::path::Path>) -> Result<()> {
+    let Some(expression) = expression else {
+        let supported = jsonpath::FEATURES
+            .iter()
+            .map(|(syntax, meaning)| serde_json::json!({ "syntax": syntax, "selects": meaning }))
+            .collect::<Vec<_>>();
+        let output = serde_json::json!({
+            "supported": supported,
+            "unsupported": jsonpath::UNSUPPORTED,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    };
+    let sample = sample.context("a sample JSON file is required to test an expression")?;
+    let source =
+        std::fs::read(sample).with_context(|| format!("failed to read {}", sample.display()))?;
+    let matches = expression.match_spans(&source)?;
+    let output = serde_json::json!({
+        "expression": expression.to_string(),
+        "matches": matches,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// Renders grouped headers (or form fields) as a JSON object. A header sent
+/// once maps to its value, a repeated header to an array of values;
+/// undisclosed values are `null`.
+fn headers_json(headers: Vec<(String, Vec<Option<&str>>)>) -> serde_json::Value {
+    let map = headers
+        .into_iter()
+        .map(|(name, values)| {
+            let value = match values.as_slice() {
+                [value] => serde_json::json!(value),
+                values => serde_json::json!(values),
+            };
+            (name, value)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    map.into()
+}
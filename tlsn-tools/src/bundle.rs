@@ -0,0 +1,169 @@
+//! Single-file proof bundles.
+//!
+//! A bundle carries a presentation together with its manifest sidecar and
+//! the prover's summary, so a proof can be handed over as one artifact. The
+//! format is a versioned header followed by named entries:
+//!
+//! ```text
+//! magic    8 bytes   "DJNBNDL\0"
+//! version  u32 LE    BUNDLE_VERSION
+//! count    u32 LE    number of entries
+//! entries  count ×   name_len u16 LE, name (UTF-8), data_len u64 LE, data
+//! ```
+//!
+//! Entries are `presentation.bin` (required, bincode presentation),
+//! `manifest.json` (optional, see [`crate::manifest`]) and `metadata.json`
+//! (optional, the prover's JSON summary). Readers skip entries they don't
+//! know. Only the presentation is attested; the other entries are claims the
+//! verifier re-checks or ignores.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::manifest::Manifest;
+
+/// Identifies a bundle file.
+pub const BUNDLE_MAGIC: &[u8; 8] = b"DJNBNDL\0";
+
+/// Current bundle format version.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Upper bound on each entry other than the presentation.
+pub const MAX_EXTRA_ENTRY_BYTES: u64 = 1024 * 1024;
+
+const PRESENTATION_ENTRY: &str = "presentation.bin";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const METADATA_ENTRY: &str = "metadata.json";
+
+/// The contents of a bundle.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// Serialized presentation.
+    pub presentation: Vec<u8>,
+    pub manifest: Option<Manifest>,
+    /// Unattested metadata, normally the prover's summary.
+    pub metadata: Option<Value>,
+}
+
+impl Bundle {
+    pub fn new(presentation: Vec<u8>) -> Self {
+        Self {
+            presentation,
+            manifest: None,
+            metadata: None,
+        }
+    }
+
+    /// Encodes the bundle.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut entries = vec![(PRESENTATION_ENTRY, self.presentation.clone())];
+        if let Some(manifest) = &self.manifest {
+            entries.push((MANIFEST_ENTRY, serde_json::to_vec_pretty(manifest)?));
+        }
+        if let Some(metadata) = &self.metadata {
+            entries.push((METADATA_ENTRY, serde_json::to_vec_pretty(metadata)?));
+        }
+
+        let mut out = BUNDLE_MAGIC.to_vec();
+        out.extend(BUNDLE_VERSION.to_le_bytes());
+        out.extend((entries.len() as u32).to_le_bytes());
+        for (name, data) in entries {
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(name.as_bytes());
+            out.extend((data.len() as u64).to_le_bytes());
+            out.extend(data);
+        }
+        Ok(out)
+    }
+
+    /// Decodes a bundle, rejecting a presentation over `max_presentation_bytes`
+    /// and any other entry over [`MAX_EXTRA_ENTRY_BYTES`].
+    pub fn from_bytes(bytes: &[u8], max_presentation_bytes: u64) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(BUNDLE_MAGIC.len())? != BUNDLE_MAGIC {
+            anyhow::bail!("not a bundle: bad magic");
+        }
+        let version = u32::from_le_bytes(reader.array()?);
+        if version != BUNDLE_VERSION {
+            anyhow::bail!("unsupported bundle version {version}");
+        }
+        let count = u32::from_le_bytes(reader.array()?);
+
+        let mut presentation = None;
+        let mut manifest = None;
+        let mut metadata = None;
+        for _ in 0..count {
+            let name_len = u16::from_le_bytes(reader.array()?);
+            let name = std::str::from_utf8(reader.take(name_len.into())?)
+                .context("bundle entry name is not UTF-8")?;
+            let data_len = u64::from_le_bytes(reader.array()?);
+            let limit = if name == PRESENTATION_ENTRY {
+                max_presentation_bytes
+            } else {
+                MAX_EXTRA_ENTRY_BYTES
+            };
+            if data_len > limit {
+                anyhow::bail!(
+                    "bundle entry {name} is {data_len} bytes, over the {limit} byte limit"
+                );
+            }
+            let data = reader.take(data_len as usize)?;
+            match name {
+                PRESENTATION_ENTRY => presentation = Some(data.to_vec()),
+                MANIFEST_ENTRY => {
+                    manifest = Some(Manifest::from_slice(data).context("invalid bundled manifest")?)
+                }
+                METADATA_ENTRY => {
+                    metadata =
+                        Some(serde_json::from_slice(data).context("invalid bundled metadata")?)
+                }
+                _ => {}
+            }
+        }
+        if !reader.0.is_empty() {
+            anyhow::bail!("trailing bytes after the last bundle entry");
+        }
+
+        Ok(Self {
+            presentation: presentation.context("bundle has no presentation")?,
+            manifest,
+            metadata,
+        })
+    }
+
+    /// Reads and decodes a bundle file, checking its size before reading it.
+    pub fn read(path: &Path, max_presentation_bytes: u64) -> Result<Self> {
+        // Generous bound: the presentation plus a couple of small entries.
+        let max_len = max_presentation_bytes + 4 * MAX_EXTRA_ENTRY_BYTES;
+        let len = std::fs::metadata(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .len();
+        if len > max_len {
+            anyhow::bail!("bundle is {len} bytes, over the {max_len} byte limit");
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::from_bytes(&bytes, max_presentation_bytes)
+            .with_context(|| format!("invalid bundle {}", path.display()))
+    }
+}
+
+/// Cursor over the encoded bundle.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.0.len() {
+            anyhow::bail!("bundle is truncated");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+}
@@ -0,0 +1,317 @@
+//! Server certificate checks beyond what `Presentation::verify` does.
+//!
+//! Verification already checks the certificate chain against the server name
+//! through the crypto provider. These helpers let the verifier confirm that
+//! independently and apply its own pins: read the leaf certificate out of the
+//! identity proof, match the attested name against its SubjectAltNames and
+//! hash its public key.
+//!
+//! A [`TrustBundle`] instead changes what verification itself trusts: it
+//! replaces the built-in root CAs with a fixed set loaded from PEM.
+
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tlsn::{
+    attestation::{presentation::Presentation, CryptoProvider},
+    webpki::{CertificateDer, RootCertStore, ServerCertVerifier},
+};
+use x509_parser::{
+    certificate::X509Certificate, extensions::GeneralName, pem::Pem, prelude::FromDer,
+};
+
+/// What the verifier checks about the leaf certificate.
+#[derive(Debug, Clone)]
+pub struct LeafCert {
+    /// DNS names and IP addresses from the SubjectAltName extension.
+    pub sans: Vec<String>,
+    /// SHA-256 of the DER-encoded SubjectPublicKeyInfo.
+    pub spki_sha256: [u8; 32],
+}
+
+impl LeafCert {
+    /// Reads and parses the leaf certificate of `presentation`.
+    pub fn from_presentation(presentation: &Presentation) -> Result<Self> {
+        let der = leaf_certificate(presentation)?;
+        Ok(Self {
+            sans: subject_alt_names(&der)?,
+            spki_sha256: spki_sha256(&der)?,
+        })
+    }
+}
+
+/// Returns the DER-encoded leaf certificate from the presentation's server
+/// identity proof.
+///
+/// Presentation fields are private, so the handshake data is read through the
+/// presentation's serde representation, as in [`crate::inspect`].
+pub fn leaf_certificate(presentation: &Presentation) -> Result<Vec<u8>> {
+    let value = serde_json::to_value(presentation).context("presentation is not serializable")?;
+    let identity = value
+        .get("identity")
+        .filter(|v| !v.is_null())
+        .context("presentation has no server identity proof")?;
+    let leaf = find_key(identity, "certs")
+        .and_then(Value::as_array)
+        .and_then(|certs| certs.first())
+        .context("server identity proof has no certificates")?;
+    der_bytes(leaf).context("leaf certificate is not a byte array")
+}
+
+/// Depth-first search for the first member named `key`.
+fn find_key<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .get(key)
+            .or_else(|| map.values().find_map(|v| find_key(v, key))),
+        Value::Array(items) => items.iter().find_map(|v| find_key(v, key)),
+        _ => None,
+    }
+}
+
+/// Reads a certificate serialized as a byte sequence, possibly wrapped in a
+/// single-field newtype.
+fn der_bytes(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+            .collect(),
+        Value::Object(map) if map.len() == 1 => map.values().next().and_then(der_bytes),
+        _ => None,
+    }
+}
+
+/// Lists the DNS names and IP addresses in a certificate's SubjectAltName
+/// extension. Returns an empty list if the certificate has none.
+pub fn subject_alt_names(der: &[u8]) -> Result<Vec<String>> {
+    let (_, cert) = X509Certificate::from_der(der)
+        .map_err(|e| anyhow::anyhow!("failed to parse leaf certificate: {e}"))?;
+    let Some(san) = cert
+        .subject_alternative_name()
+        .map_err(|e| anyhow::anyhow!("invalid SubjectAltName extension: {e}"))?
+    else {
+        return Ok(Vec::new());
+    };
+
+    Ok(san
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns) => Some(dns.to_string()),
+            GeneralName::IPAddress(bytes) => ip_from_bytes(bytes).map(|ip| ip.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Hashes a certificate's SubjectPublicKeyInfo, as used for HPKP-style pins.
+/// The hash stays the same across renewals that reuse the key.
+pub fn spki_sha256(der: &[u8]) -> Result<[u8; 32]> {
+    let (_, cert) = X509Certificate::from_der(der)
+        .map_err(|e| anyhow::anyhow!("failed to parse leaf certificate: {e}"))?;
+    Ok(Sha256::digest(cert.public_key().raw).into())
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+/// Returns the SAN entry that covers `server_name`, if any.
+///
+/// Names compare case-insensitively, ignoring a trailing dot. Wildcards
+/// follow RFC 6125 section 6.4.3: `*` must be the whole left-most label of
+/// the SAN and covers exactly one label of the name, so `*.example.com`
+/// matches `api.example.com` but not `example.com` or `a.b.example.com`.
+/// Partial wildcards (`f*.example.com`), wildcards over a single-label suffix
+/// (`*.com`) and wildcard matches for IP addresses are rejected. Names compare
+/// case-insensitively, and an internationalized `server_name` is compared in
+/// its ASCII (`xn--`) form.
+pub fn matching_san<'a>(server_name: &str, sans: &'a [String]) -> Option<&'a str> {
+    let name = server_name.trim_end_matches('.');
+    // Certificates carry internationalized names in their ASCII form.
+    let name = match url::Host::parse(name) {
+        Ok(url::Host::Domain(ascii)) => ascii,
+        _ => name.to_ascii_lowercase(),
+    };
+    if name.is_empty() || name.contains('*') {
+        return None;
+    }
+    let is_ip = name.parse::<IpAddr>().is_ok();
+    sans.iter()
+        .find(|san| {
+            let san = san.trim_end_matches('.').to_ascii_lowercase();
+            match san.strip_prefix("*.") {
+                Some(suffix) => {
+                    !is_ip
+                        && suffix.contains('.')
+                        && !suffix.contains('*')
+                        && name
+                            .split_once('.')
+                            .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix)
+                }
+                None => !san.contains('*') && san == name,
+            }
+        })
+        .map(String::as_str)
+}
+
+/// Normalizes a TLS server name for SNI: lowercased, without a trailing dot.
+///
+/// SNI must carry the full host name even when the server's certificate is a
+/// wildcard, so a name containing `*` is rejected, as is an IP address,
+/// which SNI can't carry.
+pub fn sni_name(server_name: &str) -> Result<String> {
+    let name = server_name.trim_end_matches('.').to_ascii_lowercase();
+    anyhow::ensure!(!name.is_empty(), "server name is empty");
+    anyhow::ensure!(
+        !name.contains('*'),
+        "server name {server_name:?} is a wildcard; use the full host name the \
+         certificate's wildcard covers"
+    );
+    anyhow::ensure!(
+        name.trim_matches(['[', ']']).parse::<IpAddr>().is_err(),
+        "server name {server_name:?} is an IP address; TLS needs a DNS name"
+    );
+    Ok(name)
+}
+
+/// A root CA from a [`TrustBundle`], as reported by the verifier.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustRoot {
+    pub subject: String,
+    /// Hex SHA-256 of the DER certificate.
+    pub sha256: String,
+    #[serde(skip)]
+    der: Vec<u8>,
+}
+
+/// A fixed set of root CAs that replaces the built-in roots when checking
+/// the attested server certificate chain, for offline or pinned
+/// verification.
+#[derive(Debug, Clone)]
+pub struct TrustBundle {
+    pub roots: Vec<TrustRoot>,
+}
+
+impl TrustBundle {
+    /// Loads every `CERTIFICATE` block from a PEM file. Fails if the file
+    /// holds no certificates or any of them doesn't parse.
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut roots = Vec::new();
+        for block in Pem::iter_from_buffer(&pem) {
+            let block =
+                block.map_err(|e| anyhow::anyhow!("invalid PEM in {}: {e}", path.display()))?;
+            if block.label != "CERTIFICATE" {
+                continue;
+            }
+            let (_, cert) = X509Certificate::from_der(&block.contents)
+                .map_err(|e| anyhow::anyhow!("invalid certificate in {}: {e}", path.display()))?;
+            roots.push(TrustRoot {
+                subject: cert.subject().to_string(),
+                sha256: hex::encode(Sha256::digest(&block.contents)),
+                der: block.contents.clone(),
+            });
+        }
+        if roots.is_empty() {
+            anyhow::bail!("{} contains no certificates", path.display());
+        }
+        Ok(Self { roots })
+    }
+
+    /// A crypto provider that trusts exactly these roots.
+    pub fn crypto_provider(&self) -> Result<CryptoProvider> {
+        let store = RootCertStore {
+            roots: self
+                .roots
+                .iter()
+                .map(|root| CertificateDer(root.der.clone()))
+                .collect(),
+        };
+        Ok(CryptoProvider {
+            cert: ServerCertVerifier::new(&store)
+                .map_err(|e| anyhow::anyhow!("failed to load trust bundle: {e}"))?,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn wildcard_covers_exactly_one_leftmost_label() {
+        let wildcard = sans(&["*.example.com"]);
+        assert_eq!(
+            matching_san("a.example.com", &wildcard),
+            Some("*.example.com")
+        );
+        assert_eq!(matching_san("a.b.example.com", &wildcard), None);
+        assert_eq!(matching_san("example.com", &wildcard), None);
+        assert_eq!(matching_san(".example.com", &wildcard), None);
+    }
+
+    #[test]
+    fn wildcard_must_be_the_whole_leftmost_label() {
+        for san in [
+            "a.*.example.com",
+            "api.example.*",
+            "f*.example.com",
+            "*a.example.com",
+            "*.com",
+            "*",
+        ] {
+            assert_eq!(matching_san("a.example.com", &sans(&[san])), None, "{san}");
+        }
+        assert_eq!(matching_san("a.example.com", &sans(&["*.*.com"])), None);
+    }
+
+    #[test]
+    fn names_compare_case_insensitively() {
+        let san = sans(&["*.Example.COM"]);
+        assert_eq!(matching_san("API.example.com", &san), Some("*.Example.COM"));
+        assert_eq!(
+            matching_san("Example.Com.", &sans(&["example.com"])),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn internationalized_names_compare_in_ascii_form() {
+        let san = sans(&["*.xn--bcher-kva.example"]);
+        assert_eq!(
+            matching_san("shop.bücher.example", &san),
+            Some("*.xn--bcher-kva.example")
+        );
+        assert_eq!(
+            matching_san("SHOP.XN--BCHER-KVA.EXAMPLE", &san),
+            Some("*.xn--bcher-kva.example")
+        );
+        assert_eq!(matching_san("shop.bucher.example", &san), None);
+    }
+
+    #[test]
+    fn ip_addresses_never_match_wildcards() {
+        assert_eq!(matching_san("10.0.0.1", &sans(&["*.0.0.1"])), None);
+        assert_eq!(
+            matching_san("10.0.0.1", &sans(&["10.0.0.1"])),
+            Some("10.0.0.1")
+        );
+    }
+}
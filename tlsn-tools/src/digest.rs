@@ -0,0 +1,275 @@
+//! HTTP Digest authentication (RFC 7616) for the prover.
+//!
+//! Digest auth takes two round trips: an unauthenticated request that the
+//! server answers with `401` and a `WWW-Authenticate: Digest` challenge, then
+//! the real request carrying an `Authorization` header computed from the
+//! challenge. The prover makes the first request over a plain TLS connection
+//! of its own, outside the notarized session, and only the authenticated
+//! request is attested. The challenge itself is therefore not part of the
+//! presentation.
+//!
+//! Supported: `qop=auth` or no `qop`, with the `MD5`, `MD5-sess`, `SHA-256`
+//! and `SHA-256-sess` algorithms. `auth-int` and userhash are not.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use http_body_util::Empty;
+use hyper::{body::Bytes, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use rand::RngCore as _;
+use sha2::{Digest as _, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{
+    rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+
+/// Username and password for Digest auth. `Debug` hides the password.
+#[derive(Clone)]
+pub struct DigestCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for DigestCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DigestCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Hash function named by a challenge's `algorithm` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestAlgorithm {
+    fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "MD5" => Ok(Self::Md5),
+            "MD5-SESS" => Ok(Self::Md5Sess),
+            "SHA-256" => Ok(Self::Sha256),
+            "SHA-256-SESS" => Ok(Self::Sha256Sess),
+            _ => anyhow::bail!("unsupported Digest algorithm: {name}"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+            Self::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn hash(self, data: &str) -> String {
+        match self {
+            Self::Md5 | Self::Md5Sess => hex::encode(md5::Md5::digest(data)),
+            Self::Sha256 | Self::Sha256Sess => hex::encode(Sha256::digest(data)),
+        }
+    }
+
+    fn is_session(self) -> bool {
+        matches!(self, Self::Md5Sess | Self::Sha256Sess)
+    }
+}
+
+/// A parsed `WWW-Authenticate: Digest` challenge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    pub algorithm: DigestAlgorithm,
+    /// Whether the server offered `qop=auth`. Without it the legacy RFC 2069
+    /// response is computed.
+    pub qop_auth: bool,
+}
+
+impl DigestChallenge {
+    /// Parses a `WWW-Authenticate` header value. Returns `None` if it isn't
+    /// a Digest challenge.
+    pub fn parse(header: &str) -> Option<Result<Self>> {
+        let header = header.trim();
+        let (scheme, params) = header.split_once(char::is_whitespace)?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+        Some(Self::parse_params(params))
+    }
+
+    fn parse_params(params: &str) -> Result<Self> {
+        let params = auth_params(params)?;
+        let get = |name: &str| {
+            params
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+        };
+
+        let qop_auth = match get("qop") {
+            Some(qop) => {
+                if !qop
+                    .split(',')
+                    .any(|q| q.trim().eq_ignore_ascii_case("auth"))
+                {
+                    anyhow::bail!("server only offers qop={qop}; only qop=auth is supported");
+                }
+                true
+            }
+            None => false,
+        };
+        Ok(Self {
+            realm: get("realm").context("Digest challenge has no realm")?,
+            nonce: get("nonce").context("Digest challenge has no nonce")?,
+            opaque: get("opaque"),
+            algorithm: get("algorithm")
+                .as_deref()
+                .map_or(Ok(DigestAlgorithm::Md5), DigestAlgorithm::parse)?,
+            qop_auth,
+        })
+    }
+
+    /// Computes the `Authorization` header value for `method` and `uri` (the
+    /// request target, e.g. `/v1/odds?x=1`), with a random client nonce.
+    pub fn authorization(
+        &self,
+        credentials: &DigestCredentials,
+        method: &str,
+        uri: &str,
+    ) -> String {
+        let mut cnonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut cnonce);
+        let cnonce = hex::encode(cnonce);
+        // The first request with this nonce.
+        const NC: &str = "00000001";
+        let alg = self.algorithm;
+
+        let mut ha1 = alg.hash(&format!(
+            "{}:{}:{}",
+            credentials.username, self.realm, credentials.password
+        ));
+        if alg.is_session() {
+            ha1 = alg.hash(&format!("{ha1}:{}:{cnonce}", self.nonce));
+        }
+        let ha2 = alg.hash(&format!("{method}:{uri}"));
+        let response = if self.qop_auth {
+            alg.hash(&format!("{ha1}:{}:{NC}:{cnonce}:auth:{ha2}", self.nonce))
+        } else {
+            alg.hash(&format!("{ha1}:{}:{ha2}", self.nonce))
+        };
+
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{uri}\", \
+             algorithm={}, response=\"{response}\"",
+            quote(&credentials.username),
+            quote(&self.realm),
+            quote(&self.nonce),
+            alg.name(),
+        );
+        if self.qop_auth {
+            header.push_str(&format!(", qop=auth, nc={NC}, cnonce=\"{cnonce}\""));
+        }
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        header
+    }
+}
+
+/// Splits `name=value, name="quoted value"` auth parameters.
+fn auth_params(s: &str) -> Result<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let (name, after) = rest
+            .split_once('=')
+            .with_context(|| format!("malformed Digest parameter: {rest}"))?;
+        let after = after.trim_start();
+        let (value, after) = if let Some(quoted) = after.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((_, '\\')) => value.extend(chars.next().map(|(_, c)| c)),
+                    Some((i, '"')) => break i + 1,
+                    Some((_, c)) => value.push(c),
+                    None => anyhow::bail!("unterminated quoted Digest parameter"),
+                }
+            };
+            (value, &quoted[end..])
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            (after[..end].trim().to_string(), &after[end..])
+        };
+        params.push((name.trim().to_string(), value));
+        rest = after.trim_start().trim_start_matches(',').trim_start();
+    }
+    Ok(params)
+}
+
+/// Escapes a value for a quoted-string.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Sends an unauthenticated `method` request for `uri` over a plain TLS
+/// connection on `socket` (TCP, or a proxied stream) and returns the server's Digest challenge. The
+/// certificate is checked against the webpki roots and `server_name`.
+pub async fn fetch_challenge<S>(
+    socket: S,
+    server_name: &str,
+    host_header: &str,
+    method: &str,
+    uri: &str,
+) -> Result<DigestChallenge>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(server_name.to_string())
+        .with_context(|| format!("invalid server name: {server_name}"))?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(name, socket)
+        .await
+        .context("TLS handshake for the Digest challenge failed")?;
+
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake::<_, Empty<Bytes>>(TokioIo::new(tls)).await?;
+    tokio::spawn(connection);
+
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Host", host_header)
+        .header("Connection", "close")
+        .body(Empty::new())?;
+    let response = sender.send_request(request).await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        anyhow::bail!(
+            "expected 401 with a Digest challenge, got {}",
+            response.status()
+        );
+    }
+    response
+        .headers()
+        .get_all("www-authenticate")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(DigestChallenge::parse)
+        .context("401 response carries no WWW-Authenticate: Digest challenge")?
+}
@@ -0,0 +1,1201 @@
+//! Selective disclosure rules for HTTP transcripts.
+//!
+//! A [`DisclosureBuilder`] accumulates high-level reveal/redact rules and
+//! resolves them against a parsed [`HttpTranscript`] when [`finish`] is called,
+//! producing the [`TranscriptProof`] that goes into a presentation.
+//!
+//! The request line and target are always revealed. Request headers are
+//! revealed unless they match a redaction rule, in which case only the header
+//! name is disclosed, or with [`DisclosureBuilder::reveal_header_prefix`] the
+//! name and the first bytes of the value. On the response side the status line is always revealed;
+//! headers and body are revealed according to the rules.
+//!
+//! Responses without a body to frame, such as the response to a HEAD request,
+//! are described by a [`BodilessResponse`] and disclosed with
+//! [`finish_bodiless`]. So are responses whose body can't be parsed, such as
+//! compressed ones; their body can only be revealed whole.
+//!
+//! A body revealed in full can still have substrings matching a regex, such
+//! as session IDs or email addresses, hidden with
+//! [`DisclosureBuilder::redact_body_regex`].
+//!
+//! The HTTP committer commits each header value, JSON value and opaque body
+//! as a whole, and a proof can only open whole commitments. Rules that
+//! reveal part of one list the parts in [`DisclosureBuilder::extra_commits`],
+//! which the prover commits before requesting the attestation.
+//!
+//! When a transcript doesn't parse as HTTP cleanly, [`RawLines`] is a
+//! coarser fallback: it reveals the raw transcript except for whole lines
+//! matching a regex.
+//!
+//! For maximum privacy, [`reveal_only`] inverts this: it reveals an explicit
+//! allowlist of byte ranges and JSON values and nothing else, and reports what
+//! a verifier won't be able to parse as a result.
+//!
+//! [`finish`]: DisclosureBuilder::finish
+//! [`finish_bodiless`]: DisclosureBuilder::finish_bodiless
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use tlsn::transcript::{
+    Direction, TranscriptCommitConfigBuilder, TranscriptProof, TranscriptProofBuilder,
+};
+use tlsn_formats::{
+    http::{Body, BodyContent, Header, HttpTranscript, Request, Response},
+    spansy::Spanned,
+};
+
+use crate::http::{self, final_response, final_response_bounds, header_ranges};
+use crate::jsonpath::JsonPath;
+
+/// What part of the final response body to reveal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BodyRule {
+    /// The entire body.
+    Full,
+    /// A JSON value selected by a dot-separated path (e.g. `data.0.price`).
+    JsonPath(String),
+    /// A byte range relative to the start of the body.
+    Range(Range<usize>),
+}
+
+/// Accumulates disclosure rules for the request/response exchanges of one
+/// connection.
+#[derive(Debug, Clone, Default)]
+pub struct DisclosureBuilder {
+    redacted_headers: Vec<String>,
+    /// Request headers revealed in full even if a redaction pattern matches.
+    revealed_request_headers: Vec<String>,
+    /// Request headers whose values are revealed up to a length only.
+    header_prefixes: Vec<HeaderPrefix>,
+    revealed_response_headers: Option<Vec<String>>,
+    body_rules: Vec<BodyRule>,
+    /// When set, the request's form body is revealed except for the values
+    /// of these (decoded, lowercase) field names.
+    redacted_form_fields: Option<Vec<String>>,
+    /// Matches of these are hidden from a body revealed in full.
+    redacted_body_patterns: Vec<Regex>,
+}
+
+impl DisclosureBuilder {
+    /// Creates a builder that reveals the request, the response status line and
+    /// all response headers, but none of the response body.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redacts the value of any request header whose lowercase name contains
+    /// `pattern`, including every instance of a repeated header. The header
+    /// name itself stays revealed.
+    pub fn redact_header(&mut self, pattern: &str) -> &mut Self {
+        self.redacted_headers.push(pattern.trim().to_lowercase());
+        self
+    }
+
+    /// Reveals only the first `prefix.len` bytes of the value of every
+    /// request header named `prefix.name` (case-insensitive), e.g. the
+    /// `Bearer ` of an `Authorization` header, and hides the rest. Takes
+    /// precedence over [`redact_header`](Self::redact_header). Building the
+    /// proof fails if a value is no longer than the prefix, since the prefix
+    /// would then reveal all of it.
+    pub fn reveal_header_prefix(&mut self, prefix: &HeaderPrefix) -> &mut Self {
+        self.header_prefixes.push(HeaderPrefix {
+            name: prefix.name.trim().to_lowercase(),
+            len: prefix.len,
+        });
+        self
+    }
+
+    /// Reveals the value of every request header named `name`
+    /// (case-insensitive) even if it matches a
+    /// [`redact_header`](Self::redact_header) pattern.
+    /// [`reveal_header_prefix`](Self::reveal_header_prefix) takes precedence
+    /// over this.
+    pub fn reveal_request_header(&mut self, name: &str) -> &mut Self {
+        self.revealed_request_headers
+            .push(name.trim().to_lowercase());
+        self
+    }
+
+    /// Restricts revealed response headers to an explicit set. Once called,
+    /// only headers added with this method are revealed on the response side,
+    /// each instance of a repeated header alike.
+    pub fn reveal_header(&mut self, name: &str) -> &mut Self {
+        self.revealed_response_headers
+            .get_or_insert_with(Vec::new)
+            .push(name.trim().to_lowercase());
+        self
+    }
+
+    /// Reveals the request's URL-encoded form body, except for the values of
+    /// fields named in `redacted` (case-insensitive). Field names and every
+    /// instance of a repeated field are handled alike. Without this the
+    /// request body stays hidden.
+    pub fn reveal_form_body(&mut self, redacted: &[String]) -> &mut Self {
+        self.redacted_form_fields =
+            Some(redacted.iter().map(|f| f.trim().to_lowercase()).collect());
+        self
+    }
+
+    /// Reveals the entire response body.
+    pub fn reveal_body(&mut self) -> &mut Self {
+        self.body_rules.push(BodyRule::Full);
+        self
+    }
+
+    /// Reveals the JSON value at `path` in the response body.
+    pub fn reveal_json_path(&mut self, path: &str) -> &mut Self {
+        self.body_rules.push(BodyRule::JsonPath(path.to_string()));
+        self
+    }
+
+    /// Reveals a byte range of the response body.
+    pub fn reveal_body_range(&mut self, range: Range<usize>) -> &mut Self {
+        self.body_rules.push(BodyRule::Range(range));
+        self
+    }
+
+    /// Hides every match of `pattern` in a response body that is revealed in
+    /// full. Matches are found in the whole response, so one that starts in
+    /// the head is clamped to the body; overlapping matches are merged.
+    pub fn redact_body_regex(&mut self, pattern: Regex) -> &mut Self {
+        self.redacted_body_patterns.push(pattern);
+        self
+    }
+
+    fn header_prefix(&self, name: &str) -> Option<&HeaderPrefix> {
+        self.header_prefixes
+            .iter()
+            .find(|p| name.eq_ignore_ascii_case(&p.name))
+    }
+
+    /// Returns the ranges [`finish`](Self::finish) opens separately from the
+    /// ranges the HTTP committer commits them in; see [`ExtraCommits`].
+    pub fn extra_commits(&self, transcript: &HttpTranscript) -> Result<ExtraCommits> {
+        let mut commits = ExtraCommits::default();
+        for req in &transcript.requests {
+            self.request_commits(req, &mut commits)?;
+        }
+        for resp in transcript
+            .responses
+            .iter()
+            .filter(|resp| !http::is_interim(resp))
+        {
+            let Some(body) = resp.body.as_ref() else {
+                continue;
+            };
+            for rule in &self.body_rules {
+                match rule {
+                    BodyRule::Full if !self.redacted_body_patterns.is_empty() => {
+                        let (revealed, hidden) = self.split_body(resp, body);
+                        for range in revealed.into_iter().chain(hidden) {
+                            commits.push_recv(range);
+                        }
+                    }
+                    BodyRule::Range(range) => commits.push_recv(body_subrange(body, range)?),
+                    BodyRule::Full | BodyRule::JsonPath(_) => {}
+                }
+            }
+        }
+        Ok(commits)
+    }
+
+    /// Like [`extra_commits`](Self::extra_commits), for
+    /// [`finish_bodiless`](Self::finish_bodiless).
+    pub fn extra_commits_bodiless(&self, request: &Request) -> Result<ExtraCommits> {
+        let mut commits = ExtraCommits::default();
+        self.request_commits(request, &mut commits)?;
+        Ok(commits)
+    }
+
+    fn request_commits(&self, req: &Request, commits: &mut ExtraCommits) -> Result<()> {
+        for header in &req.headers {
+            let name = header.name.as_str();
+            if let Some(prefix) = self.header_prefix(name) {
+                let (revealed, hidden) = prefix_split(name, header_value_range(header), prefix)?;
+                commits.push_sent(revealed);
+                commits.push_sent(hidden);
+            }
+        }
+        for (range, _) in self.form_parts(req) {
+            commits.push_sent(range);
+        }
+        Ok(())
+    }
+
+    fn is_redacted(&self, name: &str) -> bool {
+        let name_lower = name.to_lowercase();
+        !self.revealed_request_headers.contains(&name_lower)
+            && self.redacted_headers.iter().any(|r| name_lower.contains(r))
+    }
+
+    fn is_revealed_response_header(&self, name: &str) -> bool {
+        match &self.revealed_response_headers {
+            Some(names) => names.iter().any(|n| name.eq_ignore_ascii_case(n)),
+            None => true,
+        }
+    }
+
+    fn reveal_request(
+        &self,
+        req: &Request,
+        proof_builder: &mut TranscriptProofBuilder<'_>,
+    ) -> Result<()> {
+        // Reveal request structure and target.
+        proof_builder.reveal_sent(&req.without_data())?;
+        proof_builder.reveal_sent(&req.request.target)?;
+
+        // Reveal headers, redacting sensitive ones. Each instance of a
+        // repeated header is its own entry, so rules apply to all of them.
+        for header in &req.headers {
+            let name = header.name.as_str();
+            if let Some(prefix) = self.header_prefix(name) {
+                let (revealed, _) = prefix_split(name, header_value_range(header), prefix)?;
+                proof_builder.reveal_sent(&header.without_value())?;
+                if !revealed.is_empty() {
+                    proof_builder.reveal_sent(&revealed)?;
+                }
+            } else if self.is_redacted(name) {
+                // Redact the value but reveal the header name.
+                proof_builder.reveal_sent(&header.without_value())?;
+            } else {
+                proof_builder.reveal_sent(header)?;
+            }
+        }
+
+        for (range, revealed) in self.form_parts(req) {
+            if revealed {
+                proof_builder.reveal_sent(&range)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits a URL-encoded request body under
+    /// [`reveal_form_body`](Self::reveal_form_body) into transcript ranges,
+    /// each with whether it is revealed: the `&` separators, each field name
+    /// with its `=`, and each value. Empty without form rules or a body.
+    fn form_parts(&self, req: &Request) -> Vec<(Range<usize>, bool)> {
+        let (Some(redacted), Some(body)) = (&self.redacted_form_fields, &req.body) else {
+            return Vec::new();
+        };
+        let start = body.span().indices().min().unwrap_or_default();
+        let bytes = body.span().as_bytes();
+        let mut parts = Vec::new();
+        for (i, _) in bytes.iter().enumerate().filter(|(_, &b)| b == b'&') {
+            parts.push((start + i..start + i + 1, true));
+        }
+        for (name, value) in http::form_field_ranges(bytes) {
+            // The name and its `=`, if any.
+            parts.push((start + name.start..start + value.start.max(name.end), true));
+            let decoded = url::form_urlencoded::parse(&bytes[name])
+                .next()
+                .map(|(name, _)| name.to_lowercase())
+                .unwrap_or_default();
+            if !value.is_empty() {
+                let revealed = !redacted.contains(&decoded);
+                parts.push((start + value.start..start + value.end, revealed));
+            }
+        }
+        parts
+    }
+
+    /// Resolves the accumulated rules against `transcript` and builds the
+    /// transcript proof.
+    ///
+    /// A transcript of several requests sent over one connection has the
+    /// rules applied to each request and its response alike.
+    pub fn finish(
+        &self,
+        transcript: &HttpTranscript,
+        mut proof_builder: TranscriptProofBuilder<'_>,
+    ) -> Result<TranscriptProof> {
+        if transcript.requests.is_empty() {
+            anyhow::bail!("transcript contains no request");
+        }
+        let mut responses = transcript.responses.iter();
+        for req in &transcript.requests {
+            self.reveal_request(req, &mut proof_builder)?;
+
+            // Reveal any 1xx informational responses in full so the verifier
+            // can skip past them, then apply the rules to the final response.
+            let resp = loop {
+                let resp = responses
+                    .next()
+                    .context("transcript contains no final response")?;
+                if !http::is_interim(resp) {
+                    break resp;
+                }
+                proof_builder.reveal_recv(resp)?;
+            };
+            self.reveal_response(resp, &mut proof_builder)?;
+        }
+
+        Ok(proof_builder.build()?)
+    }
+
+    fn reveal_response(
+        &self,
+        resp: &Response,
+        proof_builder: &mut TranscriptProofBuilder<'_>,
+    ) -> Result<()> {
+        proof_builder.reveal_recv(&resp.without_data())?;
+
+        for header in &resp.headers {
+            let name = header.name.as_str();
+            if self.is_revealed_response_header(name) {
+                proof_builder.reveal_recv(header)?;
+            }
+        }
+
+        // A missing body is fine when the whole body was asked for (there is
+        // nothing to reveal), but not when specific parts were requested.
+        if let Some(body) = resp.body.as_ref() {
+            for rule in &self.body_rules {
+                match rule {
+                    BodyRule::Full if self.redacted_body_patterns.is_empty() => {
+                        proof_builder.reveal_recv(body)?;
+                    }
+                    BodyRule::Full => {
+                        let (revealed, _) = self.split_body(resp, body);
+                        for range in &revealed {
+                            proof_builder.reveal_recv(range)?;
+                        }
+                    }
+                    BodyRule::JsonPath(path) => {
+                        let BodyContent::Json(json) = &body.content else {
+                            anyhow::bail!("cannot reveal JSON path {path}: body is not JSON");
+                        };
+                        let value = json
+                            .get(path)
+                            .with_context(|| format!("JSON path not found in body: {path}"))?;
+                        proof_builder.reveal_recv(value)?;
+                    }
+                    BodyRule::Range(range) => {
+                        proof_builder.reveal_recv(&body_subrange(body, range)?)?;
+                    }
+                }
+            }
+        } else if self.body_rules.iter().any(|r| *r != BodyRule::Full) {
+            anyhow::bail!("body disclosure requested but response has no body");
+        }
+        Ok(())
+    }
+
+    /// Splits a response body revealed in full around the matches of
+    /// [`redact_body_regex`](Self::redact_body_regex) patterns: the ranges
+    /// revealed between matches, and the matches.
+    fn split_body(&self, resp: &Response, body: &Body) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+        let message = resp.span();
+        let offset = message.indices().min().unwrap_or_default();
+        let body_start = body.span().indices().min().unwrap_or_default();
+        split_around_matches(
+            &self.redacted_body_patterns,
+            message.as_bytes(),
+            offset,
+            &(body_start..body_start + body.span().len()),
+        )
+    }
+
+    /// Like [`finish`](Self::finish), for an exchange whose response has no
+    /// body, or a raw one. Body rules other than [`BodyRule::Full`] are
+    /// rejected; [`BodyRule::Full`] reveals a raw body.
+    pub fn finish_bodiless(
+        &self,
+        request: &Request,
+        response: &BodilessResponse,
+        mut proof_builder: TranscriptProofBuilder<'_>,
+    ) -> Result<TranscriptProof> {
+        if self.body_rules.iter().any(|r| *r != BodyRule::Full) {
+            anyhow::bail!(match response.body {
+                Some(_) => "only the whole body can be revealed for a response that isn't parsed",
+                None => "body disclosure requested but response has no body",
+            });
+        }
+        if response.body.is_some() && !self.redacted_body_patterns.is_empty() {
+            anyhow::bail!(
+                "cannot redact parts of a body that isn't parsed, such as a compressed one"
+            );
+        }
+        self.reveal_request(request, &mut proof_builder)?;
+        if let Some(body) = response
+            .body
+            .as_ref()
+            .filter(|_| !self.body_rules.is_empty())
+        {
+            proof_builder.reveal_recv(body)?;
+        }
+
+        for range in &response.structure {
+            proof_builder.reveal_recv(range)?;
+        }
+        for (name, value) in &response.header_values {
+            if self.is_revealed_response_header(name) {
+                proof_builder.reveal_recv(value)?;
+            }
+        }
+
+        Ok(proof_builder.build()?)
+    }
+}
+
+/// The transcript range of a header's value.
+fn header_value_range(header: &Header) -> Range<usize> {
+    let value = header.value.span();
+    let start = value.indices().min().unwrap_or_default();
+    start..start + value.len()
+}
+
+/// Splits a header value into the prefix [`HeaderPrefix`] reveals and the
+/// rest, failing if the prefix would be all of it.
+fn prefix_split(
+    name: &str,
+    value: Range<usize>,
+    prefix: &HeaderPrefix,
+) -> Result<(Range<usize>, Range<usize>)> {
+    if prefix.len >= value.len() {
+        anyhow::bail!(
+            "{name} value is {} bytes, so revealing a {}-byte prefix would disclose all of it",
+            value.len(),
+            prefix.len
+        );
+    }
+    let split = value.start + prefix.len;
+    Ok((value.start..split, split..value.end))
+}
+
+/// Ranges that disclosure rules reveal apart from the larger ranges the HTTP
+/// committer commits them in, such as the prefix of a header value and the
+/// rest of it. A proof can only open whole commitments, so each of these has
+/// to be committed on its own, before the attestation is requested.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtraCommits {
+    pub sent: Vec<Range<usize>>,
+    pub recv: Vec<Range<usize>>,
+}
+
+impl ExtraCommits {
+    fn push_sent(&mut self, range: Range<usize>) {
+        if !range.is_empty() && !self.sent.contains(&range) {
+            self.sent.push(range);
+        }
+    }
+
+    fn push_recv(&mut self, range: Range<usize>) {
+        if !range.is_empty() && !self.recv.contains(&range) {
+            self.recv.push(range);
+        }
+    }
+
+    /// Commits to every range.
+    pub fn commit(&self, builder: &mut TranscriptCommitConfigBuilder<'_>) -> Result<()> {
+        for range in &self.sent {
+            builder.commit_sent(range)?;
+        }
+        for range in &self.recv {
+            builder.commit_recv(range)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a range of `self` that `committed` lacks, with its direction,
+    /// if there is one.
+    pub fn missing_from(&self, committed: &Self) -> Option<(Direction, Range<usize>)> {
+        let sent = self
+            .sent
+            .iter()
+            .find(|range| !committed.sent.contains(range))
+            .map(|range| (Direction::Sent, range.clone()));
+        sent.or_else(|| {
+            self.recv
+                .iter()
+                .find(|range| !committed.recv.contains(range))
+                .map(|range| (Direction::Received, range.clone()))
+        })
+    }
+}
+
+/// Returns the transcript range of `range`, relative to the start of `body`,
+/// or an error if it is empty or runs past the end of the body.
+fn body_subrange(body: &Body, range: &Range<usize>) -> Result<Range<usize>> {
+    let start = body.span().indices().min().unwrap_or_default();
+    offset_subrange(start..start + body.span().len(), range)
+}
+
+fn offset_subrange(body: Range<usize>, range: &Range<usize>) -> Result<Range<usize>> {
+    let len = body.len();
+    if range.start >= range.end || range.end > len {
+        anyhow::bail!(
+            "body range {}..{} out of bounds (body is {len} bytes)",
+            range.start,
+            range.end
+        );
+    }
+    Ok(body.start + range.start..body.start + range.end)
+}
+
+/// Splits `body` (transcript offsets) of `message`, a response starting at
+/// transcript offset `offset`, into the ranges between matches of
+/// `patterns` and the matches themselves; see [`body_matches`].
+fn split_around_matches(
+    patterns: &[Regex],
+    message: &[u8],
+    offset: usize,
+    body: &Range<usize>,
+) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let matches = body_matches(patterns, message, offset, body);
+    let mut revealed = Vec::new();
+    let mut cursor = body.start;
+    for redacted in &matches {
+        if redacted.start > cursor {
+            revealed.push(cursor..redacted.start);
+        }
+        cursor = redacted.end;
+    }
+    if cursor < body.end {
+        revealed.push(cursor..body.end);
+    }
+    (revealed, matches)
+}
+
+/// Finds every match of `patterns` in `message`, a response starting at
+/// transcript offset `offset`, clamped to `body` (transcript offsets). Each
+/// pattern is also tried from inside its previous match so that overlapping
+/// matches are found. The result is sorted and overlaps are merged.
+fn body_matches(
+    patterns: &[Regex],
+    message: &[u8],
+    offset: usize,
+    body: &Range<usize>,
+) -> Vec<Range<usize>> {
+    let mut matches = Vec::new();
+    for pattern in patterns {
+        let mut from = 0;
+        while let Some(m) = pattern.find_at(message, from) {
+            let start = (offset + m.start()).max(body.start);
+            let end = (offset + m.end()).min(body.end);
+            if start < end {
+                matches.push(start..end);
+            }
+            from = m.start() + 1;
+            if from > message.len() {
+                break;
+            }
+        }
+    }
+    matches.sort_by_key(|m| m.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for m in matches {
+        match merged.last_mut() {
+            Some(last) if m.start <= last.end => last.end = last.end.max(m.end),
+            _ => merged.push(m),
+        }
+    }
+    merged
+}
+
+/// Returns the transcript byte range of the JSON value at a dotted `path`
+/// (e.g. `data.0.price`) in the final response body.
+pub fn json_value_range(transcript: &HttpTranscript, path: &str) -> Result<Range<usize>> {
+    let resp =
+        final_response(&transcript.responses).context("transcript contains no final response")?;
+    let body = resp.body.as_ref().context("response has no body")?;
+    let BodyContent::Json(json) = &body.content else {
+        anyhow::bail!("cannot locate JSON path {path}: body is not JSON");
+    };
+    let value = json
+        .get(path)
+        .with_context(|| format!("JSON path not found in body: {path}"))?;
+    let start = value.span().indices().min().unwrap_or_default();
+    let end = value.span().indices().max().map_or(start, |i| i + 1);
+    Ok(start..end)
+}
+
+/// Expands `path`, which may contain wildcards and negative indices, into the
+/// dotted path of every value it selects in the final response body, for
+/// [`DisclosureBuilder::reveal_json_path`]. Fails if nothing matches.
+pub fn expand_json_path(transcript: &HttpTranscript, path: &JsonPath) -> Result<Vec<String>> {
+    let resp =
+        final_response(&transcript.responses).context("transcript contains no final response")?;
+    let body = resp.body.as_ref().context("response has no body")?;
+    if !matches!(body.content, BodyContent::Json(_)) {
+        anyhow::bail!("cannot reveal JSON path {path}: body is not JSON");
+    }
+    let value: serde_json::Value =
+        serde_json::from_slice(body.span().as_bytes()).context("body is not valid JSON")?;
+    let paths: Vec<String> = path
+        .select_with_paths(&value)
+        .into_iter()
+        .map(|(dotted, _)| dotted)
+        .collect();
+    if paths.is_empty() {
+        anyhow::bail!("JSON path not found in body: {path}");
+    }
+    Ok(paths)
+}
+
+/// The response head of an exchange whose response carries no body, such as
+/// the response to a HEAD request.
+///
+/// The HTTP transcript parser frames response bodies by `Content-Length`,
+/// which a HEAD response declares without sending, so these responses are
+/// located in the raw received bytes instead. Any 1xx interim responses and
+/// the final head minus its header values form the always-revealed structure.
+///
+/// A response whose body is sent but can't be parsed, such as a compressed
+/// one, is handled the same way with the body kept as one raw range
+/// ([`parse_with_raw_body`](Self::parse_with_raw_body)).
+#[derive(Debug, Clone)]
+pub struct BodilessResponse {
+    structure: Vec<Range<usize>>,
+    header_values: Vec<(String, Range<usize>)>,
+    body: Option<Range<usize>>,
+}
+
+impl BodilessResponse {
+    /// Locates the final response head in `received`.
+    pub fn parse(received: &[u8]) -> Result<Self> {
+        let (head_start, body_start) =
+            final_response_bounds(received).context("response head is incomplete")?;
+        let head = std::str::from_utf8(&received[head_start..body_start])
+            .context("response head is not valid UTF-8")?;
+
+        let mut structure = Vec::new();
+        let mut header_values = Vec::new();
+        let mut cursor = 0;
+        for (name, value) in header_ranges(head) {
+            let value = (head_start + value.start)..(head_start + value.end);
+            if value.start > cursor {
+                structure.push(cursor..value.start);
+            }
+            cursor = value.end;
+            if !value.is_empty() {
+                header_values.push((name.to_string(), value));
+            }
+        }
+        structure.push(cursor..body_start);
+
+        Ok(Self {
+            structure,
+            header_values,
+            body: None,
+        })
+    }
+
+    /// Like [`parse`](Self::parse), keeping everything after the final head
+    /// as an opaque body.
+    pub fn parse_with_raw_body(received: &[u8]) -> Result<Self> {
+        let mut response = Self::parse(received)?;
+        let (_, body_start) =
+            final_response_bounds(received).context("response head is incomplete")?;
+        response.body = (body_start < received.len()).then(|| body_start..received.len());
+        Ok(response)
+    }
+
+    /// Commits to every range [`DisclosureBuilder::finish_bodiless`] may reveal.
+    pub fn commit(&self, builder: &mut TranscriptCommitConfigBuilder<'_>) -> Result<()> {
+        for range in &self.structure {
+            builder.commit_recv(range)?;
+        }
+        for (_, value) in &self.header_values {
+            builder.commit_recv(value)?;
+        }
+        if let Some(body) = &self.body {
+            builder.commit_recv(body)?;
+        }
+        Ok(())
+    }
+}
+
+/// One direction of a raw transcript split at CRLFs into lines to reveal and
+/// lines to hide, for redaction that doesn't rely on parsing HTTP.
+///
+/// This is coarser and less safe than the structured rules of
+/// [`DisclosureBuilder`]: a pattern that misses a line, say because a secret
+/// is folded or split differently than expected, reveals it. Hidden lines
+/// are still committed; the CRLFs between lines stay revealed.
+#[derive(Debug, Clone, Default)]
+pub struct RawLines {
+    revealed: Vec<Range<usize>>,
+    hidden: Vec<Range<usize>>,
+}
+
+impl RawLines {
+    /// Hides every line of `data` that one of `patterns` matches, and the
+    /// value of every `name: value` line whose lowercase name contains one of
+    /// `redacted_headers`, as [`DisclosureBuilder::redact_header`] would.
+    /// Header names are looked for on every line, so this can hide more than
+    /// the header block.
+    pub fn split(data: &[u8], patterns: &[Regex], redacted_headers: &[String]) -> Self {
+        let mut lines = Self::default();
+        let mut start = 0;
+        while start < data.len() {
+            let end = data[start..]
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .map_or(data.len(), |i| start + i);
+            let line = &data[start..end];
+            let header_value = line.iter().position(|&b| b == b':').filter(|&colon| {
+                let name = String::from_utf8_lossy(&line[..colon]).to_lowercase();
+                redacted_headers
+                    .iter()
+                    .any(|pattern| name.trim().contains(pattern.as_str()))
+            });
+            if patterns.iter().any(|pattern| pattern.is_match(line)) {
+                lines.hide(start..end);
+            } else if let Some(colon) = header_value {
+                lines.reveal(start..start + colon + 1);
+                lines.hide(start + colon + 1..end);
+            } else {
+                lines.reveal(start..end);
+            }
+            lines.reveal(end..(end + 2).min(data.len()));
+            start = end + 2;
+        }
+        lines
+    }
+
+    fn reveal(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        match self.revealed.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.revealed.push(range),
+        }
+    }
+
+    fn hide(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.hidden.push(range);
+        }
+    }
+
+    /// Returns the number of hidden lines and header values.
+    pub fn hidden_count(&self) -> usize {
+        self.hidden.len()
+    }
+
+    /// Commits to every revealed range and hidden line.
+    pub fn commit(
+        &self,
+        builder: &mut TranscriptCommitConfigBuilder<'_>,
+        direction: Direction,
+    ) -> Result<()> {
+        for range in self.revealed.iter().chain(&self.hidden) {
+            match direction {
+                Direction::Sent => builder.commit_sent(range)?,
+                Direction::Received => builder.commit_recv(range)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Reveals everything but the hidden lines.
+    pub fn reveal_in(
+        &self,
+        proof_builder: &mut TranscriptProofBuilder<'_>,
+        direction: Direction,
+    ) -> Result<()> {
+        for range in &self.revealed {
+            match direction {
+                Direction::Sent => proof_builder.reveal_sent(range)?,
+                Direction::Received => proof_builder.reveal_recv(range)?,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// A limit on how much of the transcript a presentation may disclose, either
+/// as a fraction of all transcript bytes (`50%`) or an absolute byte count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisclosureThreshold {
+    /// Fraction of sent plus received bytes, in `0.0..=1.0`.
+    Ratio(f64),
+    /// Number of revealed bytes across both directions.
+    Bytes(usize),
+}
+
+impl FromStr for DisclosureThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f64 = percent
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid percentage: {s}"))?;
+            if !(0.0..=100.0).contains(&percent) {
+                anyhow::bail!("percentage must be between 0 and 100: {s}");
+            }
+            Ok(Self::Ratio(percent / 100.0))
+        } else {
+            Ok(Self::Bytes(s.parse().with_context(|| {
+                format!("expected <percent>% or a byte count: {s}")
+            })?))
+        }
+    }
+}
+
+impl fmt::Display for DisclosureThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ratio(ratio) => write!(f, "{}%", ratio * 100.0),
+            Self::Bytes(bytes) => write!(f, "{bytes} bytes"),
+        }
+    }
+}
+
+impl DisclosureThreshold {
+    /// Returns true if revealing `revealed` of `total` bytes exceeds the limit.
+    pub fn is_exceeded(&self, revealed: usize, total: usize) -> bool {
+        match *self {
+            Self::Ratio(ratio) => total > 0 && revealed as f64 / total as f64 > ratio,
+            Self::Bytes(bytes) => revealed > bytes,
+        }
+    }
+}
+
+/// A request header whose value is revealed up to `len` bytes only, parsed
+/// from `<name>:<len>`, e.g. `authorization:7`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderPrefix {
+    pub name: String,
+    pub len: usize,
+}
+
+impl FromStr for HeaderPrefix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, len) = s
+            .rsplit_once(':')
+            .with_context(|| format!("expected <header>:<bytes>: {s}"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("missing header name: {s}");
+        }
+        let len = len
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid prefix length: {s}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            len,
+        })
+    }
+}
+
+impl fmt::Display for HeaderPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.len)
+    }
+}
+
+/// One explicitly revealed part of the transcript, for allowlist-only
+/// disclosure ([`reveal_only`]). Parsed from `sent:<start>..<end>`,
+/// `recv:<start>..<end>` (transcript byte offsets) or `json:<jsonpath>` (a
+/// single value in the final response body).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevealSelector {
+    Sent(Range<usize>),
+    Recv(Range<usize>),
+    Json(JsonPath),
+}
+
+impl FromStr for RevealSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, spec) = s
+            .split_once(':')
+            .context("expected sent:<start>..<end>, recv:<start>..<end> or json:<jsonpath>")?;
+        let range = || -> Result<Range<usize>> {
+            let (start, end) = spec
+                .split_once("..")
+                .with_context(|| format!("expected <start>..<end>: {spec}"))?;
+            let range = start.trim().parse()?..end.trim().parse()?;
+            if range.is_empty() {
+                anyhow::bail!("empty range: {spec}");
+            }
+            Ok(range)
+        };
+        match kind.trim() {
+            "sent" => Ok(Self::Sent(range()?)),
+            "recv" => Ok(Self::Recv(range()?)),
+            "json" => Ok(Self::Json(spec.parse()?)),
+            other => anyhow::bail!("unknown selector kind {other:?}: expected sent, recv or json"),
+        }
+    }
+}
+
+/// Builds a transcript proof that reveals exactly `selectors` and nothing
+/// else: unlike [`DisclosureBuilder`], not even the request line or the
+/// response status line is revealed unless selected.
+///
+/// `http` is the parsed transcript, needed only for JSON selectors. Returns
+/// the proof and warnings about what verifiers will be unable to parse from
+/// the revealed bytes; see [`parse_warnings`].
+pub fn reveal_only(
+    selectors: &[RevealSelector],
+    sent: &[u8],
+    received: &[u8],
+    http: Option<&HttpTranscript>,
+    mut proof_builder: TranscriptProofBuilder<'_>,
+) -> Result<(TranscriptProof, Vec<String>)> {
+    let (sent_ranges, recv_ranges, json_selected) =
+        selected_ranges(selectors, sent, received, http)?;
+
+    for range in &sent_ranges {
+        proof_builder.reveal_sent(range)?;
+    }
+    for range in &recv_ranges {
+        proof_builder.reveal_recv(range)?;
+    }
+
+    let mut warnings = parse_warnings(sent, received, &sent_ranges, &recv_ranges);
+    if json_selected {
+        if let Some((_, body_start)) = final_response_bounds(received) {
+            if !is_covered(&recv_ranges, &(body_start..received.len())) {
+                warnings.push(
+                    "the response body is only partly revealed: verifiers see the selected \
+                     JSON values but cannot parse the body as JSON"
+                        .into(),
+                );
+            }
+        }
+    }
+    Ok((proof_builder.build()?, warnings))
+}
+
+/// Returns the ranges [`reveal_only`] opens for `selectors`. None of them
+/// need line up with what the HTTP committer commits, so the prover commits
+/// each one on its own.
+pub fn reveal_only_commits(
+    selectors: &[RevealSelector],
+    sent: &[u8],
+    received: &[u8],
+    http: Option<&HttpTranscript>,
+) -> Result<ExtraCommits> {
+    let (sent_ranges, recv_ranges, _) = selected_ranges(selectors, sent, received, http)?;
+    let mut commits = ExtraCommits::default();
+    for range in sent_ranges {
+        commits.push_sent(range);
+    }
+    for range in recv_ranges {
+        commits.push_recv(range);
+    }
+    Ok(commits)
+}
+
+/// Resolves `selectors` to sent and received ranges, and whether any of them
+/// is a JSON selector.
+fn selected_ranges(
+    selectors: &[RevealSelector],
+    sent: &[u8],
+    received: &[u8],
+    http: Option<&HttpTranscript>,
+) -> Result<(Vec<Range<usize>>, Vec<Range<usize>>, bool)> {
+    let mut sent_ranges = Vec::new();
+    let mut recv_ranges = Vec::new();
+    let mut json_selected = false;
+    for selector in selectors {
+        match selector {
+            RevealSelector::Sent(range) => {
+                if range.end > sent.len() {
+                    anyhow::bail!(
+                        "sent range {range:?} out of bounds (sent {} bytes)",
+                        sent.len()
+                    );
+                }
+                sent_ranges.push(range.clone());
+            }
+            RevealSelector::Recv(range) => {
+                if range.end > received.len() {
+                    anyhow::bail!(
+                        "recv range {range:?} out of bounds (received {} bytes)",
+                        received.len()
+                    );
+                }
+                recv_ranges.push(range.clone());
+            }
+            RevealSelector::Json(path) => {
+                let transcript = http.context("JSON selectors need a response body")?;
+                let dotted = path
+                    .to_dotted()
+                    .with_context(|| format!("JSON selector must name a single field: {path}"))?;
+                recv_ranges.push(json_value_range(transcript, &dotted)?);
+                json_selected = true;
+            }
+        }
+    }
+    Ok((sent_ranges, recv_ranges, json_selected))
+}
+
+/// Lists what a verifier won't be able to parse when only `sent_ranges` and
+/// `recv_ranges` of the transcript are revealed: the request line, the final
+/// response's status line, and the end of its head, without which revealed
+/// body bytes can't be told apart from headers.
+pub fn parse_warnings(
+    sent: &[u8],
+    received: &[u8],
+    sent_ranges: &[Range<usize>],
+    recv_ranges: &[Range<usize>],
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let line_end = |data: &[u8], start: usize| {
+        data[start..]
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .map(|i| start + i)
+    };
+
+    if let Some(end) = line_end(sent, 0) {
+        if !is_covered(sent_ranges, &(0..end)) {
+            warnings.push(
+                "the request line is not revealed: verifiers cannot tell the method or target"
+                    .into(),
+            );
+        }
+    }
+
+    let Some((head_start, body_start)) = final_response_bounds(received) else {
+        return warnings;
+    };
+    if let Some(end) = line_end(received, head_start) {
+        if !is_covered(recv_ranges, &(head_start..end)) {
+            warnings.push(
+                "the response status line is not revealed: verifiers cannot parse the response"
+                    .into(),
+            );
+        }
+    }
+    let reveals_body = recv_ranges.iter().any(|r| r.end > body_start);
+    if reveals_body && !is_covered(recv_ranges, &(body_start - 4..body_start)) {
+        warnings.push(
+            "the end of the response headers is not revealed: verifiers cannot locate the \
+             body, so revealed body bytes only show up in the raw transcript"
+                .into(),
+        );
+    }
+    warnings
+}
+
+/// Returns true if the union of `ranges` contains every byte of `range`.
+fn is_covered(ranges: &[Range<usize>], range: &Range<usize>) -> bool {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|r| r.start);
+    let mut pos = range.start;
+    for r in sorted {
+        if pos >= range.end {
+            break;
+        }
+        if r.start <= pos && r.end > pos {
+            pos = r.end;
+        }
+    }
+    pos >= range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nX-Session: abc123\r\n\r\n\
+        {\"session\":\"abc123\",\"email\":\"a@example.com\",\"odds\":1.5}";
+
+    fn body_range() -> Range<usize> {
+        let start = RESPONSE.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        start..RESPONSE.len()
+    }
+
+    /// Replaces every byte outside `revealed` with [`crate::verify::REDACTED_BYTE`],
+    /// as a verifier sees the presentation.
+    fn disclosed(data: &[u8], revealed: &[Range<usize>]) -> Vec<u8> {
+        let mut out = vec![crate::verify::REDACTED_BYTE; data.len()];
+        for range in revealed {
+            out[range.clone()].copy_from_slice(&data[range.clone()]);
+        }
+        out
+    }
+
+    #[test]
+    fn redacted_body_matches_are_not_disclosed() {
+        let patterns = [
+            Regex::new("abc123").unwrap(),
+            Regex::new(r"[a-z]+@[a-z.]+").unwrap(),
+        ];
+        let body = body_range();
+        let (revealed, hidden) = split_around_matches(&patterns, RESPONSE, 0, &body);
+
+        let seen = disclosed(RESPONSE, &revealed);
+        let seen = String::from_utf8_lossy(&seen);
+        assert!(!seen.contains("abc123"));
+        assert!(!seen.contains("a@example.com"));
+        assert!(seen.contains("\"odds\":1.5}"));
+        assert_eq!(hidden.len(), 2);
+    }
+
+    #[test]
+    fn body_split_partitions_the_body() {
+        let patterns = [Regex::new("abc123").unwrap()];
+        let body = body_range();
+        let (revealed, hidden) = split_around_matches(&patterns, RESPONSE, 0, &body);
+
+        // The header match is outside the body, so only the body one is hidden.
+        assert_eq!(hidden.len(), 1);
+        let mut parts: Vec<_> = revealed.iter().chain(&hidden).cloned().collect();
+        parts.sort_by_key(|r| r.start);
+        assert_eq!(parts.first().unwrap().start, body.start);
+        assert_eq!(parts.last().unwrap().end, body.end);
+        for pair in parts.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn body_matches_are_clamped_and_merged() {
+        let patterns = [
+            Regex::new(r"\r\n\r\n\{").unwrap(),
+            Regex::new("session\":\"abc").unwrap(),
+            Regex::new("abc123").unwrap(),
+        ];
+        let body = body_range();
+        let matches = body_matches(&patterns, RESPONSE, 0, &body);
+        // The match straddling the head ends up as the body's first byte;
+        // the overlapping session matches are merged.
+        assert_eq!(matches[0], body.start..body.start + 1);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(&RESPONSE[matches[1].clone()], b"session\":\"abc123");
+    }
+
+    #[test]
+    fn body_ranges_are_offset_and_bounded() {
+        assert_eq!(offset_subrange(40..60, &(2..5)).unwrap(), 42..45);
+        assert_eq!(offset_subrange(40..60, &(0..20)).unwrap(), 40..60);
+        assert!(offset_subrange(40..60, &(10..21)).is_err());
+        assert!(offset_subrange(40..60, &(5..5)).is_err());
+    }
+
+    #[test]
+    fn reveal_only_commits_each_selected_range() {
+        let selectors: Vec<RevealSelector> = ["sent:0..3", "recv:9..12", "recv:9..12"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let commits =
+            reveal_only_commits(&selectors, b"GET / HTTP/1.1\r\n", RESPONSE, None).unwrap();
+        assert_eq!(commits.sent, vec![0..3]);
+        assert_eq!(commits.recv, vec![9..12]);
+
+        let outside: RevealSelector = "sent:0..99".parse().unwrap();
+        assert!(reveal_only_commits(&[outside], b"GET", RESPONSE, None).is_err());
+    }
+}
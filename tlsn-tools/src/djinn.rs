@@ -0,0 +1,149 @@
+//! A single entry point for embedding the prover and verifier.
+//!
+//! [`Djinn`] holds the configuration shared by every operation: the crypto
+//! provider, the notary trust list and the [`Transport`] to the notary. Build
+//! it once and call [`Djinn::prove`] / [`Djinn::verify`] as often as needed.
+//! [`verify_presentation`] verifies without one, for callers that only
+//! verify.
+//!
+//! # Lifecycle and ownership
+//!
+//! A `Djinn` owns no connections or background tasks. The configuration sits
+//! behind an [`Arc`], so clones are cheap and share it; clone one into each
+//! task that needs it. Each `prove` call opens its own notary session and
+//! target connection and tears both down before returning, including when it
+//! fails or its future is dropped. `verify` is CPU-bound and synchronous; run
+//! it on a blocking thread when calling from async code.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tlsn::attestation::CryptoProvider;
+
+use crate::{
+    notary::NotaryClient,
+    prove::{self, ProveOptions, ProveResult, SavedAttestation, Transport},
+    verify::{self, checks::Checks, VerificationReport},
+    MAX_PRESENTATION_BYTES,
+};
+
+/// Configuration shared by a [`Djinn`] and its clones.
+pub struct DjinnConfig {
+    /// Checks attestation signatures and server certificates.
+    pub crypto_provider: CryptoProvider,
+    /// Hex-encoded notary public keys accepted by [`Djinn::verify`]. Empty
+    /// accepts any notary.
+    pub trusted_keys: Vec<String>,
+    /// How [`Djinn::prove`] reaches the notary and targets.
+    pub transport: Transport,
+}
+
+impl DjinnConfig {
+    /// A configuration with the default crypto provider and no trust list.
+    pub fn new(transport: Transport) -> Self {
+        Self {
+            crypto_provider: CryptoProvider::default(),
+            trusted_keys: Vec::new(),
+            transport,
+        }
+    }
+}
+
+/// Per-call options for [`Djinn::verify`] and [`verify_presentation`].
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Largest serialized presentation accepted, in bytes.
+    pub max_presentation_bytes: u64,
+    /// Hex-encoded notary public keys accepted by [`verify_presentation`].
+    /// Empty accepts any notary. [`Djinn::verify`] checks
+    /// [`DjinnConfig::trusted_keys`] instead.
+    pub trusted_keys: Vec<String>,
+    /// Expectations on the disclosed data, reported in
+    /// [`VerificationReport::checks`].
+    pub checks: Checks,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            max_presentation_bytes: MAX_PRESENTATION_BYTES,
+            trusted_keys: Vec::new(),
+            checks: Checks::default(),
+        }
+    }
+}
+
+/// Verifies a serialized presentation, raw bincode or in a JSON envelope,
+/// with the default crypto provider, without a [`Djinn`].
+///
+/// Failures carry an [`ErrorCode`](verify::error::ErrorCode), as the
+/// verifier CLI reports them. A presentation that verifies but fails
+/// [`VerifyOptions::checks`] is returned with the failures in
+/// [`VerificationReport::checks`].
+pub fn verify_presentation(bytes: &[u8], opts: VerifyOptions) -> Result<VerificationReport> {
+    verify::verify_bytes(
+        bytes,
+        opts.max_presentation_bytes,
+        &opts.trusted_keys,
+        &opts.checks,
+        &CryptoProvider::default(),
+    )
+}
+
+/// Shared prover/verifier handle. `Clone`, `Send` and `Sync`.
+#[derive(Clone)]
+pub struct Djinn {
+    config: Arc<DjinnConfig>,
+}
+
+impl Djinn {
+    pub fn new(config: DjinnConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    /// The shared configuration.
+    pub fn config(&self) -> &DjinnConfig {
+        &self.config
+    }
+
+    /// Makes one attested request and returns its presentation.
+    pub async fn prove(&self, opts: &ProveOptions) -> Result<ProveResult> {
+        prove::prove(&self.config.transport, opts, &self.config.crypto_provider).await
+    }
+
+    /// Like [`prove`](Self::prove), with the attestation requested from
+    /// `notary`; see [`prove::prove_with_notary`].
+    pub async fn prove_with_notary(
+        &self,
+        opts: &ProveOptions,
+        notary: &mut (dyn NotaryClient + Send),
+    ) -> Result<ProveResult> {
+        prove::prove_with_notary(
+            &self.config.transport,
+            opts,
+            &self.config.crypto_provider,
+            notary,
+        )
+        .await
+    }
+
+    /// Builds a new presentation from a saved attestation, offline; see
+    /// [`prove::rebuild`].
+    pub fn rebuild(&self, saved: &SavedAttestation, opts: &ProveOptions) -> Result<ProveResult> {
+        prove::rebuild(saved, opts, &self.config.crypto_provider)
+    }
+
+    /// Verifies a serialized presentation against the trust list, like
+    /// [`verify_presentation`].
+    pub fn verify(&self, bytes: &[u8], opts: &VerifyOptions) -> Result<VerificationReport> {
+        verify::verify_bytes(
+            bytes,
+            opts.max_presentation_bytes,
+            &self.config.trusted_keys,
+            &opts.checks,
+            &self.config.crypto_provider,
+        )
+    }
+}
@@ -0,0 +1,177 @@
+//! Portable JSON envelopes around serialized presentations.
+//!
+//! A bincode presentation is opaque binary. An envelope wraps the same bytes,
+//! base64-encoded, in a self-describing JSON object, so proofs can be stored
+//! in databases and sent over text-only channels:
+//!
+//! ```json
+//! {
+//!   "format": "djinn-presentation/1",
+//!   "presentation": "<base64 of the bincode presentation>",
+//!   "sha256": "<hex SHA-256 of the bincode presentation>",
+//!   "server": "api.the-odds-api.com",
+//!   "response_status": 200,
+//!   "prover_version": "0.1.0",
+//!   "created_at": "2025-06-01T18:00:00Z"
+//! }
+//! ```
+//!
+//! Only the presentation is attested. The other fields are unattested
+//! claims for indexing; verifiers report what the presentation itself
+//! discloses. Readers tell an envelope from raw bincode by its first
+//! non-whitespace byte, `{`, which no bincode presentation starts with, and
+//! ignore fields they don't know. A bare base64 presentation, without the
+//! envelope around it, is read too.
+//!
+//! Envelope errors carry an [`ErrorCode`]: `PRESENTATION_TOO_LARGE` for an
+//! oversized presentation and `MALFORMED_PRESENTATION` otherwise.
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::verify::error::{ErrorCode, VerifyError};
+
+/// The `format` of envelopes this version writes and reads.
+pub const ENVELOPE_FORMAT: &str = "djinn-presentation/1";
+
+/// Room for the envelope's fields besides the encoded presentation.
+const MAX_ENVELOPE_OVERHEAD: u64 = 64 * 1024;
+
+/// A presentation in a JSON envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Always [`ENVELOPE_FORMAT`].
+    pub format: String,
+    /// Standard base64 of the bincode presentation.
+    pub presentation: String,
+    /// Hex SHA-256 of the bincode presentation.
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prover_version: Option<String>,
+    /// RFC 3339 time the envelope was written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl Envelope {
+    /// Wraps a bincode presentation, stamped with this crate's version and
+    /// the current time.
+    pub fn new(presentation: &[u8]) -> Self {
+        Self {
+            format: ENVELOPE_FORMAT.to_string(),
+            presentation: base64::engine::general_purpose::STANDARD.encode(presentation),
+            sha256: hex::encode(Sha256::digest(presentation)),
+            server: None,
+            response_status: None,
+            prover_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            created_at: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        }
+    }
+
+    /// Encodes the envelope as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    /// Parses an envelope, rejecting other formats.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let envelope: Self = serde_json::from_slice(bytes)
+            .map_err(|e| malformed(format!("invalid presentation envelope: {e}")))?;
+        if envelope.format != ENVELOPE_FORMAT {
+            return Err(malformed(format!(
+                "unsupported presentation envelope format {:?}; expected {ENVELOPE_FORMAT}",
+                envelope.format
+            )));
+        }
+        Ok(envelope)
+    }
+
+    /// Decodes the bincode presentation, refusing one over `max_bytes` before
+    /// decoding it and checking it against the envelope's digest.
+    pub fn decode(&self, max_bytes: u64) -> Result<Vec<u8>> {
+        let bytes = decode_base64(self.presentation.as_bytes(), max_bytes)?;
+        let digest = hex::encode(Sha256::digest(&bytes));
+        if !digest.eq_ignore_ascii_case(&self.sha256) {
+            return Err(malformed(format!(
+                "envelope sha256 {} doesn't match its presentation ({digest})",
+                self.sha256
+            )));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Decodes a base64 presentation, refusing one over `max_bytes` before
+/// decoding it.
+fn decode_base64(text: &[u8], max_bytes: u64) -> Result<Vec<u8>> {
+    let decoded_len = text.len() as u64 / 4 * 3;
+    if decoded_len > max_bytes + 2 {
+        return Err(VerifyError::new(
+            ErrorCode::PresentationTooLarge,
+            format!(
+                "base64 presentation is about {decoded_len} bytes, \
+                 over the {max_bytes} byte limit"
+            ),
+        )
+        .into());
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(text)
+        .map_err(|e| malformed(format!("presentation is not valid base64: {e}")))
+}
+
+fn malformed(message: String) -> anyhow::Error {
+    VerifyError::new(ErrorCode::MalformedPresentation, message).into()
+}
+
+/// Returns true if `bytes` look like an envelope rather than raw bincode.
+pub fn is_envelope(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .is_some_and(|&b| b == b'{')
+}
+
+/// Returns true if `bytes` are base64 text rather than raw bincode: the
+/// standard alphabet, padding and whitespace only. Serialized presentations
+/// hold zero bytes in their fixed-width integers, so even a short prefix of
+/// raw bincode fails this.
+pub fn is_base64(bytes: &[u8]) -> bool {
+    !bytes.is_empty()
+        && bytes.iter().all(|&b| {
+            b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=') || b.is_ascii_whitespace()
+        })
+}
+
+/// Returns the bincode presentation in `bytes`: unwrapped from an envelope,
+/// decoded from base64 text, or `bytes` itself if they are neither.
+pub fn unwrap_presentation(bytes: &[u8], max_bytes: u64) -> Result<Cow<'_, [u8]>> {
+    if is_envelope(bytes) {
+        return Ok(Cow::Owned(Envelope::from_slice(bytes)?.decode(max_bytes)?));
+    }
+    if is_base64(bytes) {
+        let text: Vec<u8> = bytes
+            .iter()
+            .copied()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        return Ok(Cow::Owned(decode_base64(&text, max_bytes)?));
+    }
+    Ok(Cow::Borrowed(bytes))
+}
+
+/// Largest envelope file worth reading for a presentation of up to
+/// `max_bytes`.
+pub fn max_envelope_len(max_bytes: u64) -> u64 {
+    max_bytes.div_ceil(3) * 4 + MAX_ENVELOPE_OVERHEAD
+}
@@ -0,0 +1,127 @@
+//! Client side of `Expect: 100-continue`.
+//!
+//! hyper's client skips interim 1xx responses, so the `100 Continue` is
+//! observed on the connection instead: [`ContinueWatch`] wraps the TLS stream
+//! and signals once the server answers the request head, and
+//! [`continue_body`] holds the request body back until then. If the server
+//! answers with a final status instead, the body is never sent and the
+//! response is returned as usual. Servers that ignore the expectation are
+//! handled by sending the body anyway after a timeout.
+
+use std::convert::Infallible;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt as _, StreamBody};
+use hyper::body::{Bytes, Frame};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::http::{is_informational, status_code};
+
+/// Longest response head scanned for the server's answer.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Wraps a connection and reports whether the server answered the request
+/// head with `100 Continue` (`true`) or with a final response (`false`).
+pub struct ContinueWatch<T> {
+    inner: T,
+    head: Vec<u8>,
+    signal: Option<oneshot::Sender<bool>>,
+}
+
+impl<T> ContinueWatch<T> {
+    /// Wraps `inner`, returning the receiver for the server's answer.
+    pub fn new(inner: T) -> (Self, oneshot::Receiver<bool>) {
+        let (tx, rx) = oneshot::channel();
+        let watch = Self {
+            inner,
+            head: Vec::new(),
+            signal: Some(tx),
+        };
+        (watch, rx)
+    }
+
+    fn scan(&mut self, data: &[u8]) {
+        if self.signal.is_none() {
+            return;
+        }
+        self.head.extend_from_slice(data);
+        while let Some(end) = self.head.windows(4).position(|w| w == b"\r\n\r\n") {
+            let code = status_code(&String::from_utf8_lossy(&self.head[..end]));
+            match code {
+                Some(100) => return self.send(true),
+                // Other interim responses (e.g. 103 Early Hints) don't answer
+                // the expectation.
+                Some(code) if is_informational(code) => {
+                    self.head.drain(..end + 4);
+                }
+                _ => return self.send(false),
+            }
+        }
+        if self.head.len() > MAX_HEAD {
+            self.send(false);
+        }
+    }
+
+    fn send(&mut self, proceed: bool) {
+        if let Some(signal) = self.signal.take() {
+            let _ = signal.send(proceed);
+        }
+        self.head = Vec::new();
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ContinueWatch<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            self.scan(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for ContinueWatch<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A request body that waits for the server's answer before sending `body`.
+///
+/// On `100 Continue`, or if no answer arrives within `timeout`, the body is
+/// sent. On a final response it is held back for good, so the rejected
+/// upload doesn't use up the send budget.
+pub fn continue_body(
+    body: Bytes,
+    answer: oneshot::Receiver<bool>,
+    timeout: Duration,
+) -> UnsyncBoxBody<Bytes, Infallible> {
+    let frame = async move {
+        if let Ok(Ok(false)) = tokio::time::timeout(timeout, answer).await {
+            futures::future::pending::<()>().await;
+        }
+        Ok::<_, Infallible>(Frame::data(body))
+    };
+    StreamBody::new(futures::stream::once(frame)).boxed_unsync()
+}
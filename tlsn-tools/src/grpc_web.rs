@@ -0,0 +1,134 @@
+//! gRPC-Web framing over HTTP/1.1.
+//!
+//! A gRPC-Web body is a sequence of frames: a flag byte, a big-endian `u32`
+//! length and that many bytes of payload. Flag bit `0x80` marks a trailers
+//! frame, whose payload is HTTP/1-style `name: value` lines carrying
+//! `grpc-status` and `grpc-message`. In the `-text` variant the whole body is
+//! base64-encoded.
+//!
+//! Only unary calls are supported: one request message, and a response that
+//! is read to completion before it is decoded. Compressed frames (flag bit
+//! `0x01`) are reported as-is rather than decompressed.
+//!
+//! Only built with the `grpc-web` feature.
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+
+/// Content type of binary gRPC-Web bodies with protobuf messages.
+pub const CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// Content type of base64-encoded gRPC-Web bodies.
+pub const CONTENT_TYPE_TEXT: &str = "application/grpc-web-text+proto";
+
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// Frames one request message, base64-encoding it for the `-text` variant.
+pub fn encode_request(message: &[u8], text: bool) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.push(0);
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+    if text {
+        base64::engine::general_purpose::STANDARD
+            .encode(frame)
+            .into_bytes()
+    } else {
+        frame
+    }
+}
+
+/// Returns true if `content_type` is a base64 (`-text`) gRPC-Web type.
+pub fn is_text(content_type: &str) -> bool {
+    content_type
+        .trim()
+        .to_ascii_lowercase()
+        .starts_with("application/grpc-web-text")
+}
+
+/// A decoded gRPC-Web response body.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcWebResponse {
+    /// Message payloads, in order.
+    pub messages: Vec<Vec<u8>>,
+    /// Trailers, with lowercase names.
+    pub trailers: Vec<(String, String)>,
+}
+
+impl GrpcWebResponse {
+    /// Decodes a response body. `text` selects the base64 variant.
+    pub fn decode(body: &[u8], text: bool) -> Result<Self> {
+        let decoded;
+        let mut rest = if text {
+            // Each frame may be base64-encoded (and padded) separately, so
+            // decode in padded chunks rather than as one string.
+            decoded = decode_text(body)?;
+            decoded.as_slice()
+        } else {
+            body
+        };
+
+        let mut response = Self::default();
+        while !rest.is_empty() {
+            if rest.len() < 5 {
+                anyhow::bail!("truncated gRPC-Web frame header");
+            }
+            let flag = rest[0];
+            let len = u32::from_be_bytes(rest[1..5].try_into().expect("4 bytes")) as usize;
+            let payload = rest
+                .get(5..5 + len)
+                .context("gRPC-Web frame is shorter than its declared length")?;
+            rest = &rest[5 + len..];
+
+            if flag & TRAILERS_FLAG != 0 {
+                let trailers = std::str::from_utf8(payload).context("trailers are not UTF-8")?;
+                response
+                    .trailers
+                    .extend(trailers.split("\r\n").filter_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                    }));
+            } else {
+                response.messages.push(payload.to_vec());
+            }
+        }
+        Ok(response)
+    }
+
+    /// The `grpc-status` trailer, if present.
+    pub fn status(&self) -> Option<&str> {
+        self.trailer("grpc-status")
+    }
+
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn decode_text(body: &[u8]) -> Result<Vec<u8>> {
+    let body: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let mut out = Vec::new();
+    for chunk in body.split_inclusive(|&b| b == b'=') {
+        // Padding ends a chunk; a run of `=` leaves empty remainders.
+        if chunk.iter().all(|&b| b == b'=') {
+            continue;
+        }
+        let mut chunk = chunk.to_vec();
+        while chunk.len() % 4 != 0 {
+            chunk.push(b'=');
+        }
+        out.extend(
+            base64::engine::general_purpose::STANDARD
+                .decode(&chunk)
+                .context("invalid base64 in gRPC-Web text body")?,
+        );
+    }
+    Ok(out)
+}
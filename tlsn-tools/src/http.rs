@@ -0,0 +1,460 @@
+//! Helpers for reading HTTP structure out of a disclosed transcript.
+//!
+//! The verifier fills redacted bytes with `X`, so these helpers work on
+//! partially revealed data as long as the parts they look at were disclosed.
+
+use std::io::Read as _;
+use std::ops::Range;
+
+use anyhow::Result;
+
+/// Splits an HTTP message into its head (start line and headers) and body.
+///
+/// Returns `None` for the body when the head terminator was not found.
+pub fn split_message(data: &str) -> (&str, Option<&str>) {
+    match data.split_once("\r\n\r\n") {
+        Some((head, body)) => (head, Some(body)),
+        None => (data, None),
+    }
+}
+
+/// Parses the status code from the status line of a response head.
+pub fn status_code(head: &str) -> Option<u16> {
+    let status_line = head.lines().next()?;
+    let mut parts = status_line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+/// Returns the value of the first header named `name` (case-insensitive).
+///
+/// Redacted header lines don't contain a `:` separator and are skipped.
+pub fn header_value<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    header_value_range(head, name).map(|range| &head[range])
+}
+
+/// Returns the byte range of the first `name` header's value within `head`,
+/// excluding surrounding whitespace.
+pub fn header_value_range(head: &str, name: &str) -> Option<Range<usize>> {
+    header_ranges(head)
+        .into_iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, range)| range)
+}
+
+/// Returns every header in `head` as its trimmed name and the byte range of
+/// its value (excluding surrounding whitespace), in order.
+pub fn header_ranges(head: &str) -> Vec<(&str, Range<usize>)> {
+    header_spans(head)
+        .into_iter()
+        .map(|(name, value)| (&head[name], value))
+        .collect()
+}
+
+/// Like [`header_ranges`], with the byte range of each trimmed name.
+fn header_spans(head: &str) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut headers = Vec::new();
+    let mut line_start = 0;
+    for (i, line) in head.split("\r\n").enumerate() {
+        let start = line_start;
+        line_start += line.len() + 2;
+        if i == 0 {
+            continue;
+        }
+        let Some((n, v)) = line.split_once(':') else {
+            continue;
+        };
+        let name_start = start + (n.len() - n.trim_start().len());
+        let value_start = start + n.len() + 1 + (v.len() - v.trim_start().len());
+        headers.push((
+            name_start..name_start + n.trim().len(),
+            value_start..value_start + v.trim().len(),
+        ));
+    }
+    headers
+}
+
+/// Groups the headers in `head` by lowercase name, in order of first
+/// appearance, keeping every instance of a repeated header.
+///
+/// `revealed` is asked about byte ranges of `head`. Headers whose name wasn't
+/// disclosed are left out; a value that wasn't disclosed is `None`.
+pub fn grouped_headers(
+    head: &str,
+    revealed: impl Fn(&Range<usize>) -> bool,
+) -> Vec<(String, Vec<Option<&str>>)> {
+    let mut groups: Vec<(String, Vec<Option<&str>>)> = Vec::new();
+    for (name, value) in header_spans(head) {
+        if !revealed(&name) {
+            continue;
+        }
+        let value = revealed(&value).then(|| &head[value]);
+        let name = head[name].to_ascii_lowercase();
+        match groups.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value),
+            None => groups.push((name, vec![value])),
+        }
+    }
+    groups
+}
+
+/// Returns the method from the request line of a request head.
+pub fn request_method(head: &str) -> Option<&str> {
+    head.lines().next()?.split_whitespace().next()
+}
+
+/// Returns the target (path and query) from the request line of a request head.
+pub fn request_target(head: &str) -> Option<&str> {
+    head.lines().next()?.split_whitespace().nth(1)
+}
+
+/// Returns the byte range of the query string (after `?`, before any `#`)
+/// of the request target in a request head.
+pub fn query_range(head: &str) -> Option<Range<usize>> {
+    let line = head.lines().next()?;
+    let target = request_target(head)?;
+    let target_start = line.find(target)?;
+    let query_start = target_start + target.find('?')? + 1;
+    let query_end = target
+        .find('#')
+        .map_or(target_start + target.len(), |i| target_start + i);
+    Some(query_start..query_end.max(query_start))
+}
+
+/// Returns true for 3xx redirection status codes.
+pub fn is_redirect(code: u16) -> bool {
+    (300..400).contains(&code)
+}
+
+/// Resolves a `Location` header value against the URL of the request it
+/// answered, so relative (`/next`, `next`, `//host/next`) and absolute values
+/// compare equal when they point to the same place.
+pub fn resolve_location(request_url: &str, location: &str) -> Option<String> {
+    let base = url::Url::parse(request_url).ok()?;
+    base.join(location.trim()).ok().map(String::from)
+}
+
+/// Locates the final response in raw received bytes, skipping any leading 1xx
+/// informational responses (`100 Continue`, `103 Early Hints`).
+///
+/// Returns the offsets where the final response's head starts and where its
+/// body starts, or `None` if no complete head was found.
+pub fn final_response_bounds(data: &[u8]) -> Option<(usize, usize)> {
+    let mut head_start = 0;
+    loop {
+        let rest = &data[head_start..];
+        let head_len = rest.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let body_start = head_start + head_len + 4;
+        match status_code(&String::from_utf8_lossy(&rest[..head_len])) {
+            Some(code) if is_informational(code) => head_start = body_start,
+            _ => return Some((head_start, body_start)),
+        }
+    }
+}
+
+/// Returns true for 1xx informational status codes.
+pub fn is_informational(code: u16) -> bool {
+    (100..200).contains(&code)
+}
+
+/// Returns true for statuses whose responses never have a body, whatever
+/// their `Content-Length` says: `204 No Content` and `304 Not Modified`.
+pub fn is_bodiless_status(code: u16) -> bool {
+    code == 204 || code == 304
+}
+
+/// Returns true if a parsed response is a 1xx informational one.
+pub fn is_interim(response: &tlsn_formats::http::Response) -> bool {
+    response
+        .status
+        .code
+        .as_str()
+        .parse::<u16>()
+        .is_ok_and(is_informational)
+}
+
+/// Returns the final response of a parsed transcript, skipping 1xx
+/// informational responses that precede it.
+pub fn final_response(
+    responses: &[tlsn_formats::http::Response],
+) -> Option<&tlsn_formats::http::Response> {
+    responses.iter().find(|resp| !is_interim(resp))
+}
+
+/// Splits one direction of a transcript into the byte ranges of the HTTP
+/// messages sent back to back on the connection, in order. 1xx interim
+/// responses are kept with the response that follows them.
+///
+/// Bodies are framed by `Content-Length`; 204 and 304 responses have none
+/// whatever it says. A response without a readable one (chunked, or with the
+/// header redacted) runs to the end of `data`, as does a message cut short.
+pub fn message_ranges(data: &[u8]) -> Vec<Range<usize>> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let Some((head_start, body_start)) = final_response_bounds(&data[start..]) else {
+            messages.push(start..data.len());
+            break;
+        };
+        let head = String::from_utf8_lossy(&data[start + head_start..start + body_start]);
+        let end = match header_value(&head, "content-length").map(|len| len.parse::<usize>()) {
+            _ if status_code(&head).is_some_and(is_bodiless_status) => start + body_start,
+            Some(Ok(len)) => (start + body_start + len).min(data.len()),
+            Some(Err(_)) => data.len(),
+            // A request without one has no body; a response is read to the
+            // end of the connection.
+            None if status_code(&head).is_none() => start + body_start,
+            None => data.len(),
+        };
+        messages.push(start..end);
+        start = end;
+    }
+    messages
+}
+
+/// Returns the size of the body of `message`, one request or response (after
+/// any interim responses), as transmitted: chunked framing included, and
+/// compressed bytes as sent. `None` if the end of the head can't be found,
+/// e.g. because it is redacted.
+pub fn body_len(message: &[u8]) -> Option<usize> {
+    let (_, body_start) = final_response_bounds(message)?;
+    Some(message.len() - body_start)
+}
+
+/// Checks the final response in `data` against its declared `Content-Length`.
+///
+/// Returns `(expected, received)` body sizes when fewer bytes were received
+/// than declared, e.g. because the connection dropped mid-body.
+pub fn truncated_body(data: &[u8]) -> Option<(usize, usize)> {
+    let (head_start, body_start) = final_response_bounds(data)?;
+    let head = String::from_utf8_lossy(&data[head_start..body_start]);
+    if status_code(&head).is_some_and(is_bodiless_status) {
+        return None;
+    }
+    let expected: usize = header_value(&head, "content-length")?.parse().ok()?;
+    let received = data.len() - body_start;
+    (received < expected).then_some((expected, received))
+}
+
+/// Content type of URL-encoded form bodies.
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Splits a URL-encoded form body into `(name, value)` byte ranges, one per
+/// `&`-separated field. A field without `=` has an empty value range at its
+/// end.
+pub fn form_field_ranges(body: &[u8]) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for part in body.split(|&b| b == b'&') {
+        let end = start + part.len();
+        if !part.is_empty() {
+            fields.push(match part.iter().position(|&b| b == b'=') {
+                Some(eq) => (start..start + eq, start + eq + 1..end),
+                None => (start..end, end..end),
+            });
+        }
+        start = end + 1;
+    }
+    fields
+}
+
+/// Groups the fields of a disclosed form body by decoded name, in order of
+/// first appearance. A field whose name isn't disclosed is skipped; an
+/// undisclosed value is `None`.
+pub fn grouped_form_fields(
+    body: &[u8],
+    revealed: impl Fn(&Range<usize>) -> bool,
+) -> Vec<(String, Vec<Option<String>>)> {
+    let decode = |bytes: &[u8]| -> String {
+        url::form_urlencoded::parse(bytes)
+            .next()
+            .map(|(name, _)| name.into_owned())
+            .unwrap_or_default()
+    };
+    let mut groups: Vec<(String, Vec<Option<String>>)> = Vec::new();
+    for (name, value) in form_field_ranges(body) {
+        if !revealed(&name) {
+            continue;
+        }
+        let value = revealed(&value).then(|| decode(&body[value]));
+        let name = decode(&body[name]);
+        match groups.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.push(value),
+            None => groups.push((name, vec![value])),
+        }
+    }
+    groups
+}
+
+/// Upper bound on an inflated body, against decompression bombs.
+pub const MAX_DECODED_BODY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Undoes a `Content-Encoding` (e.g. `gzip` or `br, gzip`), applying the
+/// listed codings in reverse order. Supports `gzip`, `x-gzip`, `deflate`
+/// (zlib-wrapped or raw), `br` and `identity`.
+pub fn decode_content(body: &[u8], encoding: &str) -> Result<Vec<u8>> {
+    let mut data = body.to_vec();
+    for coding in encoding
+        .split(',')
+        .rev()
+        .map(|c| c.trim().to_ascii_lowercase())
+    {
+        let limit = MAX_DECODED_BODY_BYTES + 1;
+        let mut out = Vec::new();
+        match coding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => {
+                flate2::read::MultiGzDecoder::new(data.as_slice())
+                    .take(limit)
+                    .read_to_end(&mut out)?;
+            }
+            "deflate" => {
+                // Servers disagree on whether deflate is zlib-wrapped.
+                if flate2::read::ZlibDecoder::new(data.as_slice())
+                    .take(limit)
+                    .read_to_end(&mut out)
+                    .is_err()
+                {
+                    out.clear();
+                    flate2::read::DeflateDecoder::new(data.as_slice())
+                        .take(limit)
+                        .read_to_end(&mut out)?;
+                }
+            }
+            "br" => {
+                brotli::Decompressor::new(data.as_slice(), 4096)
+                    .take(limit)
+                    .read_to_end(&mut out)?;
+            }
+            other => anyhow::bail!("unsupported content coding: {other}"),
+        }
+        if out.len() as u64 > MAX_DECODED_BODY_BYTES {
+            anyhow::bail!("decoded body is over the {MAX_DECODED_BODY_BYTES} byte limit");
+        }
+        data = out;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+    const EARLY_HINTS: &[u8] = b"HTTP/1.1 103 Early Hints\r\nLink: </app.css>; rel=preload\r\n\r\n";
+
+    fn concat(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn final_response_skips_100_continue() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let data = concat(&[CONTINUE, ok]);
+        let (head_start, body_start) = final_response_bounds(&data).unwrap();
+        assert_eq!(head_start, CONTINUE.len());
+        assert_eq!(&data[body_start..], b"ok");
+        assert_eq!(message_ranges(&data), vec![0..data.len()]);
+        assert_eq!(body_len(&data), Some(2));
+    }
+
+    #[test]
+    fn final_response_skips_103_before_204() {
+        let no_content = b"HTTP/1.1 204 No Content\r\nContent-Length: 10\r\n\r\n";
+        let next = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let data = concat(&[EARLY_HINTS, no_content, next]);
+        let (head_start, body_start) = final_response_bounds(&data).unwrap();
+        assert_eq!(head_start, EARLY_HINTS.len());
+        // A 204 has no body whatever its Content-Length says, so the next
+        // response starts right after its head.
+        assert_eq!(body_start, EARLY_HINTS.len() + no_content.len());
+        assert_eq!(
+            message_ranges(&data),
+            vec![0..body_start, body_start..data.len()]
+        );
+        assert_eq!(truncated_body(&data[..body_start]), None);
+    }
+
+    #[test]
+    fn truncated_head_has_no_bounds() {
+        let cut = b"HTTP/1.1 200 OK\r\nContent-Len";
+        assert_eq!(final_response_bounds(cut), None);
+        assert_eq!(message_ranges(cut), vec![0..cut.len()]);
+        assert_eq!(body_len(cut), None);
+
+        // An interim response alone doesn't count as the final one.
+        let data = concat(&[CONTINUE, cut]);
+        assert_eq!(final_response_bounds(&data), None);
+        assert_eq!(final_response_bounds(CONTINUE), None);
+    }
+
+    #[test]
+    fn message_ranges_split_pipelined_messages() {
+        let first = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc";
+        let second = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nde";
+        let data = concat(&[first, second]);
+        // The second body is cut short and runs to the end of the data.
+        assert_eq!(
+            message_ranges(&data),
+            vec![0..first.len(), first.len()..data.len()]
+        );
+        assert_eq!(truncated_body(second), Some((5, 2)));
+    }
+
+    /// Reveals everything except the byte ranges in `hidden`.
+    fn revealed_except(hidden: &[Range<usize>]) -> impl Fn(&Range<usize>) -> bool + '_ {
+        move |range| {
+            !hidden
+                .iter()
+                .any(|h| h.start < range.end && range.start < h.end)
+        }
+    }
+
+    #[test]
+    fn grouped_headers_keep_repeated_headers_in_order() {
+        let head = "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nVary: Accept\r\nset-cookie: b=2\r\n\
+                    SET-COOKIE:c=3";
+        assert_eq!(
+            grouped_headers(head, |_| true),
+            vec![
+                (
+                    "set-cookie".to_string(),
+                    vec![Some("a=1"), Some("b=2"), Some("c=3")]
+                ),
+                ("vary".to_string(), vec![Some("Accept")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_headers_report_redacted_names_and_values() {
+        let head = "GET / HTTP/1.1\r\nHost: example.com\r\nAuthorization: Bearer t\r\n\
+                    X-Key: k\r\nX-Key: v";
+        let auth = header_value_range(head, "authorization").unwrap();
+        let key_line = head.find("X-Key").unwrap();
+        // The authorization value and the first X-Key name are redacted.
+        let hidden = [auth, key_line..key_line + 5];
+        assert_eq!(
+            grouped_headers(head, revealed_except(&hidden)),
+            vec![
+                ("host".to_string(), vec![Some("example.com")]),
+                ("authorization".to_string(), vec![None]),
+                ("x-key".to_string(), vec![Some("v")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn header_names_are_located_on_their_own_line() {
+        // A value that contains its own header name, and padding around the
+        // name: the name range must still cover the name itself.
+        let head = "HTTP/1.1 200 OK\r\n Etag : \"Etag\"";
+        let (name, value) = header_spans(head)[0].clone();
+        assert_eq!(&head[name.clone()], "Etag");
+        assert_eq!(&head[value], "\"Etag\"");
+        let hidden = [name];
+        assert!(grouped_headers(head, revealed_except(&hidden)).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! Structural checks on presentations, run before cryptographic verification.
+//!
+//! `Presentation::verify` is comparatively expensive and reports malformed
+//! input as deep verification failures. These checks are cheap and reject
+//! inputs that can't possibly verify with a clear structural error.
+
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use bincode::Options;
+use tlsn::attestation::presentation::Presentation;
+
+use crate::{envelope, KNOWN_NOTARY_KEY_ALGS};
+
+pub use self::layout::{DirectionLayout, Layout};
+
+mod layout;
+
+/// Deserializes a bincode presentation, rejecting trailing bytes. A
+/// presentation in a JSON envelope is unwrapped first.
+///
+/// Uses the same encoding as `bincode::serialize`, so it reads everything the
+/// prover writes. Decoding fails once it would read or allocate more than
+/// `limit` bytes, so a length prefix claiming a huge collection is rejected
+/// instead of allocated.
+pub fn deserialize_presentation(bytes: &[u8], limit: u64) -> Result<Presentation> {
+    let bytes = envelope::unwrap_presentation(bytes, limit)?;
+    bincode_options(limit)
+        .deserialize(&bytes)
+        .context("failed to deserialize presentation")
+}
+
+/// Like [`deserialize_presentation`], but decodes from `reader` as it reads,
+/// so the serialized presentation is never held in memory in one piece. Used
+/// for presentations arriving in another encoding, e.g. through a base64
+/// decoder.
+///
+/// The limit covers the bytes read from `reader`; anything left after the
+/// presentation is rejected.
+pub fn deserialize_presentation_from(mut reader: impl Read, limit: u64) -> Result<Presentation> {
+    // `reject_trailing_bytes` only knows how to check slices; readers are
+    // checked by hand below.
+    let presentation: Presentation = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+        .deserialize_from(&mut reader)
+        .context("failed to deserialize presentation")?;
+    let trailing = reader
+        .read(&mut [0u8])
+        .context("failed to deserialize presentation")?;
+    if trailing != 0 {
+        anyhow::bail!("failed to deserialize presentation: trailing bytes after the presentation");
+    }
+    Ok(presentation)
+}
+
+/// Bincode options matching `bincode::serialize`, with trailing bytes
+/// rejected and decoding bounded by `limit` bytes.
+pub fn bincode_options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .with_limit(limit)
+}
+
+/// Checks that a presentation is internally consistent enough to verify, and
+/// returns what it declares about itself; see [`Layout`].
+///
+/// Besides the notary key, this checks that both the server identity and the
+/// transcript proof are present, that the revealed transcript's length
+/// matches the attested one, and that its revealed ranges are in bounds and
+/// match the revealed bytes it carries.
+pub fn check_structure(presentation: &Presentation) -> Result<Layout> {
+    let key = presentation.verifying_key();
+    let alg = key.alg.to_string();
+    if key.data.is_empty() {
+        anyhow::bail!("notary verifying key is empty");
+    }
+    // SEC1-encoded EC public keys: 33 bytes compressed, 65 uncompressed.
+    if KNOWN_NOTARY_KEY_ALGS.contains(&alg.as_str()) && ![33, 65].contains(&key.data.len()) {
+        anyhow::bail!(
+            "notary verifying key has invalid length {} for {alg}",
+            key.data.len()
+        );
+    }
+
+    let layout = Layout::of(presentation).context("presentation is not serializable")?;
+    layout.check()?;
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bincode length prefix claiming `len` items, followed by a few bytes.
+    fn claiming(len: u64) -> Vec<u8> {
+        let mut bytes = len.to_le_bytes().to_vec();
+        bytes.extend_from_slice(b"abc");
+        bytes
+    }
+
+    #[test]
+    fn huge_length_prefix_is_rejected_before_allocating() {
+        // Allocating u64::MAX bytes would abort the test process, so getting
+        // an error back shows the limit is checked first.
+        let err = bincode_options(1024)
+            .deserialize::<String>(&claiming(u64::MAX))
+            .unwrap_err();
+        assert!(matches!(*err, bincode::ErrorKind::SizeLimit), "{err}");
+
+        let err = bincode_options(1024)
+            .deserialize::<Vec<u8>>(&claiming(u64::MAX))
+            .unwrap_err();
+        assert!(
+            matches!(
+                *err,
+                bincode::ErrorKind::SizeLimit | bincode::ErrorKind::Io(_)
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn length_prefix_over_the_limit_is_rejected() {
+        // Within the data the prefix claims, but over the limit.
+        let mut bytes = 2000u64.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[b'a'; 2000]);
+        let err = bincode_options(1024)
+            .deserialize::<String>(&bytes)
+            .unwrap_err();
+        assert!(matches!(*err, bincode::ErrorKind::SizeLimit), "{err}");
+        assert!(bincode_options(4096).deserialize::<String>(&bytes).is_ok());
+    }
+
+    #[test]
+    fn malicious_presentation_is_rejected() {
+        assert!(deserialize_presentation(&claiming(u64::MAX), 1024).is_err());
+        assert!(deserialize_presentation_from(&claiming(u64::MAX)[..], 1024).is_err());
+    }
+}
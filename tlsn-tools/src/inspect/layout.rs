@@ -0,0 +1,645 @@
+//! Reads the parts of a presentation that [`check_structure`] checks.
+//!
+//! Presentation fields are private to tlsn, so they are read while the
+//! presentation serializes itself into [`Walker`], a serializer that keeps
+//! track of the field path and records only the values below. Nothing is
+//! buffered: transcript bytes are counted, not copied.
+//!
+//! - whether the server identity and transcript proofs are present;
+//! - the attested connection time and transcript length;
+//! - the revealed transcript: its declared total length, revealed byte
+//!   ranges and revealed bytes, per direction.
+//!
+//! [`check_structure`]: super::check_structure
+
+use std::fmt;
+use std::ops::Range;
+
+use serde::ser::{self, Serialize};
+
+/// What a presentation declares about itself, unverified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub identity: bool,
+    pub transcript: bool,
+    /// Attested connection time, UNIX seconds.
+    pub connection_time: Option<u64>,
+    pub sent: DirectionLayout,
+    pub received: DirectionLayout,
+}
+
+/// One direction of a [`Layout`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectionLayout {
+    /// Transcript length in the attestation.
+    pub attested_len: Option<u64>,
+    /// Transcript length the revealed transcript declares.
+    pub total_len: Option<u64>,
+    /// Revealed byte ranges.
+    pub revealed: Vec<Range<u64>>,
+    /// Number of revealed bytes carried.
+    pub revealed_bytes: Option<u64>,
+}
+
+impl Layout {
+    /// Reads the layout of `value`, normally a presentation.
+    pub fn of(value: &impl Serialize) -> Result<Self, WalkError> {
+        let mut walker = Walker::default();
+        value.serialize(&mut walker)?;
+        Ok(walker.layout)
+    }
+
+    /// Checks that the proofs verification needs are present and that the
+    /// declared lengths and ranges agree with each other.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if !self.identity {
+            anyhow::bail!("presentation has no server identity proof");
+        }
+        if !self.transcript {
+            anyhow::bail!("presentation has no transcript proof");
+        }
+        self.sent.check("sent")?;
+        self.received.check("received")
+    }
+}
+
+impl DirectionLayout {
+    fn check(&self, direction: &str) -> anyhow::Result<()> {
+        if let (Some(total), Some(attested)) = (self.total_len, self.attested_len) {
+            if total != attested {
+                anyhow::bail!(
+                    "transcript proof declares {total} {direction} bytes, but the attestation \
+                     covers {attested}"
+                );
+            }
+        }
+        let mut end = 0;
+        for range in &self.revealed {
+            if range.start < end || range.start >= range.end {
+                anyhow::bail!(
+                    "revealed {direction} ranges are empty, overlapping or out of order at \
+                     {range:?}"
+                );
+            }
+            end = range.end;
+        }
+        if let Some(total) = self.total_len.or(self.attested_len) {
+            if end > total {
+                anyhow::bail!(
+                    "revealed {direction} range ends at {end}, past the transcript's {total} bytes"
+                );
+            }
+        }
+        let declared: u64 = self.revealed.iter().map(|r| r.end - r.start).sum();
+        if let Some(carried) = self.revealed_bytes {
+            if carried != declared {
+                anyhow::bail!(
+                    "transcript proof carries {carried} revealed {direction} bytes, but its \
+                     ranges cover {declared}"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A presentation that failed to serialize.
+#[derive(Debug)]
+pub struct WalkError(String);
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for WalkError {}
+
+impl ser::Error for WalkError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Field names below `transcript` (the transcript proof) in a presentation.
+const REVEALED_BYTES: [&str; 2] = ["sent_authed", "received_authed"];
+const REVEALED_RANGES: [&str; 2] = ["sent_idx", "recv_idx"];
+const TOTAL_LEN: [&str; 2] = ["sent_total", "recv_total"];
+/// Field names below `transcript_length` in the attestation.
+const ATTESTED_LEN: [&str; 2] = ["sent", "received"];
+
+#[derive(Default)]
+struct Walker {
+    /// Names of the struct fields being serialized, outermost first. Map
+    /// values push `""`, so nothing under a map matches.
+    path: Vec<&'static str>,
+    /// Start of the range whose end is expected next.
+    range_start: Option<u64>,
+    layout: Layout,
+}
+
+impl Walker {
+    fn direction(&mut self, index: usize) -> &mut DirectionLayout {
+        match index {
+            0 => &mut self.layout.sent,
+            _ => &mut self.layout.received,
+        }
+    }
+
+    fn in_transcript_proof(&self) -> bool {
+        self.path.first() == Some(&"transcript")
+    }
+
+    fn record_option(&mut self, present: bool) {
+        match self.path.as_slice() {
+            ["identity"] => self.layout.identity = present,
+            ["transcript"] => self.layout.transcript = present,
+            _ => {}
+        }
+    }
+
+    fn record_int(&mut self, value: u64) {
+        let Some(&last) = self.path.last() else {
+            return;
+        };
+        if self.in_transcript_proof() {
+            if let Some(i) = TOTAL_LEN.iter().position(|&n| n == last) {
+                self.direction(i).total_len = Some(value);
+                return;
+            }
+            let mut ancestors = self.path.iter().rev().skip(1);
+            if let Some(i) = ancestors.find_map(|n| REVEALED_RANGES.iter().position(|r| r == n)) {
+                match last {
+                    "start" => self.range_start = Some(value),
+                    "end" => {
+                        if let Some(start) = self.range_start.take() {
+                            self.direction(i).revealed.push(start..value);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        } else if self.path.contains(&"connection_info") {
+            if last == "time" {
+                self.layout.connection_time = Some(value);
+            } else if let Some(i) = ATTESTED_LEN.iter().position(|&n| n == last) {
+                if self.path.contains(&"transcript_length") {
+                    self.direction(i).attested_len = Some(value);
+                }
+            }
+        }
+    }
+
+    fn record_len(&mut self, len: Option<usize>) {
+        let (Some(&last), Some(len)) = (self.path.last(), len) else {
+            return;
+        };
+        if self.in_transcript_proof() {
+            if let Some(i) = REVEALED_BYTES.iter().position(|&n| n == last) {
+                self.direction(i).revealed_bytes = Some(len as u64);
+            }
+        }
+    }
+
+    fn field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), WalkError> {
+        self.path.push(name);
+        let result = value.serialize(&mut *self);
+        self.path.pop();
+        result
+    }
+}
+
+impl ser::Serializer for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, _: bool) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), WalkError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), WalkError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), WalkError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), WalkError> {
+        if let Ok(v) = u64::try_from(v) {
+            self.record_int(v);
+        }
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), WalkError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), WalkError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), WalkError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), WalkError> {
+        self.record_int(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_str(self, _: &str) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), WalkError> {
+        self.record_len(Some(v.len()));
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), WalkError> {
+        self.record_option(false);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), WalkError> {
+        self.record_option(true);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), WalkError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), WalkError> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, WalkError> {
+        self.record_len(len);
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, WalkError> {
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WalkError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WalkError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WalkError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WalkError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, _: &T) -> Result<(), WalkError> {
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), WalkError> {
+        self.field("", value)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), WalkError> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Walker {
+    type Ok = ();
+    type Error = WalkError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), WalkError> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<(), WalkError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The shape of a serialized presentation, reduced to what `Layout` reads.
+
+    #[derive(Serialize)]
+    struct Presentation {
+        attestation: AttestationProof,
+        identity: Option<()>,
+        transcript: Option<TranscriptProof>,
+    }
+
+    #[derive(Serialize)]
+    struct AttestationProof {
+        body: Body,
+    }
+
+    #[derive(Serialize)]
+    struct Body {
+        connection_info: Field<ConnectionInfo>,
+    }
+
+    #[derive(Serialize)]
+    struct Field<T> {
+        id: u32,
+        data: T,
+    }
+
+    #[derive(Serialize)]
+    struct ConnectionInfo {
+        time: u64,
+        transcript_length: TranscriptLength,
+    }
+
+    #[derive(Serialize)]
+    struct TranscriptLength {
+        sent: u32,
+        received: u32,
+    }
+
+    #[derive(Serialize)]
+    struct TranscriptProof {
+        transcript: PartialTranscript,
+        encoding_proof: Option<()>,
+    }
+
+    #[derive(Serialize)]
+    struct PartialTranscript {
+        sent_authed: Vec<u8>,
+        received_authed: Vec<u8>,
+        sent_idx: RangeSet,
+        recv_idx: RangeSet,
+        sent_total: usize,
+        recv_total: usize,
+    }
+
+    #[derive(Serialize)]
+    struct RangeSet {
+        ranges: Vec<Range<usize>>,
+    }
+
+    fn presentation() -> Presentation {
+        Presentation {
+            attestation: AttestationProof {
+                body: Body {
+                    connection_info: Field {
+                        id: 1,
+                        data: ConnectionInfo {
+                            time: 1_700_000_000,
+                            transcript_length: TranscriptLength {
+                                sent: 20,
+                                received: 30,
+                            },
+                        },
+                    },
+                },
+            },
+            identity: Some(()),
+            transcript: Some(TranscriptProof {
+                transcript: PartialTranscript {
+                    sent_authed: vec![b'a'; 9],
+                    received_authed: vec![b'b'; 30],
+                    sent_idx: RangeSet {
+                        ranges: vec![0..5, 10..14],
+                    },
+                    recv_idx: RangeSet {
+                        ranges: vec![0..30],
+                    },
+                    sent_total: 20,
+                    recv_total: 30,
+                },
+                encoding_proof: None,
+            }),
+        }
+    }
+
+    fn transcript(presentation: &mut Presentation) -> &mut PartialTranscript {
+        &mut presentation.transcript.as_mut().unwrap().transcript
+    }
+
+    fn check_err(presentation: &Presentation) -> String {
+        Layout::of(presentation)
+            .unwrap()
+            .check()
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn reads_the_declared_layout() {
+        let layout = Layout::of(&presentation()).unwrap();
+        layout.check().unwrap();
+        assert!(layout.identity && layout.transcript);
+        assert_eq!(layout.connection_time, Some(1_700_000_000));
+        assert_eq!(
+            layout.sent,
+            DirectionLayout {
+                attested_len: Some(20),
+                total_len: Some(20),
+                revealed: vec![0..5, 10..14],
+                revealed_bytes: Some(9),
+            }
+        );
+        assert_eq!(layout.received.revealed, vec![0..30]);
+    }
+
+    #[test]
+    fn rejects_missing_proofs() {
+        let mut p = presentation();
+        p.transcript = None;
+        assert!(check_err(&p).contains("no transcript proof"));
+
+        let mut p = presentation();
+        p.identity = None;
+        assert!(check_err(&p).contains("no server identity proof"));
+    }
+
+    #[test]
+    fn rejects_ranges_past_the_transcript() {
+        let mut p = presentation();
+        let t = transcript(&mut p);
+        t.recv_idx.ranges = vec![0..31];
+        t.received_authed.push(b'b');
+        assert!(check_err(&p).contains("past the transcript's 30 bytes"));
+    }
+
+    #[test]
+    fn rejects_revealed_bytes_not_matching_ranges() {
+        let mut p = presentation();
+        transcript(&mut p).sent_authed.truncate(4);
+        assert!(check_err(&p).contains("carries 4 revealed sent bytes"));
+    }
+
+    #[test]
+    fn rejects_lengths_not_matching_the_attestation() {
+        let mut p = presentation();
+        transcript(&mut p).sent_total = 25;
+        assert!(check_err(&p).contains("declares 25 sent bytes"));
+    }
+
+    #[test]
+    fn rejects_overlapping_ranges() {
+        let mut p = presentation();
+        transcript(&mut p).sent_idx.ranges = vec![0..5, 4..8];
+        assert!(check_err(&p).contains("overlapping"));
+    }
+}
@@ -0,0 +1,270 @@
+//! A small JSONPath dialect used by the disclosure and assertion features.
+//!
+//! Supported syntax:
+//!
+//! - `$` — the root (optional; a path may also start with a key)
+//! - `.key` or `['key']` / `["key"]` — object member
+//! - `[n]` — array element; negative `n` counts from the end
+//! - `.*` or `[*]` — every member of an object or element of an array
+//!
+//! Filters, slices, unions and recursive descent (`..`) are not supported.
+//!
+//! `djinn-tlsn-verifier jsonpath-test` prints this list and tries an
+//! expression against a sample body.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use tlsn_formats::spansy::{self, Spanned};
+
+/// The supported syntax and what it selects, as printed by `jsonpath-test`.
+pub const FEATURES: &[(&str, &str)] = &[
+    ("$", "the root (optional; a path may also start with a key)"),
+    (".key, ['key'], [\"key\"]", "object member"),
+    ("[n]", "array element; negative n counts from the end"),
+    (
+        ".*, [*]",
+        "every member of an object or element of an array",
+    ),
+];
+
+/// Syntax that is rejected rather than ignored.
+pub const UNSUPPORTED: &[&str] = &["filters", "slices", "unions", "recursive descent (..)"];
+
+/// A value selected by [`JsonPath::match_spans`].
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonMatch {
+    /// Concrete dotted path of the value (`data.0.price`).
+    pub path: String,
+    pub value: Value,
+    /// Byte range of the value in the source, as the disclosure features
+    /// would reveal it.
+    pub span: Option<Range<usize>>,
+}
+
+/// One step of a [`JsonPath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// An object member.
+    Key(String),
+    /// An array element; negative indices count from the end.
+    Index(i64),
+    /// Every child of an object or array.
+    Wildcard,
+}
+
+/// A parsed JSONPath expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    /// The segments of the path, in order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns every value the path selects in `root`, in document order.
+    pub fn select<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![root];
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|value| select_segment(value, segment))
+                .collect();
+        }
+        current
+    }
+
+    /// Like [`select`](Self::select), but also returns the concrete dotted
+    /// path of each selected value, with wildcards and negative indices
+    /// resolved.
+    pub fn select_with_paths<'a>(&self, root: &'a Value) -> Vec<(String, &'a Value)> {
+        let mut current = vec![(Vec::<String>::new(), root)];
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|(path, value)| {
+                    children(value, segment)
+                        .into_iter()
+                        .map(move |(step, child)| {
+                            let mut path = path.clone();
+                            path.push(step);
+                            (path, child)
+                        })
+                })
+                .collect();
+        }
+        current
+            .into_iter()
+            .map(|(path, value)| (path.join("."), value))
+            .collect()
+    }
+
+    /// Evaluates the path against a JSON document and locates each selected
+    /// value in it.
+    pub fn match_spans(&self, source: &[u8]) -> Result<Vec<JsonMatch>> {
+        let root: Value = serde_json::from_slice(source).context("sample is not valid JSON")?;
+        let spanned = spansy::json::parse_slice(source)
+            .map_err(|e| anyhow::anyhow!("failed to parse sample: {e}"))?;
+        Ok(self
+            .select_with_paths(&root)
+            .into_iter()
+            .map(|(path, value)| {
+                let span = spanned.get(&path).map(|v| {
+                    let start = v.span().indices().min().unwrap_or_default();
+                    let end = v.span().indices().max().map_or(start, |i| i + 1);
+                    start..end
+                });
+                JsonMatch {
+                    path,
+                    value: value.clone(),
+                    span,
+                }
+            })
+            .collect())
+    }
+
+    /// Renders the path in the dotted form used by the transcript parser
+    /// (`data.0.price`). Paths with wildcards or negative indices, which
+    /// don't name a single position, have no dotted form.
+    pub fn to_dotted(&self) -> Option<String> {
+        let parts = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Key(key) => Some(key.clone()),
+                Segment::Index(index) if *index >= 0 => Some(index.to_string()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(parts.join("."))
+    }
+}
+
+fn select_segment<'a>(value: &'a Value, segment: &Segment) -> Vec<&'a Value> {
+    children(value, segment)
+        .into_iter()
+        .map(|(_, child)| child)
+        .collect()
+}
+
+/// The children `segment` selects from `value`, with the dotted step to each.
+fn children<'a>(value: &'a Value, segment: &Segment) -> Vec<(String, &'a Value)> {
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(map)) => {
+            map.get(key).map(|v| (key.clone(), v)).into_iter().collect()
+        }
+        (Segment::Index(index), Value::Array(items)) => resolve_index(*index, items.len())
+            .map(|i| (i.to_string(), &items[i]))
+            .into_iter()
+            .collect(),
+        (Segment::Wildcard, Value::Object(map)) => {
+            map.iter().map(|(k, v)| (k.clone(), v)).collect()
+        }
+        (Segment::Wildcard, Value::Array(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Resolves a possibly-negative index against a length.
+pub fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let resolved = if index < 0 {
+        len.checked_sub(index.unsigned_abs() as usize)?
+    } else {
+        index as usize
+    };
+    (resolved < len).then_some(resolved)
+}
+
+impl FromStr for JsonPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let source = s.trim();
+        let mut rest = match source.strip_prefix('$') {
+            Some(rest) => rest,
+            None => {
+                // A bare leading key (`data.price`) is shorthand for `$.data.price`.
+                let end = source.find(['.', '[']).unwrap_or(source.len());
+                if end > 0 {
+                    segments.push(Segment::Key(source[..end].to_string()));
+                }
+                &source[end..]
+            }
+        };
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                if after.starts_with('.') {
+                    anyhow::bail!("recursive descent (..) is not supported: {s}");
+                }
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let name = &after[..end];
+                if name.is_empty() {
+                    anyhow::bail!("empty member name in JSONPath: {s}");
+                }
+                segments.push(if name == "*" {
+                    Segment::Wildcard
+                } else {
+                    Segment::Key(name.to_string())
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (segment, consumed) = parse_bracket(after)
+                    .with_context(|| format!("invalid bracket segment in JSONPath: {s}"))?;
+                segments.push(segment);
+                rest = &after[consumed..];
+            } else {
+                anyhow::bail!("unexpected character in JSONPath {s:?} at {rest:?}");
+            }
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+}
+
+/// Parses the inside of a `[...]` segment, returning the segment and the
+/// number of bytes consumed including the closing bracket.
+fn parse_bracket(s: &str) -> Result<(Segment, usize)> {
+    if let Some(quote) = s.chars().next().filter(|c| *c == '\'' || *c == '"') {
+        let body = &s[1..];
+        let end = body.find(quote).context("unterminated quoted key")?;
+        if !body[end + 1..].starts_with(']') {
+            anyhow::bail!("expected ] after quoted key");
+        }
+        return Ok((Segment::Key(body[..end].to_string()), end + 3));
+    }
+    let end = s.find(']').context("missing ]")?;
+    let inner = s[..end].trim();
+    let segment = if inner == "*" {
+        Segment::Wildcard
+    } else {
+        Segment::Index(
+            inner
+                .parse()
+                .with_context(|| format!("invalid index: {inner}"))?,
+        )
+    };
+    Ok((segment, end + 1))
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
@@ -0,0 +1,93 @@
+//! JWT envelopes for verification results.
+//!
+//! A JWT produced here is signed by whoever runs the verifier, with their own
+//! key. It asserts "this verifier checked a presentation and saw these
+//! values"; it does not carry the presentation, and consumers trusting it
+//! trust the verifier operator rather than the notary directly.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+
+use crate::verify::{disclosed_digest, VerifiedData};
+
+/// A claim that can be included in the JWT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// `server_name`: the attested server name.
+    ServerName,
+    /// `connection_time`: the attested connection time (UNIX seconds).
+    ConnectionTime,
+    /// `disclosed_digest`: hex [`disclosed_digest`] of the disclosed data.
+    DisclosedDigest,
+}
+
+impl FromStr for Claim {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "server_name" => Ok(Self::ServerName),
+            "connection_time" => Ok(Self::ConnectionTime),
+            "disclosed_digest" => Ok(Self::DisclosedDigest),
+            other => anyhow::bail!("unknown JWT claim: {other}"),
+        }
+    }
+}
+
+/// Signs verification results as JWTs.
+pub struct JwtSigner {
+    alg: Algorithm,
+    key: EncodingKey,
+}
+
+impl JwtSigner {
+    /// Loads a signing key for `alg` (e.g. `HS256`, `ES256`, `EdDSA`).
+    ///
+    /// HMAC algorithms read the file as the raw shared secret; all others
+    /// expect a PEM-encoded private key.
+    pub fn from_key_file(path: &Path, alg: &str) -> Result<Self> {
+        let alg =
+            Algorithm::from_str(alg).with_context(|| format!("unknown JWT algorithm: {alg}"))?;
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let key = match alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                EncodingKey::from_secret(&bytes)
+            }
+            Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(&bytes)?,
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&bytes)?,
+            _ => EncodingKey::from_rsa_pem(&bytes)?,
+        };
+        Ok(Self { alg, key })
+    }
+
+    /// Signs a JWT over `verified` with the selected claims plus `iat`.
+    pub fn sign(&self, verified: &VerifiedData, claims: &[Claim]) -> Result<String> {
+        let mut body = serde_json::Map::new();
+        body.insert("iat".into(), chrono::Utc::now().timestamp().into());
+        for claim in claims {
+            match claim {
+                Claim::ServerName => {
+                    body.insert("server_name".into(), verified.server_name.clone().into());
+                }
+                Claim::ConnectionTime => {
+                    body.insert("connection_time".into(), verified.time.into());
+                }
+                Claim::DisclosedDigest => {
+                    body.insert(
+                        "disclosed_digest".into(),
+                        hex::encode(disclosed_digest(verified)).into(),
+                    );
+                }
+            }
+        }
+        Ok(jsonwebtoken::encode(
+            &Header::new(self.alg),
+            &body,
+            &self.key,
+        )?)
+    }
+}
@@ -1,3 +1,53 @@
 /// Shared utilities for the Djinn TLSNotary prover and verifier.
 pub const MAX_SENT_DATA: usize = 4096;
 pub const MAX_RECV_DATA: usize = 262144; // 256 KB for odds API responses
+
+/// Bounds on a per-proof override of [`MAX_SENT_DATA`]. MPC cost grows with
+/// the limit, whether or not the data is sent.
+pub const SENT_DATA_LIMITS: std::ops::RangeInclusive<usize> = 256..=64 * 1024;
+/// Bounds on a per-proof override of [`MAX_RECV_DATA`].
+pub const RECV_DATA_LIMITS: std::ops::RangeInclusive<usize> = 1024..=16 * 1024 * 1024;
+
+/// Default upper bound on a serialized presentation, and on the memory bincode
+/// may allocate while decoding one.
+pub const MAX_PRESENTATION_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Upper bound on the notary's serialized attestation reply.
+pub const MAX_ATTESTATION_BYTES: u64 = 1024 * 1024;
+
+/// Notary signing algorithms considered acceptable, by their `KeyAlgId`
+/// display name. Anything else is reported as unexpected by the verifier.
+pub const KNOWN_NOTARY_KEY_ALGS: &[&str] = &["k256", "p256"];
+
+pub mod assertions;
+pub mod batch;
+pub mod bundle;
+pub mod cert;
+pub mod digest;
+pub mod disclosure;
+pub mod djinn;
+pub mod envelope;
+pub mod expect;
+#[cfg(feature = "grpc-web")]
+pub mod grpc_web;
+pub mod http;
+pub mod inspect;
+pub mod jsonpath;
+pub mod jwt;
+pub mod manifest;
+pub mod metrics;
+pub mod notary;
+pub mod output;
+pub mod policy;
+pub mod prove;
+pub mod proxy;
+pub mod rate;
+pub mod record;
+pub mod resolve;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod status;
+pub mod verify;
+
+pub use djinn::{verify_presentation, Djinn, DjinnConfig, VerifyOptions};
+pub use verify::VerificationReport;
@@ -0,0 +1,144 @@
+//! Manifest sidecar written next to a presentation.
+//!
+//! The manifest records what the prover meant to show with the presentation,
+//! such as `--reveal-and-assert` comparisons. Nothing in it is attested: the
+//! notary never sees it and anyone can edit it. A verifier run with
+//! `--against-manifest` uses it only as a list of claims to re-check against
+//! the disclosed data, and ties it to one presentation by hash.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::assertions::{json_eq, parse_revealed_value, Comparison};
+use crate::verify::VerifiedData;
+
+/// Current manifest format version.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A manifest sidecar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Hex SHA-256 of the serialized presentation this manifest describes.
+    pub presentation_sha256: String,
+    #[serde(default)]
+    pub assertions: Vec<ManifestAssertion>,
+}
+
+/// A comparison the prover asserted over a revealed response field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestAssertion {
+    /// JSONPath of the field in the response body.
+    pub path: String,
+    /// Comparison operator (`==`, `!=`, `<`, `<=`, `>`, `>=`).
+    pub op: String,
+    /// Value the field is compared against.
+    pub value: Value,
+    /// Byte range of the field's value in the received transcript.
+    pub range: Range<usize>,
+}
+
+impl ManifestAssertion {
+    pub fn new(comparison: &Comparison, range: Range<usize>) -> Self {
+        Self {
+            path: comparison.path.to_string(),
+            op: comparison.op.as_str().to_string(),
+            value: comparison.value.clone(),
+            range,
+        }
+    }
+
+    /// Parses the recorded comparison back into a checkable form.
+    pub fn comparison(&self) -> Result<Comparison> {
+        Ok(Comparison {
+            path: self.path.parse()?,
+            op: self.op.parse()?,
+            value: self.value.clone(),
+        })
+    }
+}
+
+impl Manifest {
+    pub fn new(presentation_sha256: String) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            presentation_sha256,
+            assertions: Vec::new(),
+        }
+    }
+
+    /// Reads a manifest, rejecting versions this build doesn't understand.
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::from_slice(&bytes).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// Parses a manifest, rejecting versions this build doesn't understand.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let manifest: Self = serde_json::from_slice(bytes)?;
+        if manifest.version != MANIFEST_VERSION {
+            anyhow::bail!("unsupported manifest version {}", manifest.version);
+        }
+        Ok(manifest)
+    }
+
+    /// Re-checks the recorded assertions against verified data, whose
+    /// serialized presentation hashes to `presentation_sha256` (hex) and
+    /// whose response body is `body`. Returns a description of each failure.
+    pub fn check(
+        &self,
+        presentation_sha256: &str,
+        data: &VerifiedData,
+        body: &[u8],
+    ) -> Vec<String> {
+        if self.presentation_sha256 != presentation_sha256 {
+            return vec!["manifest was written for a different presentation".into()];
+        }
+
+        let body_json = serde_json::from_slice::<Value>(body).ok();
+        let mut failures = Vec::new();
+        for assertion in &self.assertions {
+            let range = assertion.range.clone();
+            if range.end > data.received.len() || !data.is_received_revealed(&range) {
+                failures.push(format!("{}: value is not disclosed", assertion.path));
+                continue;
+            }
+            let comparison = match assertion.comparison() {
+                Ok(comparison) => comparison,
+                Err(e) => {
+                    failures.push(format!("invalid manifest assertion: {e:#}"));
+                    continue;
+                }
+            };
+            let actual = parse_revealed_value(&data.received[range]);
+            // With the whole body disclosed, also confirm the recorded range
+            // really holds the value at the path.
+            if let Some(json) = &body_json {
+                if !comparison
+                    .path
+                    .select(json)
+                    .iter()
+                    .any(|v| json_eq(v, &actual))
+                {
+                    failures.push(format!(
+                        "{}: manifest range does not hold the value at this path",
+                        assertion.path
+                    ));
+                    continue;
+                }
+            }
+            failures.extend(comparison.check(&actual).err());
+        }
+        failures
+    }
+
+    /// The sidecar path for a presentation: `proof.bin` → `proof.manifest.json`.
+    pub fn path_for(presentation: &Path) -> PathBuf {
+        presentation.with_extension("manifest.json")
+    }
+}
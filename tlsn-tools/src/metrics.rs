@@ -0,0 +1,112 @@
+//! One-shot OpenMetrics summary of a prover run.
+//!
+//! Short-lived runs (e.g. from cron) can't be scraped, so the prover can
+//! instead write a [`RunMetrics`] file on exit for a textfile collector such
+//! as node-exporter's to pick up.
+
+use std::fmt::Write as _;
+use std::time::{Duration, SystemTime};
+
+use crate::batch::BatchStats;
+
+/// What a finished prover run reports.
+#[derive(Debug, Clone)]
+pub struct RunMetrics {
+    pub duration: Duration,
+    /// `success`, `batch_complete` or a failure status.
+    pub status: String,
+    /// Size of the serialized presentation, for single proofs.
+    pub presentation_bytes: Option<u64>,
+    /// Fraction of transcript bytes revealed, when measured.
+    pub disclosure_ratio: Option<f64>,
+    pub batch: Option<BatchStats>,
+}
+
+impl RunMetrics {
+    pub fn new(duration: Duration, status: impl Into<String>) -> Self {
+        Self {
+            duration,
+            status: status.into(),
+            presentation_bytes: None,
+            disclosure_ratio: None,
+            batch: None,
+        }
+    }
+
+    /// Renders the metrics in the OpenMetrics text format, which Prometheus
+    /// textfile collectors also accept.
+    pub fn to_openmetrics(&self) -> String {
+        let finished = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let success = matches!(self.status.as_str(), "success" | "batch_complete");
+
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, samples: &[(String, f64)]| {
+            let _ = writeln!(out, "# TYPE djinn_prover_{name} gauge");
+            let _ = writeln!(out, "# HELP djinn_prover_{name} {help}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "djinn_prover_{name}{labels} {value}");
+            }
+        };
+        gauge(
+            "run_duration_seconds",
+            "Wall-clock duration of the last run.",
+            &[(String::new(), self.duration.as_secs_f64())],
+        );
+        gauge(
+            "run_finished_timestamp_seconds",
+            "When the last run finished.",
+            &[(String::new(), finished.as_secs_f64())],
+        );
+        gauge(
+            "run_success",
+            "1 if the last run succeeded, 0 otherwise.",
+            &[(String::new(), if success { 1.0 } else { 0.0 })],
+        );
+        gauge(
+            "run_status",
+            "Status of the last run.",
+            &[(format!("{{status=\"{}\"}}", escape(&self.status)), 1.0)],
+        );
+        if let Some(bytes) = self.presentation_bytes {
+            gauge(
+                "presentation_bytes",
+                "Size of the serialized presentation.",
+                &[(String::new(), bytes as f64)],
+            );
+        }
+        if let Some(ratio) = self.disclosure_ratio {
+            gauge(
+                "disclosure_ratio",
+                "Fraction of transcript bytes revealed.",
+                &[(String::new(), ratio)],
+            );
+        }
+        if let Some(stats) = &self.batch {
+            gauge(
+                "batch_proofs",
+                "Proofs in the last batch, by result.",
+                &[
+                    ("{result=\"succeeded\"}".into(), stats.succeeded as f64),
+                    ("{result=\"failed\"}".into(), stats.failed as f64),
+                ],
+            );
+            gauge(
+                "batch_proofs_per_second",
+                "Throughput of the last batch.",
+                &[(String::new(), stats.proofs_per_sec)],
+            );
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,192 @@
+//! The prover's attestation exchange with the notary.
+//!
+//! The prover requests its attestation through [`NotaryClient`]: by default a
+//! [`SocketNotary`] over the connection the session ran on, or any client
+//! passed to [`prove_with_notary`](crate::prove::prove_with_notary), such as
+//! one that signs attestations locally in tests. The MPC-TLS session itself
+//! still needs a live notary.
+//!
+//! # Rejection frames
+//!
+//! A notary that refuses to attest can reply with a rejection frame instead
+//! of an attestation: the ASCII bytes `DJINN-NOTARY-ERROR/1` and a newline,
+//! followed by a UTF-8 JSON object:
+//!
+//! ```json
+//! {"code": "rate-limited", "message": "at most 10 sessions per minute"}
+//! ```
+//!
+//! `code` is one of the [`NotaryError`] codes below (other values are
+//! accepted and reported as `notary_rejected`); `message` is free text for
+//! humans. The frame is the whole reply, and the notary closes the connection
+//! after it. [`NotaryError::encode`] produces it.
+//!
+//! # TLS
+//!
+//! The session and the attestation exchange run over one connection, plain
+//! TCP by default. With [`tls_config`] the prover wraps it in TLS instead, for
+//! notaries deployed behind a TLS endpoint; the attestation request then ends
+//! with a TLS `close_notify` rather than a bare TCP half-close.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bincode::Options;
+use futures::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use serde::{Deserialize, Serialize};
+use tlsn::attestation::{request::Request, Attestation};
+use tokio_rustls::rustls::{crypto::ring, pki_types::CertificateDer, ClientConfig, RootCertStore};
+use x509_parser::pem::Pem;
+
+use crate::{inspect::bincode_options, status, MAX_ATTESTATION_BYTES};
+
+/// Something that turns an attestation request into a signed attestation.
+#[async_trait]
+pub trait NotaryClient {
+    /// Sends `request` and returns the notary's attestation.
+    async fn attest(&mut self, request: &Request) -> Result<Attestation>;
+}
+
+/// A [`NotaryClient`] over the socket reclaimed from a finished session.
+///
+/// The request is written bincode-encoded, the write half is closed, and the
+/// reply is read to EOF. The socket is consumed by the first request.
+pub struct SocketNotary<S> {
+    socket: Option<S>,
+}
+
+impl<S> SocketNotary<S> {
+    pub fn new(socket: S) -> Self {
+        Self {
+            socket: Some(socket),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> NotaryClient for SocketNotary<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    async fn attest(&mut self, request: &Request) -> Result<Attestation> {
+        let mut socket = self
+            .socket
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("notary socket was already used"))?;
+
+        socket.write_all(&bincode::serialize(request)?).await?;
+        socket.close().await?;
+
+        let mut reply = Vec::new();
+        (&mut socket)
+            .take(MAX_ATTESTATION_BYTES + 1)
+            .read_to_end(&mut reply)
+            .await?;
+        if reply.len() as u64 > MAX_ATTESTATION_BYTES {
+            anyhow::bail!("notary reply exceeds {MAX_ATTESTATION_BYTES} bytes");
+        }
+        if let Some(rejection) = NotaryError::decode(&reply) {
+            return Err(rejection.into());
+        }
+        bincode_options(MAX_ATTESTATION_BYTES)
+            .deserialize(&reply)
+            .map_err(|e| status::notary_reply_error(&reply, e))
+    }
+}
+
+/// A structured rejection from the notary, decoded from a rejection frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotaryError {
+    /// `host-not-allowed`, `size-exceeded`, `rate-limited`, `unauthorized`,
+    /// or a notary-specific code.
+    pub code: String,
+    pub message: String,
+}
+
+impl NotaryError {
+    /// Prefix identifying a rejection frame.
+    pub const FRAME_MAGIC: &'static [u8] = b"DJINN-NOTARY-ERROR/1\n";
+
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Decodes a rejection frame. Returns `None` if `reply` isn't one.
+    pub fn decode(reply: &[u8]) -> Option<Self> {
+        let json = reply.strip_prefix(Self::FRAME_MAGIC)?;
+        serde_json::from_slice(json).ok()
+    }
+
+    /// Encodes this rejection as a frame, for notary implementations.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Self::FRAME_MAGIC.to_vec();
+        frame.extend(serde_json::to_vec(self).expect("notary error serializes"));
+        frame
+    }
+
+    /// The prover status reported for this rejection.
+    pub fn status(&self) -> &'static str {
+        match self.code.as_str() {
+            "host-not-allowed" => "notary_host_not_allowed",
+            "size-exceeded" => "notary_size_exceeded",
+            "rate-limited" => "notary_rate_limited",
+            "unauthorized" => "notary_unauthorized",
+            _ => "notary_rejected",
+        }
+    }
+}
+
+impl fmt::Display for NotaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "notary rejected the request ({}): {}",
+            self.code, self.message
+        )
+    }
+}
+
+impl std::error::Error for NotaryError {}
+
+/// TLS settings for the notary connection. With `ca`, only certificates
+/// chaining to the CAs in that PEM file (e.g. a self-signed dev notary's own
+/// certificate) are trusted; otherwise the system roots are.
+pub fn tls_config(ca: Option<&Path>) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    match ca {
+        Some(path) => {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            for block in Pem::iter_from_buffer(&pem) {
+                let block =
+                    block.map_err(|e| anyhow::anyhow!("invalid PEM in {}: {e}", path.display()))?;
+                if block.label == "CERTIFICATE" {
+                    roots
+                        .add(CertificateDer::from(block.contents))
+                        .with_context(|| format!("invalid certificate in {}", path.display()))?;
+                }
+            }
+            if roots.is_empty() {
+                anyhow::bail!("{} contains no certificates", path.display());
+            }
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            if native.certs.is_empty() {
+                anyhow::bail!("no system root certificates found: {:?}", native.errors);
+            }
+            roots.add_parsable_certificates(native.certs);
+        }
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
@@ -0,0 +1,81 @@
+//! Helpers for writing CLI output files.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Writes `contents` to `path`, readable and writable by the owner only.
+///
+/// Used for files that contain secrets (raw transcripts, attestation
+/// secrets). On non-Unix platforms this falls back to default permissions.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    // `mode` only applies to newly created files; tighten existing ones too.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Writes `contents` to `path` atomically: readers see either the old file or
+/// the complete new one, never a partial write.
+///
+/// The data goes to a temporary file in the same directory, which is then
+/// renamed over `path`.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".tmp-{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&tmp_path)
+            .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+        file.write_all(contents)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to replace {}", path.display()))
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Makes sure the directory `path` will be written into exists, creating it
+/// (and any missing ancestors) when `create` is set. Otherwise a missing
+/// directory is reported by name instead of as a bare IO error.
+pub fn ensure_parent_dir(path: &Path, create: bool) -> Result<()> {
+    let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if parent.is_dir() {
+        return Ok(());
+    }
+    if create {
+        return std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory {}", parent.display()));
+    }
+    anyhow::bail!(
+        "output directory {} does not exist (pass --create-dirs to create it)",
+        parent.display()
+    )
+}
@@ -0,0 +1,340 @@
+//! Declarative verification policies.
+//!
+//! A [`VerificationPolicy`] collects every requirement a presentation must
+//! meet beyond being validly signed: which notaries are trusted, the server it
+//! must come from, how old it may be, which headers must be disclosed and
+//! assertions over the JSON response body. Policies are read from TOML or JSON
+//! files, so operators and embedders express requirements in one place
+//! instead of in verifier flags:
+//!
+//! ```toml
+//! trusted_notary_keys = ["02ab..."]
+//! server_name = "api.the-odds-api.com"
+//! max_age_secs = 300
+//! required_response_headers = ["content-type"]
+//! require_body = true
+//! expect_json = ["$.sport_key=basketball_nba"]
+//! assert_value = ["$.bookmakers[0].markets[0].outcomes[0].price > 1.0"]
+//! assert_absent = ["$.error"]
+//! ```
+//!
+//! [`verify_with_policy`] verifies a serialized presentation and evaluates
+//! every rule, reporting each as passed or failed.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tlsn::attestation::CryptoProvider;
+
+use crate::assertions::{Comparison, ExpectJson, FieldComparison};
+use crate::http;
+use crate::jsonpath::JsonPath;
+use crate::verify::{self, checks::Checks, exchange::Exchange, VerificationReport, VerifiedData};
+use crate::MAX_PRESENTATION_BYTES;
+
+/// Requirements a verified presentation must meet. Every field is optional;
+/// the default policy accepts any validly signed presentation.
+///
+/// Assertions are written as in the verifier flags of the same name:
+/// `expect_json` as `<jsonpath>=<value>` and `assert_compare` as
+/// `<jsonpath> <op> <jsonpath>`. `assert_value` compares against a constant,
+/// `<jsonpath> <op> <value>`, like the prover's `--reveal-and-assert`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerificationPolicy {
+    /// Hex-encoded notary public keys to accept. Empty accepts any notary,
+    /// which only proves the presentation is self-consistent.
+    pub trusted_notary_keys: Vec<String>,
+    /// Server name the attestation must be bound to (case-insensitive).
+    pub server_name: Option<String>,
+    /// Maximum age of the attested connection, in seconds.
+    pub max_age_secs: Option<u64>,
+    /// Clock difference tolerated by `max_age_secs`, in seconds.
+    pub clock_skew_secs: u64,
+    /// Request headers whose values must be disclosed.
+    pub required_request_headers: Vec<String>,
+    /// Response headers whose values must be disclosed.
+    pub required_response_headers: Vec<String>,
+    /// Require a disclosed response body.
+    pub require_body: bool,
+    #[serde(deserialize_with = "parse_each")]
+    pub expect_json: Vec<ExpectJson>,
+    #[serde(deserialize_with = "parse_each")]
+    pub assert_compare: Vec<FieldComparison>,
+    /// Every value a path selects must satisfy the comparison.
+    #[serde(deserialize_with = "parse_each")]
+    pub assert_value: Vec<Comparison>,
+    /// JSONPaths that must select nothing in a fully disclosed body.
+    #[serde(deserialize_with = "parse_each")]
+    pub assert_absent: Vec<JsonPath>,
+    /// Largest serialized presentation accepted, in bytes.
+    pub max_presentation_bytes: u64,
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> Self {
+        Self {
+            trusted_notary_keys: Vec::new(),
+            server_name: None,
+            max_age_secs: None,
+            clock_skew_secs: 0,
+            required_request_headers: Vec::new(),
+            required_response_headers: Vec::new(),
+            require_body: false,
+            expect_json: Vec::new(),
+            assert_compare: Vec::new(),
+            assert_value: Vec::new(),
+            assert_absent: Vec::new(),
+            max_presentation_bytes: MAX_PRESENTATION_BYTES,
+        }
+    }
+}
+
+/// The outcome of one policy rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleResult {
+    /// The rule, e.g. `server_name` or `assert_compare: $.price > 1`.
+    pub rule: String,
+    pub passed: bool,
+    /// Why the rule failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RuleResult {
+    fn new(rule: impl Into<String>, outcome: Result<(), String>) -> Self {
+        Self {
+            rule: rule.into(),
+            passed: outcome.is_ok(),
+            error: outcome.err(),
+        }
+    }
+
+    /// `<rule>: <error>` if the rule failed.
+    pub fn failure(&self) -> Option<String> {
+        let error = self.error.as_ref()?;
+        Some(format!("{}: {error}", self.rule))
+    }
+}
+
+/// A presentation verified under a policy.
+#[derive(Debug, Clone)]
+pub struct PolicyVerification {
+    pub verification: VerificationReport,
+    /// Every rule of the policy, in the order it was checked.
+    pub rules: Vec<RuleResult>,
+}
+
+impl PolicyVerification {
+    /// Returns true if every rule passed.
+    pub fn passed(&self) -> bool {
+        self.rules.iter().all(|rule| rule.passed)
+    }
+
+    /// The failure messages of the rules that failed.
+    pub fn failures(&self) -> Vec<String> {
+        self.rules.iter().filter_map(RuleResult::failure).collect()
+    }
+}
+
+impl VerificationPolicy {
+    /// Reads a policy from a `.json` file, or TOML otherwise.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read policy {}", path.display()))?;
+        let policy = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&text).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str(&text).map_err(anyhow::Error::from)
+        };
+        policy.with_context(|| format!("invalid policy {}", path.display()))
+    }
+
+    /// Evaluates every rule against verified data signed by `notary_key`
+    /// (hex) at `now` (UNIX seconds).
+    ///
+    /// The header and body rules are evaluated on every exchange; with
+    /// several, each result is named after its exchange, e.g.
+    /// `exchange 1: require_body`.
+    pub fn check(&self, notary_key: &str, data: &VerifiedData, now: u64) -> Vec<RuleResult> {
+        let mut rules = Vec::new();
+
+        if !self.trusted_notary_keys.is_empty() {
+            let trusted = self
+                .trusted_notary_keys
+                .iter()
+                .any(|key| key.eq_ignore_ascii_case(notary_key));
+            rules.push(RuleResult::new(
+                "trusted_notary_keys",
+                if trusted {
+                    Ok(())
+                } else {
+                    Err(format!("notary key {notary_key} is not trusted"))
+                },
+            ));
+        }
+        if let Some(expected) = &self.server_name {
+            let outcome = match &data.server_name {
+                Some(name) if name.eq_ignore_ascii_case(expected) => Ok(()),
+                Some(name) => Err(format!("expected {expected}, got {name}")),
+                None => Err("server name is not disclosed".to_string()),
+            };
+            rules.push(RuleResult::new("server_name", outcome));
+        }
+        if let Some(max_age) = self.max_age_secs {
+            rules.push(RuleResult::new(
+                "max_age_secs",
+                verify::check_freshness(data.time, now, max_age, self.clock_skew_secs),
+            ));
+        }
+
+        let exchanges = Exchange::all(data);
+        for (i, exchange) in exchanges.iter().enumerate() {
+            let prefix = if exchanges.len() > 1 {
+                format!("exchange {i}: ")
+            } else {
+                String::new()
+            };
+            self.check_exchange(exchange, &prefix, &mut rules);
+        }
+        rules
+    }
+
+    /// Evaluates the header and body rules against one exchange, naming
+    /// each rule after `prefix`.
+    fn check_exchange(&self, exchange: &Exchange, prefix: &str, rules: &mut Vec<RuleResult>) {
+        for name in &self.required_request_headers {
+            let disclosed = http::header_value_range(&exchange.request_head, name)
+                .is_some_and(|range| exchange.is_request_head_revealed(&range));
+            rules.push(RuleResult::new(
+                format!("{prefix}required_request_headers: {name}"),
+                if disclosed {
+                    Ok(())
+                } else {
+                    Err("header value is not disclosed".to_string())
+                },
+            ));
+        }
+        for name in &self.required_response_headers {
+            let disclosed = http::header_value_range(&exchange.response_head, name)
+                .is_some_and(|range| exchange.is_response_head_revealed(&range));
+            rules.push(RuleResult::new(
+                format!("{prefix}required_response_headers: {name}"),
+                if disclosed {
+                    Ok(())
+                } else {
+                    Err("header value is not disclosed".to_string())
+                },
+            ));
+        }
+
+        let body = exchange.body_bytes();
+        let body_revealed = exchange.is_body_disclosed();
+        if self.require_body {
+            rules.push(RuleResult::new(
+                format!("{prefix}require_body"),
+                if body_revealed {
+                    Ok(())
+                } else {
+                    Err("response body is not disclosed".to_string())
+                },
+            ));
+        }
+
+        let json_rules = !self.expect_json.is_empty()
+            || !self.assert_compare.is_empty()
+            || !self.assert_value.is_empty()
+            || !self.assert_absent.is_empty();
+        if !json_rules {
+            return;
+        }
+        let json = serde_json::from_slice::<Value>(body)
+            .map_err(|e| format!("response body is not valid JSON: {e}"));
+        let fully_disclosed = exchange.is_body_revealed();
+        let on_json = |check: &dyn Fn(&Value) -> Result<(), String>| match &json {
+            Ok(json) => check(json),
+            Err(e) => Err(e.clone()),
+        };
+        for assertion in &self.expect_json {
+            rules.push(RuleResult::new(
+                format!(
+                    "{prefix}expect_json: {}={}",
+                    assertion.path, assertion.expected
+                ),
+                on_json(&|json| assertion.check(json)),
+            ));
+        }
+        for comparison in &self.assert_compare {
+            rules.push(RuleResult::new(
+                format!("{prefix}assert_compare: {comparison}"),
+                on_json(&|json| comparison.check(json)),
+            ));
+        }
+        for comparison in &self.assert_value {
+            rules.push(RuleResult::new(
+                format!("{prefix}assert_value: {comparison}"),
+                on_json(&|json| match comparison.path.select(json).as_slice() {
+                    [] => Err(format!("{}: no value at path", comparison.path)),
+                    values => values.iter().try_for_each(|value| comparison.check(value)),
+                }),
+            ));
+        }
+        for path in &self.assert_absent {
+            rules.push(RuleResult::new(
+                format!("{prefix}assert_absent: {path}"),
+                on_json(&|json| {
+                    // Absence over a partly hidden body would prove nothing.
+                    if !fully_disclosed {
+                        Err("the response body is not fully disclosed".to_string())
+                    } else if path.select(json).is_empty() {
+                        Ok(())
+                    } else {
+                        Err(format!("{path} is present in the response body"))
+                    }
+                }),
+            ));
+        }
+    }
+}
+
+/// Verifies a serialized presentation and evaluates every rule of `policy`.
+///
+/// Fails only if the presentation doesn't verify; policy violations are
+/// reported per rule in the result (see [`PolicyVerification::passed`]).
+pub fn verify_with_policy(
+    bytes: &[u8],
+    policy: &VerificationPolicy,
+    provider: &CryptoProvider,
+) -> Result<PolicyVerification> {
+    let verification = verify::verify_bytes(
+        bytes,
+        policy.max_presentation_bytes,
+        &[],
+        &Checks::default(),
+        provider,
+    )?;
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let rules = policy.check(&verification.notary_key, &verification.data, now);
+    Ok(PolicyVerification {
+        verification,
+        rules,
+    })
+}
+
+/// Deserializes a list of strings with each element's [`FromStr`].
+fn parse_each<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = anyhow::Error>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| {
+            s.parse()
+                .map_err(|e| serde::de::Error::custom(format!("{s:?}: {e:#}")))
+        })
+        .collect()
+}
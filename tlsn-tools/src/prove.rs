@@ -0,0 +1,2336 @@
+//! The prover flow: an attested HTTPS request turned into a presentation.
+//!
+//! [`prove`] runs one request end to end: MPC-TLS with the notary, the HTTP
+//! exchange with the target, the attestation, and selective disclosure. It
+//! returns the presentation in memory; writing it anywhere is up to the
+//! caller, together with [`ProveMetadata`] describing the run.
+//!
+//! With [`ProveOptions::requests`], several requests are sent one after the
+//! other over the same kept-alive connection instead, and the presentation
+//! attests every exchange, in order, as one session.
+//!
+//! [`rebuild`] builds another presentation, offline, from an attestation an
+//! earlier run saved with [`ProveOptions::attestation_output`].
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bincode::Options as _;
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt as _, Empty, Full, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    Request, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use rand::Rng as _;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt as _, ReadBuf},
+    task::AbortHandle,
+};
+use tokio_rustls::{
+    rustls::{self, crypto::ring, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use tokio_socks::tcp::Socks5Stream;
+use tokio_util::{
+    compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt},
+    either::Either,
+};
+use tracing::{info, warn};
+
+use tlsn::{
+    attestation::{
+        presentation::Presentation,
+        request::{Request as AttestationRequest, RequestConfig},
+        Attestation, CryptoProvider, Secrets,
+    },
+    config::{
+        prove::ProveConfig,
+        prover::ProverConfig,
+        tls::TlsClientConfig,
+        tls_commit::{mpc::MpcTlsConfig, TlsCommitConfig},
+    },
+    connection::{HandshakeData, ServerName},
+    prover::ProverOutput,
+    transcript::{Direction, Transcript, TranscriptCommitConfig},
+    Session,
+};
+use tlsn_formats::{
+    http::{DefaultHttpCommitter, HttpCommit, HttpTranscript},
+    spansy::http::parse_request,
+};
+
+use crate::{
+    assertions::{parse_revealed_value, Comparison},
+    cert,
+    digest::{self, DigestCredentials},
+    disclosure::{
+        expand_json_path, json_value_range, reveal_only, reveal_only_commits, BodilessResponse,
+        DisclosureBuilder, DisclosureThreshold, ExtraCommits, HeaderPrefix, RawLines,
+        RevealSelector,
+    },
+    expect::{continue_body, ContinueWatch},
+    http, inspect,
+    jsonpath::JsonPath,
+    manifest::ManifestAssertion,
+    notary::{NotaryClient, SocketNotary},
+    output::write_private,
+    proxy::{HttpProxy, Socks5Proxy},
+    record::{NotaryStream, Recorder, Replay},
+    resolve::EncryptedResolver,
+    status::{self, StatusError},
+    verify::{self, VerifiedData},
+    MAX_RECV_DATA, MAX_SENT_DATA, RECV_DATA_LIMITS, SENT_DATA_LIMITS,
+};
+
+/// How the prover reaches the notary and the target. Shared by every request
+/// made with the same setup.
+#[derive(Debug, Clone)]
+pub struct Transport {
+    pub notary_host: String,
+    pub notary_port: u16,
+    /// Connect to the notary over TLS with this configuration (see
+    /// [`notary::tls_config`](crate::notary::tls_config)) instead of plain
+    /// TCP. The certificate is checked against `notary_host`.
+    pub notary_tls: Option<Arc<ClientConfig>>,
+    /// Record the notary connection to this file (see [`record`]).
+    pub record_notary: Option<PathBuf>,
+    /// Replay this recording instead of connecting to the notary. For
+    /// debugging only: a replayed session never yields a valid proof.
+    pub replay_notary: Option<PathBuf>,
+    /// Resolve target hosts over encrypted DNS instead of the system resolver.
+    pub resolver: Option<EncryptedResolver>,
+    /// Reach the target through this SOCKS5 proxy.
+    pub proxy: Option<Socks5Proxy>,
+    /// Reach the target through a `CONNECT` tunnel on this HTTP proxy.
+    pub http_proxy: Option<HttpProxy>,
+    /// Reach the notary through this SOCKS5 proxy.
+    pub notary_proxy: Option<Socks5Proxy>,
+    /// Retries after a failed connection attempt to the notary or the
+    /// target. Only connecting is retried, never the MPC session.
+    pub connect_retries: u32,
+    /// Delay before the first retry, doubled for each further one, plus up
+    /// to half again as random jitter.
+    pub connect_backoff: Duration,
+    /// Abort a whole proof that takes longer than this, whichever phase it
+    /// is in. The per-phase timeouts below still apply.
+    pub timeout: Option<Duration>,
+    pub notary_handshake_timeout: Duration,
+    pub mpc_setup_timeout: Duration,
+    pub attestation_timeout: Duration,
+}
+
+impl Transport {
+    /// A transport to the notary at `host:port` with the default timeouts.
+    pub fn new(notary_host: impl Into<String>, notary_port: u16) -> Self {
+        Self {
+            notary_host: notary_host.into(),
+            notary_port,
+            notary_tls: None,
+            record_notary: None,
+            replay_notary: None,
+            resolver: None,
+            proxy: None,
+            http_proxy: None,
+            notary_proxy: None,
+            connect_retries: 0,
+            connect_backoff: Duration::from_millis(200),
+            timeout: None,
+            notary_handshake_timeout: Duration::from_secs(10),
+            mpc_setup_timeout: Duration::from_secs(120),
+            attestation_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Default `User-Agent` of proved requests; see [`ProveOptions::user_agent`].
+pub const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 \
+                              (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+/// What to request and what to disclose for one proof.
+#[derive(Debug, Clone)]
+pub struct ProveOptions {
+    /// Full URL to fetch, including query parameters.
+    pub url: String,
+    /// `GET`, `HEAD`, `POST` or `PUT`.
+    pub method: String,
+    /// Request body; required for `POST` and `PUT` and only valid with them.
+    pub body: Option<Bytes>,
+    pub content_type: String,
+    /// How the keys of a JSON `body` are ordered on the wire.
+    pub json_key_order: JsonKeyOrder,
+    /// Hold the body back until `100 Continue` (or `continue_timeout`).
+    pub expect_continue: bool,
+    pub continue_timeout: Duration,
+    /// Flush the request head to the connection before sending the body,
+    /// instead of writing both at once. Advanced: this changes how the
+    /// request is split into TLS records, which the notary sees.
+    pub flush_after_headers: bool,
+    /// Request header name patterns whose values are redacted.
+    pub redact_headers: Vec<String>,
+    /// Request headers whose values are revealed up to a length only; see
+    /// [`DisclosureBuilder::reveal_header_prefix`].
+    pub reveal_header_prefixes: Vec<HeaderPrefix>,
+    /// Matches of these are hidden from the response body, which must be
+    /// revealed in full; see [`DisclosureBuilder::redact_body_regex`].
+    pub redact_body_patterns: Vec<Regex>,
+    /// When either is non-empty, commit and reveal the raw transcript
+    /// without parsing it as HTTP, hiding every sent or received line these
+    /// match and the values of `redact_headers`; see [`RawLines`]. A coarse
+    /// fallback for servers whose messages don't parse.
+    pub redact_sent_lines: Vec<Regex>,
+    pub redact_recv_lines: Vec<Regex>,
+    /// When set, the request body is a URL-encoded form and is revealed,
+    /// except for the values of these fields.
+    pub form_redactions: Option<Vec<String>>,
+    /// Authenticate with HTTP Digest auth; see [`crate::digest`]. The
+    /// `Authorization` header is redacted whatever `redact_headers` says.
+    pub digest_auth: Option<DigestCredentials>,
+    /// When set, reveal only the response status line and these headers.
+    pub response_meta_headers: Option<Vec<String>>,
+    /// Accept a 3xx response (without following it) instead of failing.
+    /// `Location` is then revealed even when only response metadata is.
+    pub accept_redirect: bool,
+    /// Make the request conditional with `If-None-Match` (an entity tag such
+    /// as `"33a64df5"`) and/or `If-Modified-Since` (an HTTP date). A `304 Not
+    /// Modified` answer is then attested like a bodiless `200`, proving the
+    /// resource unchanged since that state.
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+    /// TLS server name (SNI) to use instead of the URL host. The certificate
+    /// is verified against, and the attestation bound to, this name.
+    pub server_name: Option<String>,
+    /// `Host` header to send instead of the URL host.
+    pub host_header: Option<String>,
+    /// Extra request headers, sent after the default ones. One named like a
+    /// default header replaces it. They are committed like any other header,
+    /// so secrets among them need a matching `redact_headers` pattern.
+    pub headers: Vec<RequestHeader>,
+    /// `User-Agent` to send.
+    pub user_agent: String,
+    /// How much of the `User-Agent` to disclose, whatever `redact_headers`
+    /// says. `None` leaves it to `redact_headers`, which reveals it by
+    /// default.
+    pub user_agent_disclosure: Option<UserAgentDisclosure>,
+    /// Stream the response body to this file as it arrives.
+    pub spool_body: Option<PathBuf>,
+    /// Offer `gzip, deflate, br` instead of `identity`. A compressed response
+    /// is committed as received and its body can only be revealed whole; see
+    /// [`ProveMetadata::content_encoding`].
+    pub accept_compressed: bool,
+    /// Most bytes the request may send, and the response return, in the MPC
+    /// session. Within [`SENT_DATA_LIMITS`] and [`RECV_DATA_LIMITS`]; larger
+    /// limits make MPC setup slower whatever is actually transferred.
+    pub max_sent_data: usize,
+    pub max_recv_data: usize,
+    /// Fixed size of each read from the TLS connection. `None` keeps hyper's
+    /// adaptive read buffer.
+    pub read_chunk_size: Option<usize>,
+    /// Debugging only: write the raw, unredacted transcript to this file.
+    pub dump_transcript: Option<PathBuf>,
+    /// Also write the attestation and its secrets to this file, as a
+    /// [`SavedAttestation`], to build other presentations of the session
+    /// later with [`rebuild`]. Written owner-only: the secrets hold the
+    /// unredacted transcript.
+    pub attestation_output: Option<PathBuf>,
+    /// Return the request as disclosed in the presentation; see
+    /// [`ProveMetadata::revealed_request`].
+    pub echo_request: bool,
+    /// Reveal only these values of the JSON response body instead of all of
+    /// it. Wildcards reveal every value they select.
+    pub reveal_json_paths: Vec<JsonPath>,
+    /// Response fields to reveal and check; see [`ProveMetadata::assertions`].
+    pub reveal_and_assert: Vec<Comparison>,
+    /// When non-empty, reveal exactly these parts of the transcript and
+    /// nothing else, ignoring the header and body rules above. See
+    /// [`ProveMetadata::disclosure_warnings`].
+    pub reveal_only: Vec<RevealSelector>,
+    /// When non-empty, send these requests in order over one connection
+    /// instead of a single `method` request for the URL's path; the URL then
+    /// only names the target. Each response must be complete and
+    /// uncompressed, and the disclosure rules apply to every exchange.
+    pub requests: Vec<RequestSpec>,
+    pub warn_on_large_disclosure: Option<DisclosureThreshold>,
+    /// Fail instead of warning when `warn_on_large_disclosure` is exceeded.
+    pub strict: bool,
+}
+
+impl ProveOptions {
+    /// A `GET` of `url` revealing the full response, with the default
+    /// redactions.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            method: "GET".into(),
+            body: None,
+            content_type: "application/json".into(),
+            json_key_order: JsonKeyOrder::Verbatim,
+            expect_continue: false,
+            continue_timeout: Duration::from_secs(1),
+            flush_after_headers: false,
+            redact_headers: ["authorization", "apikey", "x-api-key"]
+                .map(String::from)
+                .to_vec(),
+            reveal_header_prefixes: Vec::new(),
+            redact_body_patterns: Vec::new(),
+            redact_sent_lines: Vec::new(),
+            redact_recv_lines: Vec::new(),
+            form_redactions: None,
+            digest_auth: None,
+            response_meta_headers: None,
+            accept_redirect: false,
+            if_none_match: None,
+            if_modified_since: None,
+            server_name: None,
+            host_header: None,
+            headers: Vec::new(),
+            user_agent: USER_AGENT.into(),
+            user_agent_disclosure: None,
+            spool_body: None,
+            accept_compressed: false,
+            max_sent_data: MAX_SENT_DATA,
+            max_recv_data: MAX_RECV_DATA,
+            read_chunk_size: None,
+            dump_transcript: None,
+            attestation_output: None,
+            echo_request: false,
+            reveal_json_paths: Vec::new(),
+            reveal_and_assert: Vec::new(),
+            reveal_only: Vec::new(),
+            requests: Vec::new(),
+            warn_on_large_disclosure: None,
+            strict: false,
+        }
+    }
+}
+
+/// One request of a sequence sent over a single connection; see
+/// [`ProveOptions::requests`]. Read from JSON such as
+/// `{"method": "POST", "path": "/login", "headers": {"X-Nonce": "1"}, "body": {"user": "a"}}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestSpec {
+    /// `GET` (the default), `POST` or `PUT`.
+    #[serde(default = "RequestSpec::default_method")]
+    pub method: String,
+    /// Path and query, e.g. `/v1/odds?regions=us`.
+    pub path: String,
+    /// Headers to send in addition to, or instead of, the default ones.
+    /// Framing headers (`Content-Length`, `Transfer-Encoding`, `Connection`)
+    /// are set by the prover and can't be given.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Request body; required for `POST` and `PUT` and only valid with them.
+    /// A string is sent as is, any other JSON value compactly serialized.
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
+}
+
+impl RequestSpec {
+    fn default_method() -> String {
+        "GET".into()
+    }
+
+    /// Reads a JSON array of request specs.
+    pub fn parse_list(json: &[u8]) -> Result<Vec<Self>> {
+        let specs: Vec<Self> =
+            serde_json::from_slice(json).context("expected a JSON array of request specs")?;
+        if specs.is_empty() {
+            anyhow::bail!("the request list is empty");
+        }
+        for spec in &specs {
+            spec.check()?;
+        }
+        Ok(specs)
+    }
+
+    fn check(&self) -> Result<()> {
+        if !matches!(self.method.as_str(), "GET" | "POST" | "PUT") {
+            anyhow::bail!(
+                "unsupported method {} for {}: expected GET, POST or PUT",
+                self.method,
+                self.path
+            );
+        }
+        if self.body.is_some() != (self.method != "GET") {
+            anyhow::bail!(
+                "{} {}: a request body is required with POST and PUT and only valid with them",
+                self.method,
+                self.path
+            );
+        }
+        if !self.path.starts_with('/') {
+            anyhow::bail!("request path must start with '/': {}", self.path);
+        }
+        for (name, value) in &self.headers {
+            check_header(name, value)?;
+        }
+        Ok(())
+    }
+
+    fn body(&self) -> Option<Bytes> {
+        self.body.as_ref().map(|body| match body {
+            serde_json::Value::String(text) => Bytes::from(text.clone()),
+            other => Bytes::from(other.to_string()),
+        })
+    }
+}
+
+/// An extra request header, parsed from `Name: Value`; see
+/// [`ProveOptions::headers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for RequestHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, value) = s
+            .split_once(':')
+            .with_context(|| format!("expected <name>: <value>: {s}"))?;
+        let (name, value) = (name.trim(), value.trim());
+        check_header(name, value)?;
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Rejects header names that aren't HTTP tokens and values with control
+/// characters, so neither can end the header line early (CRLF injection),
+/// and the framing headers the prover sets itself.
+fn check_header(name: &str, value: &str) -> Result<()> {
+    if hyper::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+        anyhow::bail!("invalid header name {name:?}");
+    }
+    if hyper::header::HeaderValue::from_str(value).is_err() {
+        anyhow::bail!("invalid value for the {name} header: control characters aren't allowed");
+    }
+    if ["content-length", "transfer-encoding", "connection"]
+        .iter()
+        .any(|framing| name.eq_ignore_ascii_case(framing))
+    {
+        anyhow::bail!("the {name} header is set by the prover and can't be given");
+    }
+    Ok(())
+}
+
+/// How much of the `User-Agent` header a presentation discloses, parsed from
+/// `reveal`, `redact` or `prefix:<N>`. The header is committed either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserAgentDisclosure {
+    /// Reveal the whole value.
+    Reveal,
+    /// Reveal the header name only.
+    Redact,
+    /// Reveal the first `N` bytes of the value, e.g. `Mozilla/5.0` only.
+    Prefix(usize),
+}
+
+impl FromStr for UserAgentDisclosure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "reveal" => Ok(Self::Reveal),
+            "redact" => Ok(Self::Redact),
+            other => {
+                let len = other.strip_prefix("prefix:").with_context(|| {
+                    format!(
+                        "unknown User-Agent disclosure {other:?}: expected reveal, redact or \
+                         prefix:<bytes>"
+                    )
+                })?;
+                let len = len
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid prefix length: {other}"))?;
+                Ok(Self::Prefix(len))
+            }
+        }
+    }
+}
+
+/// A request ready to send: its line, headers in order, and body.
+struct PreparedRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Option<Bytes>,
+}
+
+impl PreparedRequest {
+    fn builder(&self) -> hyper::http::request::Builder {
+        let mut request = Request::builder()
+            .method(self.method.as_str())
+            .uri(&self.path);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    /// Size of the request as it will appear in the sent transcript.
+    fn len(&self) -> usize {
+        let line = format!("{} {} HTTP/1.1\r\n", self.method, self.path).len();
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.len() + 2 + value.len() + 2)
+            .sum();
+        line + headers + 2 + self.body.as_ref().map_or(0, Bytes::len)
+    }
+}
+
+/// How the object keys of a JSON request body are ordered when sent. Servers
+/// that sign or hash the body need exactly the expected bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonKeyOrder {
+    /// Send the body byte for byte as given: keys keep the order they were
+    /// written in, and whitespace is untouched.
+    #[default]
+    Verbatim,
+    /// Parse the body as JSON and send it compact, with the keys of every
+    /// object sorted (by UTF-8 bytes), so equal documents produce equal bytes.
+    Sorted,
+}
+
+impl JsonKeyOrder {
+    /// Applies the ordering to a request body.
+    pub fn apply(self, body: &Bytes) -> Result<Bytes> {
+        match self {
+            Self::Verbatim => Ok(body.clone()),
+            Self::Sorted => {
+                let value: serde_json::Value = serde_json::from_slice(body)
+                    .context("request body is not JSON, cannot sort its keys")?;
+                Ok(serde_json::to_vec(&sort_keys(value))?.into())
+            }
+        }
+    }
+}
+
+impl FromStr for JsonKeyOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "verbatim" => Ok(Self::Verbatim),
+            "sorted" => Ok(Self::Sorted),
+            other => anyhow::bail!("unknown JSON key order {other:?}: expected verbatim or sorted"),
+        }
+    }
+}
+
+/// Rebuilds every object in `value` with its keys inserted in sorted order.
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(sort_keys).collect())
+        }
+        other => other,
+    }
+}
+
+/// How much of the transcript a presentation reveals.
+#[derive(Debug, Clone, Serialize)]
+pub struct DisclosureReport {
+    pub revealed_bytes: usize,
+    pub total_bytes: usize,
+    pub ratio: f64,
+    pub threshold: String,
+    pub exceeded: bool,
+}
+
+/// The result of [`prove`].
+#[derive(Debug)]
+pub struct ProveResult {
+    pub presentation: Presentation,
+    pub metadata: ProveMetadata,
+}
+
+/// An attestation saved with [`ProveOptions::attestation_output`], with the
+/// secrets that open its commitments and what [`rebuild`] needs to know
+/// about the run that made it.
+///
+/// The secrets include the full transcript, unredacted: whatever was sent
+/// (API keys, tokens) and received is in there in the clear.
+#[derive(Serialize, Deserialize)]
+pub struct SavedAttestation {
+    attestation: Attestation,
+    secrets: Secrets,
+    server: String,
+    host_header: String,
+    response_statuses: Vec<u16>,
+    shape: ResponseShape,
+    /// Whether the transcript was committed as raw lines rather than parsed
+    /// as HTTP.
+    raw_lines: bool,
+    /// Ranges committed on top of the HTTP committer's, which new rules can
+    /// reveal no others of.
+    extra_commits: ExtraCommits,
+    content_encoding: Option<String>,
+    split_writes: bool,
+}
+
+impl SavedAttestation {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    /// Decodes an attestation file. Decoding is bounded by the length of
+    /// `bytes`, so a corrupt length prefix is rejected instead of allocated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        inspect::bincode_options(bytes.len() as u64)
+            .deserialize(bytes)
+            .context("not a saved attestation")
+    }
+}
+
+/// How the final response was committed, which decides how it can be
+/// disclosed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ResponseShape {
+    /// No body: a HEAD or 304 response.
+    bodiless: bool,
+    /// The body isn't parsed: bodiless or compressed.
+    opaque: bool,
+    /// An accepted 3xx, whose `Location` is revealed with the metadata.
+    redirect: bool,
+}
+
+/// What [`prove`] knows about a run besides the presentation itself.
+///
+/// Serializes to the JSON the prover CLI prints as its summary, next to the
+/// output path. Field names and meanings are stable: fields may be added, but
+/// existing ones aren't renamed, removed or repurposed. Optional fields are
+/// omitted when unset, and the per-phase [`timings`](Self::timings) name
+/// phases that may be split further in later versions.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProveMetadata {
+    /// Server name (SNI) the certificate was verified against and the
+    /// attestation is bound to.
+    pub server: String,
+    /// `Host` header sent in the request.
+    #[serde(rename = "host")]
+    pub host_header: String,
+    pub response_status: u16,
+    /// The status of every response, in order; the last is `response_status`.
+    /// Several with [`ProveOptions::requests`].
+    pub response_statuses: Vec<u16>,
+    /// Hex-encoded public key of the notary that signed the attestation.
+    pub notary_key: String,
+    /// Notary key algorithm, e.g. `k256`.
+    pub notary_key_alg: String,
+    /// Negotiated TLS version with the target: `TLS1.2` or `TLS1.3`.
+    pub tls_version: &'static str,
+    /// Connection time recorded in the attestation (seconds since the UNIX
+    /// epoch).
+    pub connection_time: u64,
+    /// Bytes sent to the target over the attested connection.
+    pub sent_bytes: usize,
+    /// Bytes received from the target over the attested connection.
+    pub received_bytes: usize,
+    /// Ranges of the sent transcript the presentation discloses.
+    pub revealed_sent: Vec<Range<usize>>,
+    /// Ranges of the received transcript the presentation discloses.
+    pub revealed_received: Vec<Range<usize>>,
+    /// Wall-clock time spent in each phase, in order.
+    pub timings: Vec<PhaseTiming>,
+    /// Wall-clock time of the whole run, in milliseconds.
+    pub total_ms: u64,
+    /// Bytes written to [`ProveOptions::spool_body`], if set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spooled_body_bytes: Option<usize>,
+    /// The checked `reveal_and_assert` comparisons, for a manifest sidecar.
+    /// Not serialized; the CLI writes them to the manifest.
+    #[serde(skip)]
+    pub assertions: Vec<ManifestAssertion>,
+    /// Set when [`ProveOptions::warn_on_large_disclosure`] is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disclosure: Option<DisclosureReport>,
+    /// With [`ProveOptions::echo_request`]: the sent transcript exactly as
+    /// committed (after hyper's header normalization), with redacted bytes
+    /// replaced by [`verify::REDACTED_BYTE`]. Not serialized.
+    #[serde(skip)]
+    pub revealed_request: Option<Vec<u8>>,
+    /// The response's `Content-Encoding`, when it isn't `identity`. The
+    /// committed body is then the compressed bytes; verifiers inflate it after
+    /// verification.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    /// With [`ProveOptions::reveal_only`]: what verifiers won't be able to
+    /// parse from the revealed bytes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disclosure_warnings: Vec<String>,
+    /// How requests with a body were written: `single`, head and body in one
+    /// write, or `split`, the head flushed first (with
+    /// [`ProveOptions::flush_after_headers`] or `expect_continue`).
+    pub request_writes: &'static str,
+}
+
+/// Time spent in one phase of [`prove`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    /// `request preparation`, `notary connect`, `MPC setup`,
+    /// `TLS handshake`, `request`, `attestation` or `presentation`.
+    pub phase: &'static str,
+    pub ms: u64,
+}
+
+/// Aborts a spawned task when dropped, so early returns don't leave protocol
+/// tasks running. Aborting a task that already finished is a no-op.
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The step a proof is in, reported when [`Transport::timeout`] cuts it
+/// short, and the time spent in each step so far.
+struct Phase(std::sync::Mutex<PhaseLog>);
+
+struct PhaseLog {
+    current: &'static str,
+    started: Instant,
+    finished: Vec<PhaseTiming>,
+}
+
+impl Phase {
+    fn new(first: &'static str) -> Self {
+        Self(std::sync::Mutex::new(PhaseLog {
+            current: first,
+            started: Instant::now(),
+            finished: Vec::new(),
+        }))
+    }
+
+    fn enter(&self, phase: &'static str) {
+        let mut log = self.0.lock().expect("phase lock poisoned");
+        let now = Instant::now();
+        let ms = now.duration_since(log.started).as_millis() as u64;
+        let previous = std::mem::replace(&mut log.current, phase);
+        log.finished.push(PhaseTiming {
+            phase: previous,
+            ms,
+        });
+        log.started = now;
+    }
+
+    fn current(&self) -> &'static str {
+        self.0.lock().expect("phase lock poisoned").current
+    }
+
+    /// Ends the current phase and returns every phase's timing.
+    fn finish(&self) -> Vec<PhaseTiming> {
+        self.enter("done");
+        std::mem::take(&mut self.0.lock().expect("phase lock poisoned").finished)
+    }
+}
+
+/// Makes one attested request and builds its presentation.
+///
+/// With [`Transport::timeout`], the whole flow is cut short once it expires:
+/// the session tasks are aborted and the error, with status `timeout`, names
+/// the phase that was running.
+pub async fn prove(
+    transport: &Transport,
+    opts: &ProveOptions,
+    provider: &CryptoProvider,
+) -> Result<ProveResult> {
+    prove_timed(transport, opts, provider, None).await
+}
+
+/// Like [`prove`], but requests the attestation from `notary` instead of
+/// over the connection the session ran on. The session itself still runs
+/// with the notary at [`Transport::notary_host`].
+pub async fn prove_with_notary(
+    transport: &Transport,
+    opts: &ProveOptions,
+    provider: &CryptoProvider,
+    notary: &mut (dyn NotaryClient + Send),
+) -> Result<ProveResult> {
+    prove_timed(transport, opts, provider, Some(notary)).await
+}
+
+async fn prove_timed(
+    transport: &Transport,
+    opts: &ProveOptions,
+    provider: &CryptoProvider,
+    notary: Option<&mut (dyn NotaryClient + Send)>,
+) -> Result<ProveResult> {
+    let phase = Phase::new("request preparation");
+    let Some(timeout) = transport.timeout else {
+        return prove_phases(transport, opts, provider, notary, &phase).await;
+    };
+    // Dropping the timed-out future drops its task guards, which abort the
+    // session driver, the prover and the HTTP connection.
+    let phases = prove_phases(transport, opts, provider, notary, &phase);
+    match tokio::time::timeout(timeout, phases).await {
+        Ok(result) => result,
+        Err(_) => Err(StatusError::new(
+            "timeout",
+            format!(
+                "timeout: no proof after {}s, timed out during {}",
+                timeout.as_secs(),
+                phase.current()
+            ),
+        )
+        .into()),
+    }
+}
+
+/// Builds a new presentation from an attestation saved with
+/// [`ProveOptions::attestation_output`], disclosing what `opts` selects
+/// instead of what the original run did. Works offline: only the disclosure
+/// options of `opts` are used.
+///
+/// The new rules can only split the transcript where the original run
+/// committed it: along its HTTP structure (headers, JSON values, body
+/// matches), or, after raw-line redaction, along the same lines. The
+/// metadata describes the saved run, with timings for the rebuild alone.
+pub fn rebuild(
+    saved: &SavedAttestation,
+    opts: &ProveOptions,
+    provider: &CryptoProvider,
+) -> Result<ProveResult> {
+    let started = Instant::now();
+    let phase = Phase::new("presentation");
+    let transcript = saved.secrets.transcript();
+    let raw = raw_lines(opts, transcript.sent(), transcript.received());
+    match (saved.raw_lines, raw.is_some()) {
+        (true, false) => anyhow::bail!(
+            "the attestation commits to raw transcript lines: rebuild it with the same \
+             --redact-sent-line and --redact-recv-line patterns"
+        ),
+        (false, true) => anyhow::bail!(
+            "the attestation commits to the parsed HTTP transcript, which raw-line redaction \
+             can't disclose"
+        ),
+        _ => {}
+    }
+    if saved.shape.opaque
+        && (!opts.reveal_and_assert.is_empty() || !opts.reveal_json_paths.is_empty())
+    {
+        anyhow::bail!(
+            "the attested response has no parsed body: reveal-and-assert and reveal-json-path \
+             need an uncompressed JSON body"
+        );
+    }
+    if raw.is_none() {
+        let needed = extra_commits(opts, saved.shape, transcript)?;
+        if let Some((direction, range)) = needed.missing_from(&saved.extra_commits) {
+            let direction = match direction {
+                Direction::Sent => "sent",
+                Direction::Received => "received",
+            };
+            anyhow::bail!(
+                "these rules reveal {direction} bytes {range:?} separately, which the original \
+                 run didn't commit to: prove again with them instead"
+            );
+        }
+    }
+
+    let (presentation, assertions, disclosure_warnings) = build_presentation(
+        opts,
+        &saved.attestation,
+        &saved.secrets,
+        saved.shape,
+        raw.as_ref(),
+        provider,
+    )?;
+    let mut metadata = presentation_metadata(opts, saved, &presentation, provider)?;
+    metadata.timings = phase.finish();
+    metadata.total_ms = started.elapsed().as_millis() as u64;
+    metadata.assertions = assertions;
+    metadata.disclosure_warnings = disclosure_warnings;
+    Ok(ProveResult {
+        presentation,
+        metadata,
+    })
+}
+
+/// What [`dry_run`] saw of the target's answer. Serializes to the JSON the
+/// prover CLI prints for `--dry-run`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    /// Server name (SNI) the certificate was verified against.
+    pub server: String,
+    pub response_status: u16,
+    /// The status of every response, in order; the last is `response_status`.
+    pub response_statuses: Vec<u16>,
+    /// Headers of the last response, in order, as `[name, value]` pairs.
+    pub response_headers: Vec<(String, String)>,
+    /// Bytes of the last response body, after any chunked transfer coding.
+    pub response_body_bytes: usize,
+    /// Bytes sent, which a proof must fit in `max_sent_data`.
+    pub sent_bytes: usize,
+    /// Bytes received, which a proof must fit in `max_recv_data`.
+    pub received_bytes: usize,
+    pub max_sent_data: usize,
+    pub max_recv_data: usize,
+}
+
+/// Sends the requests `opts` describes to the target over plain TLS, without
+/// the notary or MPC, to check the URL, headers and data limits before an
+/// attested run.
+///
+/// The certificate is checked against the roots an attested run uses.
+/// Nothing is committed, and a later proof may see a different response.
+/// Fails with status `recv_limit_exceeded` as soon as more than
+/// `max_recv_data` bytes arrive.
+pub async fn dry_run(transport: &Transport, opts: &ProveOptions) -> Result<DryRunReport> {
+    let Target {
+        host,
+        port,
+        server_name,
+        requests,
+        ..
+    } = prepare(transport, opts).await?;
+    let sent_bytes = requests.iter().map(PreparedRequest::len).sum();
+
+    info!("Dry run: connecting to target server {}:{}", host, port);
+    let socket = connect_target(transport, &host, port).await?;
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = rustls::pki_types::ServerName::try_from(server_name.clone())
+        .with_context(|| format!("invalid server name: {server_name}"))?;
+    let tls = TlsConnector::from(Arc::new(config))
+        .connect(name, socket)
+        .await
+        .context("TLS handshake with the target failed")?;
+    let received = Arc::new(AtomicUsize::new(0));
+    let tls = CountReads {
+        inner: tls,
+        count: received.clone(),
+    };
+
+    let (mut request_sender, connection): (
+        hyper::client::conn::http1::SendRequest<UnsyncBoxBody<Bytes, Infallible>>,
+        _,
+    ) = hyper::client::conn::http1::handshake(TokioIo::new(tls)).await?;
+    let connection_task = tokio::spawn(connection);
+    let _connection_guard = AbortOnDrop(connection_task.abort_handle());
+
+    let over_limit = |received: usize| {
+        StatusError::new(
+            "recv_limit_exceeded",
+            format!(
+                "received {received} bytes, over the {} byte limit on received data; raise \
+                 --max-recv-data",
+                opts.max_recv_data
+            ),
+        )
+    };
+    let mut response_statuses = Vec::new();
+    let mut response_headers = Vec::new();
+    let mut response_body_bytes = 0;
+    for (i, prepared) in requests.iter().enumerate() {
+        let body = match &prepared.body {
+            Some(body) => Full::new(body.clone()).boxed_unsync(),
+            None => Empty::<Bytes>::new().boxed_unsync(),
+        };
+        info!("Dry run: sending {} {}", prepared.method, prepared.path);
+        let response = request_sender
+            .send_request(prepared.builder().body(body)?)
+            .await?;
+        response_statuses.push(response.status().as_u16());
+        response_headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect();
+        response_body_bytes = 0;
+        let mut body = response.into_body();
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame?.into_data() {
+                response_body_bytes += data.len();
+            }
+            let received = received.load(Ordering::Relaxed);
+            if received > opts.max_recv_data {
+                return Err(over_limit(received).into());
+            }
+        }
+        if i + 1 < requests.len() {
+            request_sender.ready().await?;
+        }
+    }
+
+    let received_bytes = received.load(Ordering::Relaxed);
+    if received_bytes > opts.max_recv_data {
+        return Err(over_limit(received_bytes).into());
+    }
+    Ok(DryRunReport {
+        server: server_name,
+        response_status: *response_statuses.last().expect("at least one request"),
+        response_statuses,
+        response_headers,
+        response_body_bytes,
+        sent_bytes,
+        received_bytes,
+        max_sent_data: opts.max_sent_data,
+        max_recv_data: opts.max_recv_data,
+    })
+}
+
+/// Where a run connects and the requests it sends there.
+struct Target {
+    host: String,
+    port: u16,
+    /// Server name (SNI) the certificate is verified against.
+    server_name: String,
+    host_header: String,
+    /// A HEAD request, whose response has no body.
+    bodiless: bool,
+    requests: Vec<PreparedRequest>,
+}
+
+/// Checks `opts` and prepares the requests to send, fetching a Digest
+/// challenge first if needed. Nothing is sent to the notary.
+async fn prepare(transport: &Transport, opts: &ProveOptions) -> Result<Target> {
+    // Parse the URL to extract host, port, path
+    let url: hyper::Uri = opts.url.parse().context("invalid URL")?;
+    let host = url.host().context("URL must have a host")?.to_string();
+    let port = url.port_u16().unwrap_or(443);
+    let path = url
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/")
+        .to_string();
+    // The URL host is where we connect; SNI and Host may be overridden
+    // independently, e.g. to test domain fronting.
+    let server_name = cert::sni_name(opts.server_name.as_deref().unwrap_or(&host))?;
+    let host_header = opts.host_header.clone().unwrap_or_else(|| host.clone());
+
+    // A HEAD response declares a Content-Length without sending a body, so it
+    // is neither checked for truncation nor parsed as a full HTTP message.
+    let bodiless = opts.method == "HEAD";
+    if opts.body.is_some() != matches!(opts.method.as_str(), "POST" | "PUT") {
+        anyhow::bail!("a request body is required with POST and PUT and only valid with them");
+    }
+    if opts.expect_continue && opts.body.is_none() {
+        anyhow::bail!("Expect: 100-continue needs a request body");
+    }
+    if bodiless && !opts.reveal_and_assert.is_empty() {
+        anyhow::bail!("reveal-and-assert needs a response body; HEAD responses have none");
+    }
+    if !opts.reveal_only.is_empty()
+        && !(opts.reveal_and_assert.is_empty() && opts.reveal_json_paths.is_empty())
+    {
+        anyhow::bail!("reveal-and-assert and reveal-json-path cannot be combined with reveal-only");
+    }
+    if bodiless && !opts.reveal_json_paths.is_empty() {
+        anyhow::bail!("reveal-json-path needs a response body; HEAD responses have none");
+    }
+    for (name, value, bounds) in [
+        ("max-sent-data", opts.max_sent_data, SENT_DATA_LIMITS),
+        ("max-recv-data", opts.max_recv_data, RECV_DATA_LIMITS),
+    ] {
+        if !bounds.contains(&value) {
+            anyhow::bail!(
+                "{name} must be between {} and {} bytes, got {value}",
+                bounds.start(),
+                bounds.end()
+            );
+        }
+    }
+    if !opts.redact_body_patterns.is_empty()
+        && (opts.response_meta_headers.is_some()
+            || !opts.reveal_json_paths.is_empty()
+            || !opts.reveal_only.is_empty())
+    {
+        anyhow::bail!(
+            "body redaction patterns need the response body revealed in full, not only \
+             response metadata, JSON paths or reveal-only selections"
+        );
+    }
+    if !(opts.redact_sent_lines.is_empty() && opts.redact_recv_lines.is_empty())
+        && (opts.response_meta_headers.is_some()
+            || !opts.reveal_json_paths.is_empty()
+            || !opts.reveal_and_assert.is_empty()
+            || !opts.reveal_only.is_empty()
+            || !opts.redact_body_patterns.is_empty()
+            || !opts.reveal_header_prefixes.is_empty()
+            || opts.form_redactions.is_some()
+            || opts.user_agent_disclosure.is_some())
+    {
+        anyhow::bail!(
+            "line redaction reveals the raw transcript and can't be combined with rules that \
+             need it parsed as HTTP"
+        );
+    }
+    if !opts.requests.is_empty() {
+        if opts.method != "GET" || opts.body.is_some() {
+            anyhow::bail!("with a request sequence, each request gives its own method and body");
+        }
+        if opts.expect_continue
+            || opts.digest_auth.is_some()
+            || opts.form_redactions.is_some()
+            || opts.spool_body.is_some()
+            || opts.accept_compressed
+        {
+            anyhow::bail!(
+                "expect-continue, Digest auth, form bodies, spooling and compressed responses \
+                 are not supported with a request sequence"
+            );
+        }
+        if opts.if_none_match.is_some() || opts.if_modified_since.is_some() {
+            anyhow::bail!(
+                "with a request sequence, give conditional headers in each request's headers"
+            );
+        }
+        if !(opts.reveal_only.is_empty()
+            && opts.reveal_json_paths.is_empty()
+            && opts.reveal_and_assert.is_empty())
+        {
+            anyhow::bail!(
+                "reveal-only, reveal-json-path and reveal-and-assert select parts of a single \
+                 response and are not supported with a request sequence"
+            );
+        }
+        for spec in &opts.requests {
+            spec.check()?;
+        }
+    }
+    check_header("User-Agent", &opts.user_agent)?;
+    for header in &opts.headers {
+        check_header(&header.name, &header.value)?;
+        if header.name.eq_ignore_ascii_case("user-agent") && opts.user_agent != USER_AGENT {
+            anyhow::bail!("the User-Agent is given both as user_agent and as an extra header");
+        }
+        if header.name.eq_ignore_ascii_case("host") {
+            anyhow::bail!("the Host header is set with host_header, not as an extra header");
+        }
+        if opts.digest_auth.is_some() && header.name.eq_ignore_ascii_case("authorization") {
+            anyhow::bail!("an Authorization header can't be given with Digest auth");
+        }
+    }
+
+    let body = opts
+        .body
+        .as_ref()
+        .map(|body| opts.json_key_order.apply(body))
+        .transpose()?;
+
+    // Request headers, in the order they are sent.
+    let mut headers = default_headers(opts, &host_header, body.as_ref(), true);
+    for (name, value) in [
+        ("If-None-Match", &opts.if_none_match),
+        ("If-Modified-Since", &opts.if_modified_since),
+    ] {
+        if let Some(value) = value {
+            check_header(name, value)?;
+            headers.push((name.into(), value.clone()));
+        }
+    }
+    let extra_headers = opts.headers.iter().map(|h| (&h.name, &h.value));
+    override_headers(&mut headers, extra_headers.clone());
+
+    // Digest auth: fetch the challenge over a separate, unattested connection
+    // and send only the authenticated request in the notarized session.
+    if let Some(credentials) = &opts.digest_auth {
+        info!("Fetching Digest challenge from {}", host);
+        let socket = connect_target(transport, &host, port).await?;
+        let challenge =
+            digest::fetch_challenge(socket, &server_name, &host_header, &opts.method, &path)
+                .await?;
+        let authorization = challenge.authorization(credentials, &opts.method, &path);
+        headers.push(("Authorization".into(), authorization));
+    }
+
+    // The requests to send, in order: the one described by `opts`, or the
+    // sequence, keeping the connection alive until the last.
+    let requests = if opts.requests.is_empty() {
+        vec![PreparedRequest {
+            method: opts.method.clone(),
+            path,
+            headers,
+            body,
+        }]
+    } else {
+        opts.requests
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let body = spec
+                    .body()
+                    .map(|body| opts.json_key_order.apply(&body))
+                    .transpose()?;
+                let close = i + 1 == opts.requests.len();
+                let mut headers = default_headers(opts, &host_header, body.as_ref(), close);
+                override_headers(&mut headers, extra_headers.clone());
+                override_headers(&mut headers, &spec.headers);
+                Ok(PreparedRequest {
+                    method: spec.method.clone(),
+                    path: spec.path.clone(),
+                    headers,
+                    body,
+                })
+            })
+            .collect::<Result<_>>()?
+    };
+
+    // The sent transcript is capped before the MPC setup; a request over the
+    // cap would fail mid-protocol, so reject it here with its size.
+    let sent_len: usize = requests.iter().map(PreparedRequest::len).sum();
+    if sent_len > opts.max_sent_data {
+        let body_len: usize = requests
+            .iter()
+            .map(|request| request.body.as_ref().map_or(0, Bytes::len))
+            .sum();
+        anyhow::bail!(
+            "request is {sent_len} bytes ({body_len} of body), over the {} byte limit on \
+             sent data (--max-sent-data)",
+            opts.max_sent_data
+        );
+    }
+
+    Ok(Target {
+        host,
+        port,
+        server_name,
+        host_header,
+        bodiless,
+        requests,
+    })
+}
+
+async fn prove_phases(
+    transport: &Transport,
+    opts: &ProveOptions,
+    provider: &CryptoProvider,
+    notary: Option<&mut (dyn NotaryClient + Send)>,
+    phase: &Phase,
+) -> Result<ProveResult> {
+    let started = Instant::now();
+    let Target {
+        host,
+        port,
+        server_name,
+        host_header,
+        bodiless,
+        requests,
+    } = prepare(transport, opts).await?;
+    let split_writes = (opts.flush_after_headers || opts.expect_continue)
+        && requests.iter().any(|request| request.body.is_some());
+
+    info!(
+        "MPC limits: {} bytes sent, {} bytes received",
+        opts.max_sent_data, opts.max_recv_data
+    );
+    phase.enter("notary connect");
+    let notary_socket: Box<dyn NotaryStream> = if let Some(path) = &transport.replay_notary {
+        warn!(
+            "Replaying the notary session recorded in {}; this is for debugging and won't \
+             produce a valid proof",
+            path.display()
+        );
+        Box::new(Replay::open(path)?)
+    } else {
+        info!(
+            "Connecting to notary at {}:{}",
+            transport.notary_host, transport.notary_port
+        );
+
+        // Connect to the Notary server via TCP, and TLS if configured.
+        status::with_timeout(
+            "notary_handshake_timeout",
+            transport.notary_handshake_timeout,
+            async {
+                let (host, port) = (transport.notary_host.as_str(), transport.notary_port);
+                let socket = with_retries(transport, "notary", || async {
+                    Ok(match &transport.notary_proxy {
+                        Some(proxy) => Either::Right(proxy.connect(host, port, None).await?),
+                        None => Either::Left(tokio::net::TcpStream::connect((host, port)).await?),
+                    })
+                })
+                .await
+                .context("failed to connect to notary server")?;
+                let Some(config) = &transport.notary_tls else {
+                    return Ok(Box::new(socket) as Box<dyn NotaryStream>);
+                };
+                let name = rustls::pki_types::ServerName::try_from(transport.notary_host.clone())
+                    .context("invalid notary host name for TLS")?;
+                let socket = TlsConnector::from(config.clone())
+                    .connect(name, socket)
+                    .await
+                    .context("TLS handshake with notary server failed")?;
+                Ok(Box::new(socket) as Box<dyn NotaryStream>)
+            },
+        )
+        .await?
+    };
+    let notary_socket: Box<dyn NotaryStream> = match &transport.record_notary {
+        Some(path) => {
+            info!("Recording the notary session to {}", path.display());
+            Box::new(Recorder::create(notary_socket, path)?)
+        }
+        None => notary_socket,
+    };
+
+    // Create a session with the notary.
+    let session = Session::new(notary_socket.compat());
+    let (driver, mut handle) = session.split();
+    let driver_task = tokio::spawn(driver);
+    let _driver_guard = AbortOnDrop(driver_task.abort_handle());
+
+    // Create a new prover and run MPC setup with the notary.
+    phase.enter("MPC setup");
+    let prover = handle.new_prover(ProverConfig::builder().build()?)?;
+    let commit_config = TlsCommitConfig::builder()
+        .protocol(
+            MpcTlsConfig::builder()
+                .max_sent_data(opts.max_sent_data)
+                .max_recv_data(opts.max_recv_data)
+                .build()?,
+        )
+        .build()?;
+    let prover = status::with_timeout("mpc_setup_timeout", transport.mpc_setup_timeout, async {
+        prover
+            .commit(commit_config)
+            .await
+            .map_err(|e| status::notary_error(e, &driver_task))
+    })
+    .await?;
+
+    phase.enter("TLS handshake");
+    info!("Connecting to target server {}:{}", host, port);
+
+    // Open TCP connection to the target server.
+    let client_socket = connect_target(transport, &host, port).await?;
+
+    // Bind prover to the server connection.
+    let (tls_connection, prover_fut) = prover
+        .connect(
+            TlsClientConfig::builder()
+                .server_name(ServerName::Dns(server_name.clone().try_into()?))
+                .build()?,
+            client_socket.compat(),
+        )
+        .await?;
+    // Watch for the server's answer to the request head, for expect_continue.
+    let (tls_connection, continue_answer) = ContinueWatch::new(tls_connection.compat());
+    let tls_connection = TokioIo::new(tls_connection);
+
+    let mut prover_task = tokio::spawn(prover_fut);
+    let _prover_guard = AbortOnDrop(prover_task.abort_handle());
+
+    // HTTP handshake over the TLS connection.
+    let (mut request_sender, connection): (
+        hyper::client::conn::http1::SendRequest<UnsyncBoxBody<Bytes, Infallible>>,
+        _,
+    ) = hyper::client::conn::http1::Builder::new()
+        .read_buf_exact_size(opts.read_chunk_size)
+        // Buffer each request into a single write unless the body is held
+        // back on purpose.
+        .writev(false)
+        .handshake(tls_connection)
+        .await?;
+    let connection_task = tokio::spawn(connection);
+    let _connection_guard = AbortOnDrop(connection_task.abort_handle());
+    phase.enter("request");
+
+    let (last, earlier) = requests.split_last().expect("at least one request");
+    let mut response_statuses = Vec::new();
+
+    // Earlier requests of a sequence: each response is read in full before
+    // the next request goes out on the same connection.
+    for prepared in earlier {
+        let body = match &prepared.body {
+            Some(body) if opts.flush_after_headers => body_after_flush(body.clone()),
+            Some(body) => Full::new(body.clone()).boxed_unsync(),
+            None => Empty::<Bytes>::new().boxed_unsync(),
+        };
+        info!("Sending {} {} to {}", prepared.method, prepared.path, host);
+        let response = match request_sender
+            .send_request(prepared.builder().body(body)?)
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => return Err(exchange_error(e, &mut prover_task, opts.max_recv_data).await),
+        };
+        let status = response.status();
+        info!("Response status: {}", status);
+        check_declared_len(&response, opts.max_recv_data)?;
+        if status != StatusCode::OK && !(opts.accept_redirect && status.is_redirection()) {
+            anyhow::bail!(
+                "server returned non-200 status to {} {}: {status}",
+                prepared.method,
+                prepared.path
+            );
+        }
+        if let Some(encoding) = response_encoding(response.headers()) {
+            anyhow::bail!(
+                "response to {} {} is {encoding}-encoded, which a request sequence can't attest",
+                prepared.method,
+                prepared.path
+            );
+        }
+        response.into_body().collect().await?;
+        request_sender.ready().await?;
+        response_statuses.push(status.as_u16());
+    }
+
+    // Build the HTTP request.
+    let body = match last.body.clone() {
+        Some(body) if opts.expect_continue => {
+            continue_body(body, continue_answer, opts.continue_timeout)
+        }
+        Some(body) if opts.flush_after_headers => body_after_flush(body),
+        Some(body) => Full::new(body).boxed_unsync(),
+        None => Empty::<Bytes>::new().boxed_unsync(),
+    };
+    let request = last.builder().body(body)?;
+
+    info!("Sending request to {}", host);
+
+    let response: hyper::Response<hyper::body::Incoming> =
+        match request_sender.send_request(request).await {
+            Ok(response) => response,
+            Err(e) => return Err(exchange_error(e, &mut prover_task, opts.max_recv_data).await),
+        };
+    let status = response.status();
+    response_statuses.push(status.as_u16());
+    check_declared_len(&response, opts.max_recv_data)?;
+    // Servers may compress even when asked not to. The body is then opaque to
+    // the transcript parser and is committed and disclosed as raw bytes.
+    let content_encoding = response_encoding(response.headers());
+    if let Some(encoding) = content_encoding.as_ref().filter(|_| !earlier.is_empty()) {
+        anyhow::bail!("response is {encoding}-encoded, which a request sequence can't attest");
+    }
+    if let Some(encoding) = &content_encoding {
+        info!("Response body is {encoding}-encoded; committing the compressed bytes");
+        if !opts.reveal_and_assert.is_empty() || !opts.reveal_json_paths.is_empty() {
+            anyhow::bail!(
+                "response is {encoding}-encoded: reveal-and-assert and reveal-json-path need \
+                 an uncompressed JSON body"
+            );
+        }
+    }
+    // A 304 answers a conditional request. Like a HEAD response it has no
+    // body, even when it repeats the resource's Content-Length.
+    let not_modified = status == StatusCode::NOT_MODIFIED
+        && (opts.if_none_match.is_some() || opts.if_modified_since.is_some());
+    if not_modified && (!opts.reveal_and_assert.is_empty() || !opts.reveal_json_paths.is_empty()) {
+        anyhow::bail!(
+            "the resource is unchanged (304 Not Modified): there is no body for \
+             reveal-and-assert or reveal-json-path"
+        );
+    }
+    let bodiless = bodiless || not_modified;
+    // Responses whose body isn't parsed: none (HEAD, 304) or compressed.
+    let opaque = bodiless || content_encoding.is_some();
+
+    info!("Response status: {}", status);
+
+    let redirect = opts.accept_redirect && status.is_redirection() && !not_modified;
+    if status != StatusCode::OK && !redirect && !not_modified {
+        anyhow::bail!("server returned non-200 status: {status}");
+    }
+    let shape = ResponseShape {
+        bodiless,
+        opaque,
+        redirect,
+    };
+
+    // Optionally spool the body to disk frame by frame as it arrives.
+    let mut spooled_body_bytes = None;
+    if let Some(spool_path) = &opts.spool_body {
+        let mut file = tokio::fs::File::create(spool_path)
+            .await
+            .with_context(|| format!("failed to create {}", spool_path.display()))?;
+        let mut body = response.into_body();
+        let mut written = 0usize;
+        while let Some(frame) = body.frame().await {
+            if let Some(data) = frame?.data_ref() {
+                file.write_all(data).await?;
+                written += data.len();
+            }
+        }
+        file.flush().await?;
+        info!("Spooled {} body bytes to {}", written, spool_path.display());
+        spooled_body_bytes = Some(written);
+    }
+
+    // Finalize prover.
+    let mut prover = prover_task
+        .await?
+        .map_err(|e| recv_limit_hint(status::notary_error(e, &driver_task), opts.max_recv_data))?;
+
+    // Refuse to attest a response that ended before its declared length.
+    let received = prover.transcript().received();
+    let last_response = http::message_ranges(received)
+        .last()
+        .map_or(0, |range| range.start);
+    if let Some((expected, received)) =
+        http::truncated_body(&received[last_response..]).filter(|_| !bodiless)
+    {
+        return Err(StatusError::new(
+            "truncated_response",
+            format!(
+                "response body truncated: Content-Length is {expected} bytes but only \
+                 {received} were received"
+            ),
+        )
+        .into());
+    }
+
+    if let Some(dump_path) = &opts.dump_transcript {
+        warn!(
+            "Writing raw transcript to {}: it contains unredacted secrets",
+            dump_path.display()
+        );
+        let sent = prover.transcript().sent();
+        let received = prover.transcript().received();
+        let dump = serde_json::json!({
+            "sent": String::from_utf8_lossy(sent),
+            "received": String::from_utf8_lossy(received),
+            "sent_hex": hex::encode(sent),
+            "received_hex": hex::encode(received),
+        });
+        write_private(dump_path, &serde_json::to_vec_pretty(&dump)?)?;
+    }
+
+    // Commit to transcript segments.
+    let mut builder = TranscriptCommitConfig::builder(prover.transcript());
+    let raw = raw_lines(
+        opts,
+        prover.transcript().sent(),
+        prover.transcript().received(),
+    );
+    if let Some((sent, received)) = &raw {
+        warn!(
+            "Redacting {} sent and {} received lines of the raw transcript; line redaction is \
+             coarser than structured redaction and reveals any secret a pattern misses",
+            sent.hidden_count(),
+            received.hidden_count()
+        );
+        sent.commit(&mut builder, Direction::Sent)?;
+        received.commit(&mut builder, Direction::Received)?;
+    } else if opaque {
+        let request = parse_request(prover.transcript().sent())?;
+        DefaultHttpCommitter::default().commit_request(&mut builder, Direction::Sent, &request)?;
+        opaque_response(prover.transcript().received(), bodiless)?.commit(&mut builder)?;
+    } else {
+        let transcript = HttpTranscript::parse(prover.transcript())?;
+        DefaultHttpCommitter::default().commit_transcript(&mut builder, &transcript)?;
+    }
+    // Parts of those ranges that the rules reveal alone need commitments of
+    // their own.
+    let extra = match &raw {
+        Some(_) => ExtraCommits::default(),
+        None => extra_commits(opts, shape, prover.transcript())?,
+    };
+    extra.commit(&mut builder)?;
+    let transcript_commit = builder.build()?;
+
+    // Build attestation request config.
+    let mut builder = RequestConfig::builder();
+    builder.transcript_commit(transcript_commit);
+    let request_config = builder.build()?;
+
+    // Build prove config.
+    let mut builder = ProveConfig::builder(prover.transcript());
+    if let Some(config) = request_config.transcript_commit() {
+        builder.transcript_commit(config.clone());
+    }
+    let disclosure_config = builder.build()?;
+
+    let ProverOutput {
+        transcript_commitments,
+        transcript_secrets,
+        ..
+    } = prover
+        .prove(&disclosure_config)
+        .await
+        .map_err(|e| status::notary_error(e, &driver_task))?;
+
+    let prover_transcript = prover.transcript().clone();
+    let tls_transcript = prover.tls_transcript().clone();
+    prover
+        .close()
+        .await
+        .map_err(|e| status::notary_error(e, &driver_task))?;
+
+    // Build attestation request.
+    let mut builder = AttestationRequest::builder(&request_config);
+    builder
+        .server_name(ServerName::Dns(server_name.clone().try_into()?))
+        .handshake_data(HandshakeData {
+            certs: tls_transcript
+                .server_cert_chain()
+                .expect("server cert chain is present")
+                .to_vec(),
+            sig: tls_transcript
+                .server_signature()
+                .expect("server signature is present")
+                .clone(),
+            binding: tls_transcript.certificate_binding().clone(),
+        })
+        .transcript(prover_transcript)
+        .transcript_commitments(transcript_secrets.clone(), transcript_commitments.clone());
+
+    let (request, secrets) = builder.build(provider)?;
+
+    // Close session and reclaim socket.
+    phase.enter("attestation");
+    handle.close();
+    let socket = driver_task.await??;
+    let attestation = match notary {
+        Some(notary) => attest(notary, &request, transport, provider).await?,
+        None => {
+            let mut notary = SocketNotary::new(socket);
+            attest(&mut notary, &request, transport, provider).await?
+        }
+    };
+
+    info!("Attestation received and validated. Building presentation...");
+    phase.enter("presentation");
+
+    let saved = SavedAttestation {
+        attestation,
+        secrets,
+        server: server_name,
+        host_header,
+        response_statuses,
+        shape,
+        raw_lines: raw.is_some(),
+        extra_commits: extra,
+        content_encoding,
+        split_writes,
+    };
+    if let Some(path) = &opts.attestation_output {
+        warn!(
+            "Writing the attestation and its secrets to {}: they contain the unredacted \
+             transcript",
+            path.display()
+        );
+        write_private(path, &saved.to_bytes()?)?;
+    }
+
+    let (presentation, assertions, disclosure_warnings) = build_presentation(
+        opts,
+        &saved.attestation,
+        &saved.secrets,
+        saved.shape,
+        raw.as_ref(),
+        provider,
+    )?;
+
+    let mut metadata = presentation_metadata(opts, &saved, &presentation, provider)?;
+    metadata.timings = phase.finish();
+    metadata.total_ms = started.elapsed().as_millis() as u64;
+    metadata.spooled_body_bytes = spooled_body_bytes;
+    metadata.assertions = assertions;
+    metadata.disclosure_warnings = disclosure_warnings;
+    Ok(ProveResult {
+        presentation,
+        metadata,
+    })
+}
+
+/// Sends `request` to `notary` and checks that the attestation it returns
+/// is signed over that request.
+async fn attest(
+    notary: &mut (dyn NotaryClient + Send),
+    request: &AttestationRequest,
+    transport: &Transport,
+    provider: &CryptoProvider,
+) -> Result<Attestation> {
+    let attestation = status::with_timeout(
+        "attestation_timeout",
+        transport.attestation_timeout,
+        notary.attest(request),
+    )
+    .await?;
+    request.validate(&attestation, provider)?;
+    Ok(attestation)
+}
+
+/// The disclosure rules of `opts`, except the JSON paths, which are only
+/// resolved once the transcript is parsed. `redirect` reveals `Location`
+/// along with the response metadata.
+fn disclosure_rules(opts: &ProveOptions, redirect: bool) -> DisclosureBuilder {
+    let mut disclosure = DisclosureBuilder::new();
+    for name in &opts.redact_headers {
+        disclosure.redact_header(name);
+    }
+    if opts.digest_auth.is_some() {
+        disclosure.redact_header("authorization");
+    }
+    for prefix in &opts.reveal_header_prefixes {
+        disclosure.reveal_header_prefix(prefix);
+    }
+    match opts.user_agent_disclosure {
+        Some(UserAgentDisclosure::Reveal) => {
+            disclosure.reveal_request_header("user-agent");
+        }
+        Some(UserAgentDisclosure::Redact) => {
+            disclosure.redact_header("user-agent");
+        }
+        Some(UserAgentDisclosure::Prefix(len)) => {
+            disclosure.reveal_header_prefix(&HeaderPrefix {
+                name: "user-agent".into(),
+                len,
+            });
+        }
+        None => {}
+    }
+    for pattern in &opts.redact_body_patterns {
+        disclosure.redact_body_regex(pattern.clone());
+    }
+    if let Some(fields) = &opts.form_redactions {
+        disclosure.reveal_form_body(fields);
+    }
+    match &opts.response_meta_headers {
+        // Reveal the status line and selected headers only.
+        Some(names) => {
+            for name in names {
+                disclosure.reveal_header(name);
+            }
+            if redirect {
+                disclosure.reveal_header("location");
+            }
+        }
+        // Reveal full response (headers + body), unless only some JSON
+        // values of the body are to be revealed.
+        None if opts.reveal_json_paths.is_empty() => {
+            disclosure.reveal_body();
+        }
+        None => {}
+    }
+    disclosure
+}
+
+/// The ranges the disclosure of `opts` reveals apart from the HTTP
+/// committer's commitments; see [`ExtraCommits`].
+fn extra_commits(
+    opts: &ProveOptions,
+    shape: ResponseShape,
+    transcript: &Transcript,
+) -> Result<ExtraCommits> {
+    if !opts.reveal_only.is_empty() {
+        let http = if shape.opaque {
+            None
+        } else {
+            Some(HttpTranscript::parse(transcript)?)
+        };
+        return reveal_only_commits(
+            &opts.reveal_only,
+            transcript.sent(),
+            transcript.received(),
+            http.as_ref(),
+        );
+    }
+    let rules = disclosure_rules(opts, shape.redirect);
+    if shape.opaque {
+        rules.extra_commits_bodiless(&parse_request(transcript.sent())?)
+    } else {
+        rules.extra_commits(&HttpTranscript::parse(transcript)?)
+    }
+}
+
+/// Builds a presentation of `attestation` disclosing what `opts` selects of
+/// the transcript in `secrets`. Also returns the checked `reveal_and_assert`
+/// comparisons and the `reveal_only` warnings.
+fn build_presentation(
+    opts: &ProveOptions,
+    attestation: &Attestation,
+    secrets: &Secrets,
+    shape: ResponseShape,
+    raw: Option<&(RawLines, RawLines)>,
+    provider: &CryptoProvider,
+) -> Result<(Presentation, Vec<ManifestAssertion>, Vec<String>)> {
+    let proof_builder = secrets.transcript_proof_builder();
+
+    let mut disclosure = disclosure_rules(opts, shape.redirect);
+    let mut assertions = Vec::new();
+    let mut disclosure_warnings = Vec::new();
+    let transcript_proof = if let Some((sent, received)) = raw {
+        let mut proof_builder = proof_builder;
+        sent.reveal_in(&mut proof_builder, Direction::Sent)?;
+        received.reveal_in(&mut proof_builder, Direction::Received)?;
+        proof_builder.build()?
+    } else if !opts.reveal_only.is_empty() {
+        let transcript = secrets.transcript();
+        let http_transcript = if shape.opaque {
+            None
+        } else {
+            Some(HttpTranscript::parse(transcript)?)
+        };
+        let (proof, warnings) = reveal_only(
+            &opts.reveal_only,
+            transcript.sent(),
+            transcript.received(),
+            http_transcript.as_ref(),
+            proof_builder,
+        )?;
+        for warning in &warnings {
+            warn!("reveal-only: {warning}");
+        }
+        disclosure_warnings = warnings;
+        proof
+    } else if shape.opaque {
+        let request = parse_request(secrets.transcript().sent())?;
+        let response = opaque_response(secrets.transcript().received(), shape.bodiless)?;
+        disclosure.finish_bodiless(&request, &response, proof_builder)?
+    } else {
+        let http_transcript = HttpTranscript::parse(secrets.transcript())?;
+        for path in &opts.reveal_json_paths {
+            for dotted in expand_json_path(&http_transcript, path)? {
+                disclosure.reveal_json_path(&dotted);
+            }
+        }
+        for comparison in &opts.reveal_and_assert {
+            let path = comparison.path.to_dotted().with_context(|| {
+                format!(
+                    "reveal-and-assert path must name a single field: {}",
+                    comparison.path
+                )
+            })?;
+            // Check the assertion before attesting anything that would fail it.
+            let range = json_value_range(&http_transcript, &path)?;
+            let actual = parse_revealed_value(&secrets.transcript().received()[range.clone()]);
+            comparison
+                .check(&actual)
+                .map_err(|e| StatusError::new("assertion_failed", e))?;
+            disclosure.reveal_json_path(&path);
+            assertions.push(ManifestAssertion::new(comparison, range));
+        }
+        disclosure.finish(&http_transcript, proof_builder)?
+    };
+
+    let mut pres_builder = attestation.presentation_builder(provider);
+    pres_builder
+        .identity_proof(secrets.identity_proof())
+        .transcript_proof(transcript_proof);
+
+    Ok((pres_builder.build()?, assertions, disclosure_warnings))
+}
+
+/// The metadata of a presentation built from `saved`, with its disclosure
+/// measured as a verifier will see it. Timings, assertions, warnings and the
+/// spooled body are left for the caller to fill in.
+fn presentation_metadata(
+    opts: &ProveOptions,
+    saved: &SavedAttestation,
+    presentation: &Presentation,
+    provider: &CryptoProvider,
+) -> Result<ProveMetadata> {
+    let disclosed = verify::verify(presentation.clone(), provider)?;
+    let disclosure = match opts.warn_on_large_disclosure {
+        Some(threshold) => Some(measure_disclosure(&disclosed, threshold, opts.strict)?),
+        None => None,
+    };
+    let revealed_request = opts.echo_request.then(|| disclosed.sent.clone());
+
+    let key = presentation.verifying_key();
+    let transcript = saved.secrets.transcript();
+    Ok(ProveMetadata {
+        server: saved.server.clone(),
+        host_header: saved.host_header.clone(),
+        response_status: saved.response_statuses.last().copied().unwrap_or_default(),
+        response_statuses: saved.response_statuses.clone(),
+        notary_key: hex::encode(&key.data),
+        notary_key_alg: key.alg.to_string(),
+        tls_version: disclosed.tls_version,
+        connection_time: disclosed.time,
+        sent_bytes: transcript.sent().len(),
+        received_bytes: transcript.received().len(),
+        revealed_sent: disclosed.sent_authed.clone(),
+        revealed_received: disclosed.received_authed.clone(),
+        timings: Vec::new(),
+        total_ms: 0,
+        spooled_body_bytes: None,
+        assertions: Vec::new(),
+        disclosure,
+        revealed_request,
+        content_encoding: saved.content_encoding.clone(),
+        disclosure_warnings: Vec::new(),
+        request_writes: if saved.split_writes {
+            "split"
+        } else {
+            "single"
+        },
+    })
+}
+
+/// A request body that isn't ready on its first poll, so hyper flushes the
+/// request head before writing it.
+fn body_after_flush(body: Bytes) -> UnsyncBoxBody<Bytes, Infallible> {
+    let frame = async move {
+        tokio::task::yield_now().await;
+        Ok::<_, Infallible>(Frame::data(body))
+    };
+    StreamBody::new(futures::stream::once(frame)).boxed_unsync()
+}
+
+/// Splits the transcript for [`ProveOptions::redact_sent_lines`] and
+/// [`ProveOptions::redact_recv_lines`], or `None` when neither is set.
+fn raw_lines(opts: &ProveOptions, sent: &[u8], received: &[u8]) -> Option<(RawLines, RawLines)> {
+    if opts.redact_sent_lines.is_empty() && opts.redact_recv_lines.is_empty() {
+        return None;
+    }
+    let mut redacted_headers: Vec<String> = opts
+        .redact_headers
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    if opts.digest_auth.is_some() {
+        redacted_headers.push("authorization".into());
+    }
+    Some((
+        RawLines::split(sent, &opts.redact_sent_lines, &redacted_headers),
+        RawLines::split(received, &opts.redact_recv_lines, &[]),
+    ))
+}
+
+/// Parses a response whose body isn't parsed: absent for HEAD, otherwise kept
+/// as raw bytes.
+fn opaque_response(received: &[u8], bodiless: bool) -> Result<BodilessResponse> {
+    if bodiless {
+        BodilessResponse::parse(received)
+    } else {
+        BodilessResponse::parse_with_raw_body(received)
+    }
+}
+
+/// A connection to the target, direct or through a SOCKS5 proxy.
+type TargetStream = Either<tokio::net::TcpStream, Socks5Stream<tokio::net::TcpStream>>;
+
+/// Opens a TCP connection to the target, through the SOCKS5 or HTTP proxy
+/// and the encrypted resolver if configured, retrying failed attempts.
+async fn connect_target(transport: &Transport, host: &str, port: u16) -> Result<TargetStream> {
+    with_retries(transport, host, || {
+        connect_target_once(transport, host, port)
+    })
+    .await
+}
+
+async fn connect_target_once(transport: &Transport, host: &str, port: u16) -> Result<TargetStream> {
+    if let Some(proxy) = &transport.http_proxy {
+        info!(
+            "Tunneling to {}:{} via HTTP proxy {}",
+            host, port, proxy.addr
+        );
+        return Ok(Either::Left(proxy.connect(host, port).await?));
+    }
+    if let Some(proxy) = &transport.proxy {
+        info!(
+            "Connecting to {}:{} via SOCKS5 proxy {}",
+            host, port, proxy.addr
+        );
+        let stream = proxy
+            .connect(host, port, transport.resolver.as_ref())
+            .await?;
+        return Ok(Either::Right(stream));
+    }
+    Ok(Either::Left(match &transport.resolver {
+        Some(resolver) => {
+            let addr = resolver.resolve(host, port).await?;
+            info!("Resolved {} to {} via encrypted DNS", host, addr);
+            tokio::net::TcpStream::connect(addr).await?
+        }
+        None => tokio::net::TcpStream::connect((host, port)).await?,
+    }))
+}
+
+/// Runs a connection attempt, retrying failures up to
+/// `transport.connect_retries` times with exponential backoff and jitter.
+async fn with_retries<T, F, Fut>(transport: &Transport, peer: &str, mut connect: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match connect().await {
+            Ok(connection) => return Ok(connection),
+            Err(e) if attempt < transport.connect_retries => {
+                let backoff = transport
+                    .connect_backoff
+                    .saturating_mul(1 << attempt.min(16));
+                let jitter = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 2);
+                let delay = backoff + Duration::from_millis(jitter);
+                attempt += 1;
+                warn!(
+                    "Connecting to {} failed: {:#}; retry {}/{} in {}ms",
+                    peer,
+                    e,
+                    attempt,
+                    transport.connect_retries,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fails early, with a hint, when a response declares a body larger than the
+/// MPC receive limit; the protocol would otherwise fail partway through it.
+fn check_declared_len<B>(response: &hyper::Response<B>, max_recv_data: usize) -> Result<()> {
+    let declared = response
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.trim().parse::<usize>().ok());
+    match declared {
+        Some(len) if len > max_recv_data => Err(StatusError::new(
+            "recv_limit_exceeded",
+            format!(
+                "response body is {len} bytes, over the {max_recv_data} byte limit on received \
+                 data; raise --max-recv-data"
+            ),
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Counts the bytes read from a connection, for [`dry_run`].
+struct CountReads<T> {
+    inner: T,
+    count: Arc<AtomicUsize>,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CountReads<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            self.count
+                .fetch_add(buf.filled().len() - before, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for CountReads<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Explains a failed HTTP exchange. hyper only sees the connection drop when
+/// the MPC protocol fails underneath it, so if the prover task has already
+/// failed, its error is reported instead.
+async fn exchange_error<T, E: Into<anyhow::Error>>(
+    err: hyper::Error,
+    prover_task: &mut tokio::task::JoinHandle<Result<T, E>>,
+    max_recv_data: usize,
+) -> anyhow::Error {
+    if prover_task.is_finished() {
+        if let Ok(Err(e)) = prover_task.await {
+            return recv_limit_hint(e.into(), max_recv_data);
+        }
+    }
+    err.into()
+}
+
+/// Tags an MPC error that reports the received data going over the limit
+/// with a hint to raise it.
+fn recv_limit_hint(err: anyhow::Error, max_recv_data: usize) -> anyhow::Error {
+    let message = format!("{err:#}");
+    let lower = message.to_lowercase();
+    let received = lower.contains("recv") || lower.contains("received");
+    if received && (lower.contains("exceed") || lower.contains("limit")) {
+        StatusError::new(
+            "recv_limit_exceeded",
+            format!(
+                "{message}: the response is larger than the {max_recv_data} byte limit on \
+                 received data; raise --max-recv-data"
+            ),
+        )
+        .into()
+    } else {
+        err
+    }
+}
+
+/// A response's `Content-Encoding`, lowercase, unless absent or `identity`.
+fn response_encoding(headers: &hyper::HeaderMap) -> Option<String> {
+    headers
+        .get(hyper::header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty() && value != "identity")
+}
+
+/// The headers every request is sent with, in order. `close` ends the
+/// connection after this request.
+fn default_headers(
+    opts: &ProveOptions,
+    host_header: &str,
+    body: Option<&Bytes>,
+    close: bool,
+) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(body) = body {
+        headers.push(("Content-Type".into(), opts.content_type.clone()));
+        headers.push(("Content-Length".into(), body.len().to_string()));
+        if opts.expect_continue {
+            headers.push(("Expect".into(), "100-continue".into()));
+        }
+    }
+    let accept_encoding = if opts.accept_compressed {
+        "gzip, deflate, br"
+    } else {
+        "identity"
+    };
+    let connection = if close { "close" } else { "keep-alive" };
+    headers.extend([
+        ("Host".into(), host_header.to_string()),
+        ("Accept".into(), "application/json".into()),
+        ("Accept-Encoding".into(), accept_encoding.into()),
+        ("Connection".into(), connection.into()),
+        ("User-Agent".into(), opts.user_agent.clone()),
+    ]);
+    headers
+}
+
+/// Appends `extra` to `headers`, each replacing any header of the same name.
+fn override_headers<'a>(
+    headers: &mut Vec<(String, String)>,
+    extra: impl IntoIterator<Item = (&'a String, &'a String)>,
+) {
+    for (name, value) in extra {
+        headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        headers.push((name.clone(), value.clone()));
+    }
+}
+
+fn measure_disclosure(
+    disclosed: &VerifiedData,
+    threshold: DisclosureThreshold,
+    strict: bool,
+) -> Result<DisclosureReport> {
+    let total = disclosed.sent.len() + disclosed.received.len();
+    let revealed: usize = disclosed
+        .sent_authed
+        .iter()
+        .chain(&disclosed.received_authed)
+        .map(|range| range.len())
+        .sum();
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        revealed as f64 / total as f64
+    };
+    let exceeded = threshold.is_exceeded(revealed, total);
+    if exceeded {
+        let message = format!(
+            "presentation reveals {revealed} of {total} transcript bytes ({:.1}%), \
+             over the --warn-on-large-disclosure threshold of {threshold}",
+            ratio * 100.0
+        );
+        if strict {
+            return Err(StatusError::new("large_disclosure", message).into());
+        }
+        warn!("{message}");
+    }
+    Ok(DisclosureReport {
+        revealed_bytes: revealed,
+        total_bytes: total,
+        ratio,
+        threshold: threshold.to_string(),
+        exceeded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use tlsn::{
+        attestation::{signing::Secp256k1Signer, AttestationConfig},
+        connection::{
+            CertBinding, CertBindingV1_2, ConnectionInfo, KeyType, ServerEphemKey, ServerSignature,
+            SignatureScheme, TlsVersion, TranscriptLength,
+        },
+        webpki::CertificateDer,
+    };
+
+    use super::*;
+    use crate::notary::NotaryError;
+
+    const SENT: &[u8] = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    const RECEIVED: &[u8] = b"HTTP/1.1 204 No Content\r\n\r\n";
+
+    fn server_key() -> ServerEphemKey {
+        ServerEphemKey {
+            typ: KeyType::SECP256R1,
+            key: vec![4; 65],
+        }
+    }
+
+    /// A request over a made-up handshake: the notary only commits to the
+    /// handshake, so nothing here needs to be a real TLS session.
+    fn request(provider: &CryptoProvider) -> AttestationRequest {
+        let mut builder = AttestationRequest::builder(&RequestConfig::default());
+        builder
+            .server_name(ServerName::Dns("example.com".try_into().unwrap()))
+            .handshake_data(HandshakeData {
+                certs: vec![CertificateDer(vec![0x30, 0x00])],
+                sig: ServerSignature {
+                    scheme: SignatureScheme::ECDSA_NISTP256_SHA256,
+                    sig: vec![1; 64],
+                },
+                binding: CertBinding::V1_2(CertBindingV1_2 {
+                    client_random: [2; 32],
+                    server_random: [3; 32],
+                    server_ephemeral_key: server_key(),
+                }),
+            })
+            .transcript(Transcript::new(SENT, RECEIVED))
+            .transcript_commitments(Vec::new(), Vec::new());
+        builder.build(provider).unwrap().0
+    }
+
+    /// Signs attestations in process with a fixed key.
+    struct LocalNotary {
+        provider: CryptoProvider,
+        requests: usize,
+    }
+
+    impl LocalNotary {
+        fn new() -> Self {
+            let mut provider = CryptoProvider::default();
+            provider
+                .signer
+                .set_signer(Box::new(Secp256k1Signer::new(&[7; 32]).unwrap()));
+            Self {
+                provider,
+                requests: 0,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl NotaryClient for LocalNotary {
+        async fn attest(&mut self, request: &AttestationRequest) -> Result<Attestation> {
+            self.requests += 1;
+            let mut config = AttestationConfig::builder();
+            config.supported_signature_algs(Vec::from_iter(self.provider.signer.supported_algs()));
+            let config = config.build()?;
+            let mut builder = Attestation::builder(&config).accept_request(request.clone())?;
+            builder
+                .connection_info(ConnectionInfo {
+                    time: 1_700_000_000,
+                    version: TlsVersion::V1_2,
+                    transcript_length: TranscriptLength {
+                        sent: SENT.len() as u32,
+                        received: RECEIVED.len() as u32,
+                    },
+                })
+                .server_ephemeral_key(server_key());
+            Ok(builder.build(&self.provider)?)
+        }
+    }
+
+    /// Rejects every request, as a rate-limiting notary would.
+    struct RejectingNotary;
+
+    #[async_trait]
+    impl NotaryClient for RejectingNotary {
+        async fn attest(&mut self, _: &AttestationRequest) -> Result<Attestation> {
+            Err(NotaryError::new("rate-limited", "at most 10 sessions per minute").into())
+        }
+    }
+
+    #[tokio::test]
+    async fn attests_with_an_injected_notary() {
+        let provider = CryptoProvider::default();
+        let request = request(&provider);
+        let mut notary = LocalNotary::new();
+        let transport = Transport::new("notary.invalid", 7047);
+
+        // `attest` checks the attestation against the request it sent.
+        attest(&mut notary, &request, &transport, &provider)
+            .await
+            .unwrap();
+        assert_eq!(notary.requests, 1);
+    }
+
+    #[tokio::test]
+    async fn notary_rejections_reach_the_caller() {
+        let provider = CryptoProvider::default();
+        let request = request(&provider);
+        let transport = Transport::new("notary.invalid", 7047);
+
+        let err = attest(&mut RejectingNotary, &request, &transport, &provider)
+            .await
+            .unwrap_err();
+        let rejection = err.downcast_ref::<NotaryError>().unwrap();
+        assert_eq!(rejection.status(), "notary_rate_limited");
+    }
+
+    #[test]
+    fn saved_attestation_rejects_an_oversized_length_prefix() {
+        // The signature algorithm, then a length prefix for the signature
+        // bytes far beyond the input.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"abc");
+
+        let err = SavedAttestation::from_bytes(&bytes).err().unwrap();
+        let err = err.downcast_ref::<bincode::Error>().unwrap();
+        assert!(matches!(**err, bincode::ErrorKind::SizeLimit), "{err}");
+    }
+}
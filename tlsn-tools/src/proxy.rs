@@ -0,0 +1,229 @@
+//! SOCKS5 and HTTP CONNECT proxies for the prover's outgoing connections.
+//!
+//! The proxy only carries the TCP stream: TLS to the target still runs inside
+//! the MPC session, so the proxy sees ciphertext just like any other network
+//! hop, and the attestation is unaffected by the route. The proxy handshake
+//! happens on the stream before it is handed to the prover, so it never
+//! enters the transcript.
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::http;
+use crate::resolve::EncryptedResolver;
+
+/// A SOCKS5 proxy endpoint, parsed from `socks5://[user:pass@]host:port` or
+/// `socks5h://...`.
+///
+/// With `socks5h` the proxy resolves target host names; with `socks5` they
+/// are resolved locally (through the encrypted resolver if one is configured)
+/// and the proxy is given the address.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+    /// `host:port` of the proxy.
+    pub addr: String,
+    pub credentials: Option<(String, String)>,
+    pub remote_dns: bool,
+}
+
+impl fmt::Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("addr", &self.addr)
+            .field(
+                "credentials",
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(|(user, _)| (user, "<redacted>")),
+            )
+            .field("remote_dns", &self.remote_dns)
+            .finish()
+    }
+}
+
+impl std::str::FromStr for Socks5Proxy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .context("proxy must look like socks5://host:port")?;
+        let remote_dns = match scheme.to_ascii_lowercase().as_str() {
+            "socks5" => false,
+            "socks5h" => true,
+            other => {
+                anyhow::bail!("unsupported proxy scheme {other:?}; expected socks5 or socks5h")
+            }
+        };
+        let rest = rest.trim_end_matches('/');
+        let (credentials, addr) = match rest.rsplit_once('@') {
+            Some((userinfo, addr)) => {
+                let (user, pass) = userinfo
+                    .split_once(':')
+                    .context("proxy credentials must be user:password")?;
+                // Percent-decoded; `+` is literal here, unlike in forms.
+                let decode = |s: &str| {
+                    let s = s.replace('+', "%2B");
+                    url::form_urlencoded::parse(format!("x={s}").as_bytes())
+                        .next()
+                        .map(|(_, v)| v.into_owned())
+                        .unwrap_or_default()
+                };
+                (Some((decode(user), decode(pass))), addr)
+            }
+            None => (None, rest),
+        };
+        let port = addr
+            .rsplit_once(':')
+            .and_then(|(host, port)| (!host.is_empty()).then_some(port))
+            .with_context(|| format!("proxy address must be host:port, got {addr:?}"))?;
+        port.parse::<u16>()
+            .with_context(|| format!("invalid proxy port {port:?}"))?;
+        Ok(Self {
+            addr: addr.to_string(),
+            credentials,
+            remote_dns,
+        })
+    }
+}
+
+impl Socks5Proxy {
+    /// Opens a connection to `host:port` through the proxy.
+    pub async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        resolver: Option<&EncryptedResolver>,
+    ) -> Result<Socks5Stream<TcpStream>> {
+        let target = if self.remote_dns {
+            tokio_socks::TargetAddr::Domain(host.to_string().into(), port)
+        } else {
+            let addr = match resolver {
+                Some(resolver) => resolver.resolve(host, port).await?,
+                None => tokio::net::lookup_host((host, port))
+                    .await
+                    .with_context(|| format!("failed to resolve {host}"))?
+                    .next()
+                    .with_context(|| format!("no addresses found for {host}"))?,
+            };
+            tokio_socks::TargetAddr::Ip(addr)
+        };
+        let stream = match &self.credentials {
+            Some((user, pass)) => {
+                Socks5Stream::connect_with_password(self.addr.as_str(), target, user, pass).await
+            }
+            None => Socks5Stream::connect(self.addr.as_str(), target).await,
+        };
+        stream.with_context(|| {
+            format!(
+                "failed to connect to {host}:{port} via SOCKS5 proxy {}",
+                self.addr
+            )
+        })
+    }
+}
+
+/// Environment variable holding the `Proxy-Authorization` value for
+/// [`HttpProxy`], e.g. `Basic dXNlcjpwYXNz`. Read from the environment so
+/// credentials stay out of command lines and shell history.
+pub const PROXY_AUTHORIZATION_ENV: &str = "DJINN_PROXY_AUTHORIZATION";
+
+/// Longest CONNECT response head accepted from the proxy.
+const MAX_CONNECT_RESPONSE: usize = 16 * 1024;
+
+/// An HTTP proxy that tunnels with `CONNECT`, parsed from `http://host:port`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HttpProxy {
+    /// `host:port` of the proxy.
+    pub addr: String,
+    /// `Proxy-Authorization` header value sent with the `CONNECT`.
+    pub authorization: Option<String>,
+}
+
+impl fmt::Debug for HttpProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpProxy")
+            .field("addr", &self.addr)
+            .field(
+                "authorization",
+                &self.authorization.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+impl std::str::FromStr for HttpProxy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let addr = s
+            .strip_prefix("http://")
+            .context("HTTP proxy must look like http://host:port")?
+            .trim_end_matches('/');
+        anyhow::ensure!(
+            !addr.contains('@'),
+            "put HTTP proxy credentials in {PROXY_AUTHORIZATION_ENV}, not in the URL"
+        );
+        let port = addr
+            .rsplit_once(':')
+            .and_then(|(host, port)| (!host.is_empty()).then_some(port))
+            .with_context(|| format!("proxy address must be host:port, got {addr:?}"))?;
+        port.parse::<u16>()
+            .with_context(|| format!("invalid proxy port {port:?}"))?;
+        Ok(Self {
+            addr: addr.to_string(),
+            authorization: None,
+        })
+    }
+}
+
+impl HttpProxy {
+    /// Opens a tunnel to `host:port` through the proxy. The returned stream
+    /// is positioned right after the proxy's response, ready for TLS.
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect(self.addr.as_str())
+            .await
+            .with_context(|| format!("failed to connect to HTTP proxy {}", self.addr))?;
+
+        let authority = if host.contains(':') {
+            format!("[{host}]:{port}")
+        } else {
+            format!("{host}:{port}")
+        };
+        let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+        if let Some(authorization) = &self.authorization {
+            request.push_str(&format!("Proxy-Authorization: {authorization}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read the response head a byte at a time: anything past it belongs
+        // to the tunnel.
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            anyhow::ensure!(
+                head.len() < MAX_CONNECT_RESPONSE,
+                "HTTP proxy response head is over {MAX_CONNECT_RESPONSE} bytes"
+            );
+            let byte = stream
+                .read_u8()
+                .await
+                .context("HTTP proxy closed the connection during CONNECT")?;
+            head.push(byte);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        match http::status_code(&head) {
+            Some(code) if (200..300).contains(&code) => Ok(stream),
+            Some(407) => anyhow::bail!(
+                "HTTP proxy requires authentication ({status_line}); set {PROXY_AUTHORIZATION_ENV}"
+            ),
+            _ => anyhow::bail!("HTTP proxy refused CONNECT to {authority}: {status_line}"),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Token-bucket rate limiting for repeated proofs against quota-limited APIs.
+//!
+//! A [`Rate`] of `n/<duration>` allows bursts of up to `n` requests and
+//! refills at `n` per `duration`. [`RateLimiter::acquire`] reserves a token
+//! and sleeps until it is due, so concurrent callers are served in the order
+//! they asked.
+
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+/// A request quota: `count` requests per `per`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    /// Parses `<n>/<duration>`, where the duration is a unit (`s`, `m`, `h`)
+    /// optionally preceded by a count: `10/s`, `500/1h`, `3/90s`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (count, per) = s
+            .split_once('/')
+            .with_context(|| format!("expected <n>/<duration>, got {s:?}"))?;
+        let count: u32 = count
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid request count {count:?}"))?;
+        if count == 0 {
+            anyhow::bail!("request count must be at least 1");
+        }
+
+        let per = per.trim();
+        let split = per.find(|c: char| !c.is_ascii_digit()).unwrap_or(per.len());
+        let (amount, unit) = per.split_at(split);
+        let amount: u64 = if amount.is_empty() {
+            1
+        } else {
+            amount.parse()?
+        };
+        let unit_secs = match unit {
+            "ms" => None,
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(3600),
+            _ => anyhow::bail!("invalid duration {per:?}: expected a unit of ms, s, m or h"),
+        };
+        let per = match unit_secs {
+            Some(secs) => Duration::from_secs(amount * secs),
+            None => Duration::from_millis(amount),
+        };
+        if per.is_zero() {
+            anyhow::bail!("rate duration must be positive");
+        }
+
+        Ok(Self { count, per })
+    }
+}
+
+/// A token bucket shared by everything proving against one quota.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: Rate,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available tokens; negative when callers hold reservations.
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// A limiter starting with a full bucket.
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(rate.count),
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    /// Waits until a request may be made under the quota and returns how long
+    /// it was throttled.
+    pub async fn acquire(&self) -> Duration {
+        let wait = {
+            let mut bucket = self.bucket.lock().expect("rate limiter lock poisoned");
+            let now = Instant::now();
+            let per_sec = f64::from(self.rate.count) / self.rate.per.as_secs_f64();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_sec).min(f64::from(self.rate.count));
+            bucket.refilled = now;
+            bucket.tokens -= 1.0;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            info!(
+                "Throttling for {:.1}s to stay within {} requests per {:?}",
+                wait.as_secs_f64(),
+                self.rate.count,
+                self.rate.per
+            );
+            tokio::time::sleep(wait).await;
+        }
+        wait
+    }
+}
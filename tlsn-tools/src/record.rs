@@ -0,0 +1,202 @@
+//! Recording and replaying the prover's notary connection, for debugging.
+//!
+//! [`Recorder`] wraps the connection to the notary and appends every chunk
+//! read from or written to it to a file. [`Replay`] stands in for the notary
+//! and feeds a recording back to the prover, so a failing session can be
+//! re-run without the live server.
+//!
+//! Recordings hold the bytes above TLS, so a `--notary-tls` session replays
+//! without the TLS layer. The file starts with the line
+//! `DJINN-NOTARY-RECORDING/1`, followed by one frame per chunk: a direction
+//! byte (`>` sent to the notary, `<` received from it), the chunk length as a
+//! u32 little-endian and the chunk itself.
+//!
+//! A replay can't produce a valid proof. The prover's side of the MPC protocol
+//! is randomized on every run, so its messages won't match the recorded
+//! session, and the notary's recorded replies answer messages the new session
+//! never sent. Replay reproduces what doesn't depend on that: the order and
+//! sizes of the exchange, framing, and the notary's errors and rejections. A
+//! recorded reply is only fed back once the prover has written as many bytes
+//! as it had at that point in the recording, so a session that diverges in
+//! size stalls there until a timeout fires.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write as _};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use anyhow::{Context as _, Result};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// First line of a recording.
+const MAGIC: &[u8] = b"DJINN-NOTARY-RECORDING/1\n";
+
+const SENT: u8 = b'>';
+const RECEIVED: u8 = b'<';
+
+/// A connection to the notary, recorded, replayed or neither.
+pub trait NotaryStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> NotaryStream for T {}
+
+/// Wraps a connection and records everything read from and written to it.
+pub struct Recorder<T> {
+    inner: T,
+    log: BufWriter<File>,
+}
+
+impl<T> Recorder<T> {
+    /// Wraps `inner`, recording to `path`. An existing file is overwritten.
+    pub fn create(inner: T, path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create recording {}", path.display()))?;
+        let mut log = BufWriter::new(file);
+        log.write_all(MAGIC)?;
+        Ok(Self { inner, log })
+    }
+
+    fn record(&mut self, direction: u8, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let len = u32::try_from(data.len()).map_err(io::Error::other)?;
+        self.log.write_all(&[direction])?;
+        self.log.write_all(&len.to_le_bytes())?;
+        self.log.write_all(data)
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Recorder<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            self.record(RECEIVED, &buf.filled()[before..])?;
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Recorder<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &poll {
+            self.record(SENT, &buf[..*n])?;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.log.flush()?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.log.flush()?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Plays back the notary's side of a recording.
+///
+/// Writes are accepted and discarded; reads return the recorded replies, each
+/// once the prover has written as much as it had before that reply was
+/// received. After the last reply, reads return EOF.
+pub struct Replay {
+    /// Recorded replies, each with the bytes sent before it.
+    replies: VecDeque<(usize, Vec<u8>)>,
+    /// Offset into the front reply.
+    pos: usize,
+    written: usize,
+    waker: Option<Waker>,
+}
+
+impl Replay {
+    /// Loads a recording made by [`Recorder`].
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read recording {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("invalid recording {}", path.display()))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut rest = data.strip_prefix(MAGIC).context("not a notary recording")?;
+        let mut replies = VecDeque::new();
+        let mut sent = 0;
+        while !rest.is_empty() {
+            anyhow::ensure!(rest.len() >= 5, "truncated frame header");
+            let direction = rest[0];
+            let len = u32::from_le_bytes(rest[1..5].try_into().expect("4 bytes")) as usize;
+            let chunk = rest.get(5..5 + len).context("truncated frame")?;
+            match direction {
+                SENT => sent += len,
+                RECEIVED => replies.push_back((sent, chunk.to_vec())),
+                other => anyhow::bail!("unknown frame direction {other:#04x}"),
+            }
+            rest = &rest[5 + len..];
+        }
+        Ok(Self {
+            replies,
+            pos: 0,
+            written: 0,
+            waker: None,
+        })
+    }
+}
+
+impl AsyncRead for Replay {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let Some((sent_before, reply)) = this.replies.front() else {
+            return Poll::Ready(Ok(()));
+        };
+        if this.written < *sent_before {
+            this.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.remaining().min(reply.len() - this.pos);
+        buf.put_slice(&reply[this.pos..this.pos + n]);
+        this.pos += n;
+        if this.pos == reply.len() {
+            this.replies.pop_front();
+            this.pos = 0;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Replay {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.written += buf.len();
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
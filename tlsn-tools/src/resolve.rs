@@ -0,0 +1,80 @@
+//! Target host resolution over DNS-over-HTTPS or DNS-over-TLS.
+//!
+//! Keeps proving activity out of the local resolver's view. The resolved
+//! address is only used for the TCP connection; TLS SNI and the `Host` header
+//! keep using the hostname.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{Context, Result};
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+
+/// An encrypted DNS resolver endpoint.
+#[derive(Debug, Clone)]
+pub struct EncryptedResolver {
+    protocol: Protocol,
+    addr: SocketAddr,
+    tls_name: String,
+}
+
+impl EncryptedResolver {
+    /// Parses a resolver spec of the form `doh://<ip>[:port]` or
+    /// `dot://<ip>[:port]`.
+    ///
+    /// The resolver must be given by IP address (resolving it would leak to the
+    /// system resolver), so the name its certificate is checked against has to
+    /// be supplied separately as `tls_name`.
+    pub fn parse(spec: &str, tls_name: &str) -> Result<Self> {
+        let (scheme, rest) = spec
+            .split_once("://")
+            .context("resolver must look like doh://<ip> or dot://<ip>")?;
+        let (protocol, default_port) = match scheme {
+            "doh" => (Protocol::Https, 443),
+            "dot" => (Protocol::Tls, 853),
+            other => anyhow::bail!("unsupported resolver scheme: {other}"),
+        };
+        let rest = rest.trim_end_matches('/');
+        let addr = match rest.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => {
+                let ip: IpAddr = rest
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse()
+                    .with_context(|| format!("resolver address must be an IP: {rest}"))?;
+                SocketAddr::new(ip, default_port)
+            }
+        };
+        Ok(Self {
+            protocol,
+            addr,
+            tls_name: tls_name.to_string(),
+        })
+    }
+
+    /// Resolves `host` to a socket address with the given port.
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<SocketAddr> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, port));
+        }
+
+        let mut name_server = NameServerConfig::new(self.addr, self.protocol);
+        name_server.tls_dns_name = Some(self.tls_name.clone());
+        let mut config = ResolverConfig::new();
+        config.add_name_server(name_server);
+
+        let resolver = TokioAsyncResolver::tokio(config, ResolverOpts::default());
+        let lookup = resolver
+            .lookup_ip(host)
+            .await
+            .with_context(|| format!("failed to resolve {host} via {}", self.addr))?;
+        let ip = lookup
+            .iter()
+            .next()
+            .with_context(|| format!("no addresses found for {host}"))?;
+        Ok(SocketAddr::new(ip, port))
+    }
+}
@@ -0,0 +1,218 @@
+//! HTTP verification service, run with `djinn-tlsn-verifier serve`.
+//!
+//! Lets other services verify presentations with a network call instead of
+//! spawning a verifier process per proof. Endpoints:
+//!
+//! - `POST /verify` — the body is a serialized presentation, raw bincode when
+//!   sent as `application/octet-stream`, a JSON envelope (see
+//!   [`crate::envelope`]) when it starts with `{`, and base64 otherwise.
+//!   Responds with
+//!   the disclosed data as JSON, or `{"status": "failed", "error_code": ...,
+//!   "error": ...}` with an [`ErrorCode`].
+//! - `GET /health` — liveness probe.
+//! - `GET /metrics` — request counters in the Prometheus text format.
+//!
+//! Only built with the `server` feature.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::Value;
+use tlsn::attestation::CryptoProvider;
+use tracing::{info, warn};
+
+use crate::envelope;
+use crate::verify::{
+    self,
+    checks::Checks,
+    error::{error_code_of, ErrorCode},
+};
+
+/// Settings for the verification service.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Hex-encoded notary public keys to accept. Empty accepts any key, which
+    /// only proves the presentation is self-consistent.
+    pub trusted_keys: Vec<String>,
+    /// Largest decoded presentation accepted, in bytes.
+    pub max_presentation_bytes: u64,
+}
+
+struct AppState {
+    config: ServerConfig,
+    verified: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Serves the verification endpoints on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr, config: ServerConfig) -> Result<()> {
+    // The largest body is a presentation base64-encoded inside a JSON
+    // envelope, with the envelope's other fields around it.
+    let body_limit = usize::try_from(envelope::max_envelope_len(config.max_presentation_bytes))?;
+    if config.trusted_keys.is_empty() {
+        warn!(
+            "no trusted notary keys configured: presentations signed by any notary will be \
+             reported as verified; pass --trusted-key to restrict them"
+        );
+    }
+    let state = Arc::new(AppState {
+        config,
+        verified: AtomicU64::new(0),
+        failed: AtomicU64::new(0),
+    });
+
+    let app = Router::new()
+        .route("/verify", post(verify_handler))
+        .route("/health", get(|| async { "ok" }))
+        .route("/metrics", get(metrics_handler))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind {addr}"))?;
+    info!("Verification service listening on {}", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn verify_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<Value>) {
+    let binary = headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/octet-stream"));
+    // Base64 bodies are decoded as the presentation is deserialized, so the
+    // decoded copy is never held next to the body. The alphabet is checked
+    // up front so malformed text is still a client error.
+    let enveloped = !binary && envelope::is_envelope(&body);
+    let body = if binary || enveloped {
+        body
+    } else {
+        let text = body.slice_ref(body.trim_ascii());
+        if let Err(e) = check_base64(&text) {
+            state.failed.fetch_add(1, Ordering::Relaxed);
+            return failure(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::MalformedPresentation,
+                format!("invalid base64 body: {e}"),
+            );
+        }
+        text
+    };
+
+    let config = state.config.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        if enveloped {
+            let bytes = envelope::unwrap_presentation(&body, config.max_presentation_bytes)?;
+            verify_to_json(&bytes[..], &config)
+        } else if binary {
+            verify_to_json(&body[..], &config)
+        } else {
+            let engine = &base64::engine::general_purpose::STANDARD;
+            verify_to_json(base64::read::DecoderReader::new(&body[..], engine), &config)
+        }
+    })
+    .await
+    .context("verification task failed")
+    .and_then(|result| result);
+    match result {
+        Ok(output) => {
+            state.verified.fetch_add(1, Ordering::Relaxed);
+            (StatusCode::OK, Json(output))
+        }
+        Err(e) => {
+            state.failed.fetch_add(1, Ordering::Relaxed);
+            failure(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                error_code_of(&e),
+                format!("{e:#}"),
+            )
+        }
+    }
+}
+
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> String {
+    format!(
+        "# TYPE djinn_verifier_requests_total counter\n\
+         djinn_verifier_requests_total{{result=\"verified\"}} {}\n\
+         djinn_verifier_requests_total{{result=\"failed\"}} {}\n",
+        state.verified.load(Ordering::Relaxed),
+        state.failed.load(Ordering::Relaxed),
+    )
+}
+
+fn failure(status: StatusCode, code: ErrorCode, error: String) -> (StatusCode, Json<Value>) {
+    (
+        status,
+        Json(serde_json::json!({
+            "status": "failed",
+            "error_code": code,
+            "error": error,
+        })),
+    )
+}
+
+/// Checks that `text` is padded standard base64.
+fn check_base64(text: &[u8]) -> Result<(), String> {
+    if text.len() % 4 != 0 {
+        return Err(format!("length {} is not a multiple of 4", text.len()));
+    }
+    let padding = text.iter().rev().take_while(|&&b| b == b'=').count();
+    if padding > 2 {
+        return Err("too much padding".to_string());
+    }
+    match text[..text.len() - padding]
+        .iter()
+        .position(|&b| !(b.is_ascii_alphanumeric() || b == b'+' || b == b'/'))
+    {
+        Some(offset) => Err(format!(
+            "invalid byte {:#04x} at offset {offset}",
+            text[offset]
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Verifies a serialized presentation read from `reader` against the trust
+/// list and returns the disclosed data as JSON.
+fn verify_to_json(reader: impl std::io::Read, config: &ServerConfig) -> Result<Value> {
+    let verification = verify::verify_reader(
+        reader,
+        config.max_presentation_bytes,
+        &config.trusted_keys,
+        &Checks::default(),
+        &CryptoProvider::default(),
+    )?;
+    let data = &verification.data;
+    let time = chrono::DateTime::UNIX_EPOCH + Duration::from_secs(data.time);
+    let ranges = |ranges: &[std::ops::Range<usize>]| -> Vec<[usize; 2]> {
+        ranges.iter().map(|r| [r.start, r.end]).collect()
+    };
+
+    Ok(serde_json::json!({
+        "status": "verified",
+        "notary_key_alg": verification.notary_key_alg,
+        "notary_key": verification.notary_key,
+        "server_name": data.server_name,
+        "connection_time": time.to_rfc3339(),
+        "tls_version": data.tls_version,
+        "disclosed_digest": hex::encode(verify::disclosed_digest(data)),
+        "sent": String::from_utf8_lossy(&data.sent),
+        "received": String::from_utf8_lossy(&data.received),
+        "sent_authed": ranges(&data.sent_authed),
+        "received_authed": ranges(&data.received_authed),
+    }))
+}
@@ -0,0 +1,134 @@
+//! Machine-readable failure statuses for the prover's JSON summary.
+//!
+//! Errors that carry a [`StatusError`] or [`NotaryError`] anywhere in their
+//! chain are reported with its status; everything else is reported as
+//! `failed`.
+
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::notary::NotaryError;
+
+/// Status reported for errors without a more specific one.
+pub const FAILED: &str = "failed";
+
+/// An error tagged with the `status` value to report for it.
+#[derive(Debug)]
+pub struct StatusError {
+    pub status: &'static str,
+    pub message: String,
+}
+
+impl StatusError {
+    pub fn new(status: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Returns the status to report for `err`.
+pub fn status_of(err: &anyhow::Error) -> &'static str {
+    err.chain()
+        .find_map(|e| {
+            e.downcast_ref::<StatusError>()
+                .map(|e| e.status)
+                .or_else(|| e.downcast_ref::<NotaryError>().map(NotaryError::status))
+        })
+        .unwrap_or(FAILED)
+}
+
+/// Runs one phase of the protocol under its own timeout, failing with
+/// `status` if it doesn't complete in time.
+pub async fn with_timeout<T>(
+    status: &'static str,
+    timeout: Duration,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(StatusError::new(
+            status,
+            format!("{status}: no progress after {}s", timeout.as_secs()),
+        )
+        .into()),
+    }
+}
+
+/// Runs `fut` until the wall-clock `deadline`, failing with
+/// `deadline_exceeded` if it hasn't completed by then. A deadline already in
+/// the past fails without running `fut`.
+pub async fn with_deadline<T>(
+    deadline: SystemTime,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let remaining = deadline
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    let exceeded = || {
+        let deadline = chrono::DateTime::<chrono::Utc>::from(deadline);
+        StatusError::new(
+            "deadline_exceeded",
+            format!("deadline_exceeded: not done by {}", deadline.to_rfc3339()),
+        )
+    };
+    if remaining.is_zero() {
+        return Err(exceeded().into());
+    }
+    match tokio::time::timeout(remaining, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(exceeded().into()),
+    }
+}
+
+/// Tags an error from a notary-facing step as `notary_closed` when the
+/// session driver has already exited, i.e. the notary ended the session
+/// rather than the step failing locally.
+pub fn notary_error<T>(
+    err: impl Into<anyhow::Error>,
+    driver: &tokio::task::JoinHandle<T>,
+) -> anyhow::Error {
+    let err = err.into();
+    if driver.is_finished() {
+        StatusError::new(
+            "notary_closed",
+            format!("notary closed the session: {err:#}"),
+        )
+        .into()
+    } else {
+        err
+    }
+}
+
+/// Interprets the notary's reply to an attestation request that isn't a
+/// valid attestation. An empty reply or a plain-text message (e.g. a policy
+/// rejection) means the notary closed the session; the text is the reason.
+pub fn notary_reply_error(reply: &[u8], err: impl Into<anyhow::Error>) -> anyhow::Error {
+    if reply.is_empty() {
+        return StatusError::new(
+            "notary_closed",
+            "notary closed the connection without returning an attestation",
+        )
+        .into();
+    }
+    match std::str::from_utf8(reply) {
+        Ok(text) if text.chars().all(|c| !c.is_control() || c.is_whitespace()) => StatusError::new(
+            "notary_closed",
+            format!("notary closed the session: {}", text.trim()),
+        )
+        .into(),
+        _ => err.into().context("invalid attestation from notary"),
+    }
+}
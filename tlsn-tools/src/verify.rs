@@ -0,0 +1,596 @@
+//! Presentation verification into typed disclosed data.
+//!
+//! Failures are tagged with a stable [`error::ErrorCode`].
+
+pub mod checks;
+pub mod error;
+pub mod exchange;
+
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use tlsn::{
+    attestation::{
+        presentation::{Presentation, PresentationOutput},
+        CryptoProvider,
+    },
+    connection::TlsVersion,
+};
+
+use crate::{cert::LeafCert, envelope, inspect, output, KNOWN_NOTARY_KEY_ALGS};
+
+use self::checks::{CheckOutcome, Checks};
+use self::error::{ErrorCode, StaleSkipped, VerifyError};
+
+/// Byte substituted for redacted (unauthenticated) transcript bytes.
+pub const REDACTED_BYTE: u8 = b'X';
+
+/// Domain separator for [`disclosed_digest`], versioned so the canonical form
+/// can change without colliding with old digests.
+const DIGEST_DOMAIN: &[u8] = b"djinn-disclosed-v1";
+
+/// Data disclosed by a successfully verified presentation.
+#[derive(Debug, Clone)]
+pub struct VerifiedData {
+    /// Server name the attestation is bound to, if disclosed.
+    pub server_name: Option<String>,
+    /// Connection time attested by the notary (seconds since the UNIX epoch).
+    pub time: u64,
+    /// Attested TLS version of the connection: `TLS1.2` or `TLS1.3`.
+    pub tls_version: &'static str,
+    /// Sent transcript, with redacted bytes set to [`REDACTED_BYTE`].
+    pub sent: Vec<u8>,
+    /// Received transcript, with redacted bytes set to [`REDACTED_BYTE`].
+    pub received: Vec<u8>,
+    /// Disclosed ranges of `sent`, sorted and non-overlapping.
+    pub sent_authed: Vec<Range<usize>>,
+    /// Disclosed ranges of `received`, sorted and non-overlapping.
+    pub received_authed: Vec<Range<usize>>,
+}
+
+impl VerifiedData {
+    /// Builds the disclosed data from the output of `Presentation::verify`.
+    pub fn from_output(output: PresentationOutput) -> Result<Self> {
+        let mut transcript = output
+            .transcript
+            .context("presentation does not disclose a transcript")?;
+        transcript.set_unauthed(REDACTED_BYTE);
+
+        Ok(Self {
+            server_name: output.server_name.map(|name| name.to_string()),
+            time: output.connection_info.time,
+            tls_version: tls_version_name(&output.connection_info.version),
+            sent: transcript.sent_unsafe().to_vec(),
+            received: transcript.received_unsafe().to_vec(),
+            sent_authed: transcript.sent_authed().iter_ranges().collect(),
+            received_authed: transcript.received_authed().iter_ranges().collect(),
+        })
+    }
+
+    /// Returns true if every byte of `range` in the sent transcript was disclosed.
+    pub fn is_sent_revealed(&self, range: &Range<usize>) -> bool {
+        covers(&self.sent_authed, range)
+    }
+
+    /// Returns true if every byte of `range` in the received transcript was disclosed.
+    pub fn is_received_revealed(&self, range: &Range<usize>) -> bool {
+        covers(&self.received_authed, range)
+    }
+
+    /// Returns how many bytes of `range` in the sent transcript were disclosed.
+    pub fn sent_revealed_len(&self, range: &Range<usize>) -> usize {
+        overlap(&self.sent_authed, range)
+    }
+
+    /// Returns how many bytes of `range` in the received transcript were
+    /// disclosed.
+    pub fn received_revealed_len(&self, range: &Range<usize>) -> usize {
+        overlap(&self.received_authed, range)
+    }
+}
+
+/// Returns the name reported for a TLS version, e.g. `TLS1.3`.
+pub fn tls_version_name(version: &TlsVersion) -> &'static str {
+    match version {
+        TlsVersion::V1_2 => "TLS1.2",
+        TlsVersion::V1_3 => "TLS1.3",
+    }
+}
+
+fn overlap(authed: &[Range<usize>], range: &Range<usize>) -> usize {
+    authed
+        .iter()
+        .map(|r| {
+            r.end
+                .min(range.end)
+                .saturating_sub(r.start.max(range.start))
+        })
+        .sum()
+}
+
+fn covers(authed: &[Range<usize>], range: &Range<usize>) -> bool {
+    range.is_empty()
+        || authed
+            .iter()
+            .any(|r| r.start <= range.start && range.end <= r.end)
+}
+
+/// Verifies `presentation` and returns the data it discloses.
+pub fn verify(presentation: Presentation, provider: &CryptoProvider) -> Result<VerifiedData> {
+    let output = presentation
+        .verify(provider)
+        .map_err(VerifyError::from_presentation)?;
+    VerifiedData::from_output(output)
+}
+
+/// A verified presentation: the data it discloses, the notary key that
+/// signed it and the outcome of the [`Checks`] it was verified with.
+#[derive(Debug, Clone)]
+pub struct VerificationReport {
+    /// Notary key algorithm, e.g. `k256`.
+    pub notary_key_alg: String,
+    /// Hex-encoded notary public key.
+    pub notary_key: String,
+    /// Hex SHA-256 of the notary public key; see [`key_fingerprint`].
+    pub notary_key_fingerprint: String,
+    /// Position of the notary key in the trusted keys it was checked
+    /// against, if any were given.
+    pub notary_key_match: Option<usize>,
+    /// Set when the notary key algorithm is not one of
+    /// [`KNOWN_NOTARY_KEY_ALGS`] and [`Checks::reject_unknown_notary_alg`]
+    /// is off.
+    pub notary_key_alg_warning: Option<String>,
+    /// Hex SHA-256 of the serialized presentation, as a manifest records it.
+    pub presentation_sha256: String,
+    /// Everything disclosed: server name, connection time, transcripts and
+    /// the revealed ranges.
+    pub data: VerifiedData,
+    /// Which checks failed. A presentation that verifies but fails a check
+    /// is still reported, so callers must look at [`CheckOutcome::passed`].
+    pub checks: CheckOutcome,
+}
+
+/// Returns the fingerprint of a notary public key: the hex SHA-256 of its
+/// bytes, as the verifier's `fingerprint` command prints it.
+pub fn key_fingerprint(key: &[u8]) -> String {
+    hex::encode(Sha256::digest(key))
+}
+
+/// Deserializes, structurally checks and verifies a serialized presentation,
+/// then runs `checks` on what it discloses.
+///
+/// The presentation may be raw bincode or in a JSON envelope. Decoding is
+/// bounded by `max_bytes`. If `trusted_keys` (hex) is non-empty
+/// the notary key must be one of them; an empty list accepts any notary,
+/// which only proves the presentation is self-consistent. Failed checks
+/// don't fail the call; they are reported in [`VerificationReport::checks`].
+pub fn verify_bytes(
+    bytes: &[u8],
+    max_bytes: u64,
+    trusted_keys: &[String],
+    checks: &Checks,
+    provider: &CryptoProvider,
+) -> Result<VerificationReport> {
+    // Accept presentations in a JSON envelope; see [`crate::envelope`].
+    let bytes = envelope::unwrap_presentation(bytes, max_bytes)?;
+    if bytes.len() as u64 > max_bytes {
+        return Err(VerifyError::new(
+            ErrorCode::PresentationTooLarge,
+            format!(
+                "presentation is {} bytes, over the {max_bytes} byte limit",
+                bytes.len()
+            ),
+        )
+        .into());
+    }
+    let presentation = inspect::deserialize_presentation(&bytes, max_bytes).map_err(malformed)?;
+    verify_decoded(presentation, trusted_keys, checks, provider)
+}
+
+/// Like [`verify_bytes`], but decodes the presentation from `reader` as it
+/// reads, without buffering the serialized bytes. Presentations over
+/// `max_bytes` are reported as too large once that many bytes have been read.
+pub fn verify_reader(
+    reader: impl Read,
+    max_bytes: u64,
+    trusted_keys: &[String],
+    checks: &Checks,
+    provider: &CryptoProvider,
+) -> Result<VerificationReport> {
+    let presentation = inspect::deserialize_presentation_from(reader, max_bytes).map_err(|e| {
+        let size_limit = e.chain().any(|e| {
+            e.downcast_ref::<bincode::Error>()
+                .is_some_and(|e| matches!(**e, bincode::ErrorKind::SizeLimit))
+        });
+        if size_limit {
+            VerifyError::new(
+                ErrorCode::PresentationTooLarge,
+                format!("presentation is over the {max_bytes} byte limit"),
+            )
+        } else {
+            malformed(e)
+        }
+    })?;
+    verify_decoded(presentation, trusted_keys, checks, provider)
+}
+
+/// Reads a serialized presentation from `reader` for [`verify_bytes`].
+/// Input longer than a presentation of `max_bytes` in a JSON envelope is
+/// reported as too large without reading the rest.
+pub fn read_presentation(reader: impl Read, max_bytes: u64) -> Result<Vec<u8>> {
+    let max_input_len = envelope::max_envelope_len(max_bytes);
+    // Read one byte past the limit to tell an oversized input apart.
+    let mut bytes = Vec::new();
+    reader
+        .take(max_input_len + 1)
+        .read_to_end(&mut bytes)
+        .context("failed to read the presentation")?;
+    if bytes.len() as u64 > max_input_len {
+        return Err(VerifyError::new(
+            ErrorCode::PresentationTooLarge,
+            format!(
+                "presentation input is over {max_input_len} bytes, too large for \
+                 the {max_bytes} byte limit"
+            ),
+        )
+        .into());
+    }
+    Ok(bytes)
+}
+
+/// Like [`verify_bytes`], but reads the presentation from `reader`, telling
+/// its form from the first bytes read. Raw bincode and base64 text are
+/// decoded as they are read, so the serialized presentation is never held
+/// in memory; an envelope is read whole first, as by [`read_presentation`].
+pub fn verify_stream(
+    reader: impl Read,
+    max_bytes: u64,
+    trusted_keys: &[String],
+    checks: &Checks,
+    provider: &CryptoProvider,
+) -> Result<VerificationReport> {
+    let mut reader = BufReader::new(reader);
+    let head = reader
+        .fill_buf()
+        .context("failed to read the presentation")?;
+    let (enveloped, is_text) = (envelope::is_envelope(head), envelope::is_base64(head));
+    if enveloped {
+        let bytes = read_presentation(reader, max_bytes)?;
+        verify_bytes(&bytes, max_bytes, trusted_keys, checks, provider)
+    } else if is_text {
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let decoder = base64::read::DecoderReader::new(SkipWhitespace(reader), engine);
+        verify_reader(decoder, max_bytes, trusted_keys, checks, provider)
+    } else {
+        verify_reader(reader, max_bytes, trusted_keys, checks, provider)
+    }
+}
+
+/// Reads from the inner reader with ASCII whitespace dropped, e.g. the line
+/// breaks in wrapped base64.
+struct SkipWhitespace<R>(R);
+
+impl<R: BufRead> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let available = self.0.fill_buf()?;
+            let skip = available
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+            if skip == 0 {
+                let len = available
+                    .iter()
+                    .take(buf.len())
+                    .take_while(|b| !b.is_ascii_whitespace())
+                    .count();
+                buf[..len].copy_from_slice(&available[..len]);
+                self.0.consume(len);
+                return Ok(len);
+            }
+            self.0.consume(skip);
+        }
+    }
+}
+
+fn malformed(e: anyhow::Error) -> VerifyError {
+    VerifyError::new(ErrorCode::MalformedPresentation, format!("{e:#}"))
+}
+
+/// Structurally checks and verifies a decoded presentation; see
+/// [`verify_bytes`].
+///
+/// The notary key is checked against `trusted_keys` and the key algorithm
+/// against `checks` before the presentation is verified, and a mismatch is
+/// an error. So is a claimed connection time already over the maximum age,
+/// with [`Checks::skip_if_claimed_stale`].
+pub fn verify_decoded(
+    presentation: Presentation,
+    trusted_keys: &[String],
+    checks: &Checks,
+    provider: &CryptoProvider,
+) -> Result<VerificationReport> {
+    let layout = inspect::check_structure(&presentation)
+        .context("malformed presentation")
+        .map_err(malformed)?;
+
+    // The claimed time is unverified, so it can only cut a rejection short:
+    // a presentation that claims to be fresh is verified in full and its
+    // attested time checked again by `checks`.
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    if let (true, Some(max_age), Some(time)) = (
+        checks.skip_if_claimed_stale,
+        checks.max_age,
+        layout.connection_time,
+    ) {
+        let age = now.saturating_sub(time);
+        let skew = checks.clock_skew;
+        if age > max_age.saturating_add(skew) {
+            let err = VerifyError::new(
+                ErrorCode::TimestampOutOfRange,
+                format!(
+                    "claimed connection time is {age}s ago, over --max-age {max_age}s \
+                     (tolerance {skew}s)"
+                ),
+            );
+            return Err(anyhow::Error::new(err).context(StaleSkipped { claimed_time: time }));
+        }
+    }
+
+    let key = presentation.verifying_key();
+    let notary_key = hex::encode(&key.data);
+    let notary_key_alg = key.alg.to_string();
+    let notary_key_fingerprint = key_fingerprint(&key.data);
+    let notary_key_match = trusted_key_index(trusted_keys, &notary_key);
+    if !trusted_keys.is_empty() && notary_key_match.is_none() {
+        return Err(VerifyError::new(
+            ErrorCode::NotaryKeyMismatch,
+            format!("notary key {notary_key} is not trusted"),
+        )
+        .into());
+    }
+
+    // Judge the signing algorithm before trusting anything it signed.
+    if let Some(required) = &checks.require_notary_alg {
+        if !notary_key_alg.eq_ignore_ascii_case(required) {
+            return Err(VerifyError::new(
+                ErrorCode::NotaryKeyAlgRejected,
+                format!("notary key algorithm is {notary_key_alg}, not {required}"),
+            )
+            .into());
+        }
+    }
+    let alg_known = KNOWN_NOTARY_KEY_ALGS
+        .iter()
+        .any(|known| notary_key_alg.eq_ignore_ascii_case(known));
+    let notary_key_alg_warning =
+        (!alg_known).then(|| format!("unexpected notary key algorithm: {notary_key_alg}"));
+    if let (true, Some(warning)) = (checks.reject_unknown_notary_alg, &notary_key_alg_warning) {
+        return Err(VerifyError::new(ErrorCode::NotaryKeyAlgRejected, warning.clone()).into());
+    }
+
+    // Bincode encoding is canonical, so this is the digest of the
+    // presentation as it was serialized.
+    let mut hasher = Sha256::new();
+    bincode::serialize_into(&mut hasher, &presentation)?;
+    let presentation_sha256 = hex::encode(hasher.finalize());
+
+    // Verification consumes the presentation. The certificate's contents
+    // are only used once the chain has been verified.
+    let leaf_cert = checks
+        .needs_leaf_cert()
+        .then(|| LeafCert::from_presentation(&presentation));
+    let data = verify(presentation, provider)?;
+    let checks = checks.run(&notary_key, &data, leaf_cert.as_ref(), now);
+
+    Ok(VerificationReport {
+        notary_key_alg,
+        notary_key,
+        notary_key_fingerprint,
+        notary_key_match,
+        notary_key_alg_warning,
+        presentation_sha256,
+        data,
+        checks,
+    })
+}
+
+/// Returns the position of the hex `key` in `trusted_keys`. Hex is compared
+/// ignoring case, so keys copied in upper case still match.
+pub fn trusted_key_index(trusted_keys: &[String], key: &str) -> Option<usize> {
+    trusted_keys
+        .iter()
+        .position(|trusted| trusted.eq_ignore_ascii_case(key))
+}
+
+/// Reads a notary key allowlist: one hex key per line, with blank lines and
+/// `#` comments skipped. Returns each key with its line number.
+pub fn read_key_allowlist(path: &Path) -> Result<Vec<(usize, String)>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read notary allowlist {}", path.display()))?;
+    parse_key_allowlist(&text, path)
+}
+
+fn parse_key_allowlist(text: &str, path: &Path) -> Result<Vec<(usize, String)>> {
+    let mut keys = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let key = line.split('#').next().unwrap_or_default().trim();
+        if key.is_empty() {
+            continue;
+        }
+        hex::decode(key)
+            .with_context(|| format!("{}:{}: not a hex key: {key}", path.display(), i + 1))?;
+        keys.push((i + 1, key.to_string()));
+    }
+    anyhow::ensure!(
+        !keys.is_empty(),
+        "notary allowlist {} has no keys",
+        path.display()
+    );
+    Ok(keys)
+}
+
+/// Writes the presentation in `bytes` to `path` with only its cryptographic
+/// content, then re-reads and verifies the written copy and checks that it
+/// discloses exactly what `data` does. Returns the bytes written.
+///
+/// Presentations carry no metadata of their own; what gets dropped is the
+/// bundle around them (manifest, prover summary). Bincode encoding is
+/// canonical, so the output of a plain presentation file is byte-identical
+/// to its input.
+pub fn write_sanitized(
+    path: &Path,
+    bytes: &[u8],
+    max_bytes: u64,
+    data: &VerifiedData,
+    provider: &CryptoProvider,
+) -> Result<Vec<u8>> {
+    let presentation = inspect::deserialize_presentation(bytes, max_bytes)?;
+    output::write_atomic(path, &bincode::serialize(&presentation)?)?;
+
+    let written =
+        std::fs::read(path).with_context(|| format!("failed to read back {}", path.display()))?;
+    let reread = inspect::deserialize_presentation(&written, max_bytes)?;
+    let reverified = verify(reread, provider).context("sanitized copy does not verify")?;
+    anyhow::ensure!(
+        disclosed_digest(&reverified) == disclosed_digest(data),
+        "sanitized copy discloses different data"
+    );
+    Ok(written)
+}
+
+/// Computes a deterministic SHA-256 digest of the disclosed request and
+/// response, so independent verifiers derive the same identity for the same
+/// attested data.
+///
+/// The digest covers, in order, the ASCII domain tag `djinn-disclosed-v1`
+/// followed by the sent and then the received transcript, each encoded as:
+///
+/// - total transcript length (u64 little-endian),
+/// - number of disclosed ranges (u64 little-endian),
+/// - for each disclosed range in ascending order: start and end offsets
+///   (u64 little-endian each) followed by the disclosed bytes.
+///
+/// Redacted regions contribute only through the gaps between range offsets,
+/// so the digest does not depend on the fill byte used for display.
+pub fn disclosed_digest(verified: &VerifiedData) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(DIGEST_DOMAIN);
+    for (data, authed) in [
+        (&verified.sent, &verified.sent_authed),
+        (&verified.received, &verified.received_authed),
+    ] {
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update((authed.len() as u64).to_le_bytes());
+        for range in authed {
+            hasher.update((range.start as u64).to_le_bytes());
+            hasher.update((range.end as u64).to_le_bytes());
+            hasher.update(&data[range.clone()]);
+        }
+    }
+    hasher.finalize().into()
+}
+
+/// Checks that an attested connection `time` is no older than `max_age`
+/// seconds at `now`, allowing `skew` seconds of clock difference in either
+/// direction. Times are UNIX seconds.
+///
+/// The notary's attested time and this machine's clock are independent, so a
+/// failure reports the apparent skew and points at clock misconfiguration as a
+/// possible cause rather than just rejecting the proof as stale.
+pub fn check_freshness(time: u64, now: u64, max_age: u64, skew: u64) -> Result<(), String> {
+    if time > now.saturating_add(skew) {
+        return Err(format!(
+            "attested connection time is {}s ahead of this machine's clock \
+             (tolerance {skew}s); the local clock may be behind or the notary's \
+             ahead, see --assume-clock-skew",
+            time - now
+        ));
+    }
+    let age = now.saturating_sub(time);
+    if age > max_age.saturating_add(skew) {
+        return Err(format!(
+            "presentation is too old: connection was attested {age}s ago, over \
+             --max-age {max_age}s (tolerance {skew}s). If the proof is recent, \
+             this machine's clock may be {}s ahead of the notary's; check the \
+             system clock or set --assume-clock-skew",
+            age - max_age
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that an attested connection `time` lies within the window bounded
+/// by `not_before` and `not_after`, inclusive. Either bound may be open.
+/// Times are UNIX seconds.
+pub fn check_window(
+    time: u64,
+    not_before: Option<u64>,
+    not_after: Option<u64>,
+) -> Result<(), String> {
+    let rfc3339 = |secs: u64| {
+        (chrono::DateTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)).to_rfc3339()
+    };
+    if let Some(not_before) = not_before.filter(|&bound| time < bound) {
+        return Err(format!(
+            "attested connection time {} is before --not-before {}",
+            rfc3339(time),
+            rfc3339(not_before)
+        ));
+    }
+    if let Some(not_after) = not_after.filter(|&bound| time > bound) {
+        return Err(format!(
+            "attested connection time {} is after --not-after {}",
+            rfc3339(time),
+            rfc3339(not_after)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "02a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90";
+
+    #[test]
+    fn wrapped_base64_decodes_as_it_is_read() {
+        let text: &[u8] = b"YWJj\nZGVm\r\nZ2hp\n";
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let mut decoded = Vec::new();
+        base64::read::DecoderReader::new(SkipWhitespace(text), engine)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"abcdefghi");
+    }
+
+    #[test]
+    fn pinned_keys_match_in_any_case() {
+        let pinned = [KEY.to_ascii_uppercase()];
+        assert_eq!(trusted_key_index(&pinned, KEY), Some(0));
+        assert_eq!(trusted_key_index(&pinned, &KEY.replace("02", "03")), None);
+    }
+
+    #[test]
+    fn allowlist_keys_match_in_any_case() {
+        let text = format!(
+            "# production notaries\n\n{}  # primary\n",
+            KEY.to_ascii_uppercase()
+        );
+        let allowlist = parse_key_allowlist(&text, Path::new("notaries.txt")).unwrap();
+        assert_eq!(allowlist, vec![(3, KEY.to_ascii_uppercase())]);
+
+        let keys: Vec<String> = allowlist.into_iter().map(|(_, key)| key).collect();
+        assert_eq!(trusted_key_index(&keys, KEY), Some(0));
+    }
+
+    #[test]
+    fn allowlist_rejects_non_hex_lines() {
+        let err = parse_key_allowlist("not-a-key\n", Path::new("notaries.txt")).unwrap_err();
+        assert!(err.to_string().starts_with("notaries.txt:1:"), "{err}");
+    }
+}
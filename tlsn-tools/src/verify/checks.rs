@@ -0,0 +1,597 @@
+//! Checks on verified data beyond its signature.
+//!
+//! A valid presentation proves what was exchanged with some server at some
+//! time. [`Checks`] states what a caller expects of that exchange: the
+//! server, the attested time, the request that was sent and the response
+//! body. The verifier CLI builds them from its flags; embedders pass them in
+//! [`crate::VerifyOptions`]. Each failed check is reported in a
+//! [`CheckOutcome`] with its message, and the outcome as a whole with an
+//! [`ErrorCode`].
+
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::assertions::{self, ExpectJson, ExpectQuery, FieldComparison};
+use crate::cert::{self, LeafCert};
+use crate::jsonpath::JsonPath;
+use crate::policy::{RuleResult, VerificationPolicy};
+
+use super::error::ErrorCode;
+use super::exchange::Exchange;
+use super::VerifiedData;
+
+/// Expectations on verified data. The default checks nothing.
+///
+/// The notary key algorithm and the claimed age are judged before the
+/// presentation is verified, and reject it outright with an error; every
+/// other check runs on the verified data and is reported in a
+/// [`CheckOutcome`].
+#[derive(Debug, Clone, Default)]
+pub struct Checks {
+    /// Notary key algorithm the presentation must be signed with, e.g.
+    /// `k256` (case-insensitive).
+    pub require_notary_alg: Option<String>,
+    /// Reject notary keys of an algorithm outside
+    /// [`KNOWN_NOTARY_KEY_ALGS`](crate::KNOWN_NOTARY_KEY_ALGS), instead of
+    /// only reporting them in
+    /// [`VerificationReport::notary_key_alg_warning`](super::VerificationReport::notary_key_alg_warning).
+    pub reject_unknown_notary_alg: bool,
+    /// Skip verifying a presentation whose claimed connection time is
+    /// already over `max_age`. The claim is unverified, so this only cuts a
+    /// rejection short; see [`StaleSkipped`](super::error::StaleSkipped).
+    pub skip_if_claimed_stale: bool,
+    /// Value the disclosed User-Agent must equal.
+    pub expect_user_agent: Option<String>,
+    /// Pattern the disclosed User-Agent must match.
+    pub expect_user_agent_regex: Option<Regex>,
+    /// Maximum age of the attested connection, in seconds.
+    pub max_age: Option<u64>,
+    /// Clock difference tolerated by `max_age`, in seconds.
+    pub clock_skew: u64,
+    /// Earliest accepted connection time (UNIX seconds).
+    pub not_before: Option<u64>,
+    /// Latest accepted connection time (UNIX seconds).
+    pub not_after: Option<u64>,
+    /// Query parameters the request must carry.
+    pub expect_query: Vec<ExpectQuery>,
+    /// URL a 3xx response's disclosed Location must resolve to.
+    pub expect_location: Option<String>,
+    /// Require the server name to be disclosed.
+    pub require_server_name: bool,
+    /// Server name the attestation must be bound to (case-insensitive).
+    pub expect_server_name: Option<String>,
+    /// Required TLS version, as in [`VerifiedData::tls_version`].
+    pub require_tls_version: Option<String>,
+    /// Require part of the response body to be disclosed.
+    pub require_body: bool,
+    /// Inflate a compressed response body before the body checks.
+    pub decompress: bool,
+    /// Require the response body to be valid UTF-8.
+    pub require_utf8: bool,
+    /// Decode the response body as gRPC-Web frames.
+    #[cfg(feature = "grpc-web")]
+    pub grpc_web: bool,
+    /// Values the JSON response body must hold.
+    pub expect_json: Vec<ExpectJson>,
+    /// Comparisons between values of the JSON response body.
+    pub assert_compare: Vec<FieldComparison>,
+    /// Paths that must select nothing in the fully disclosed JSON body.
+    pub assert_absent: Vec<JsonPath>,
+    /// Require the server name among the leaf certificate's SubjectAltNames.
+    pub verify_name_in_cert: bool,
+    /// Hex SHA-256 pins of the leaf certificate's SubjectPublicKeyInfo; any
+    /// one matching is enough.
+    pub pin_spki: Vec<String>,
+    /// A policy to evaluate as well.
+    pub policy: Option<VerificationPolicy>,
+}
+
+/// What the leaf certificate checks found.
+#[derive(Debug, Clone)]
+pub struct CertCheck {
+    /// The certificate's SubjectAltNames.
+    pub sans: Vec<String>,
+    /// The SubjectAltName matching the server name, if any.
+    pub san_match: Option<String>,
+    /// Hex SHA-256 of the certificate's SubjectPublicKeyInfo.
+    pub spki_sha256: String,
+}
+
+/// The connection times a check accepted (UNIX seconds, inclusive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+}
+
+/// The result of running [`Checks`] on verified data.
+#[derive(Debug, Clone, Default)]
+pub struct CheckOutcome {
+    /// Description of each failed check. Empty if all passed.
+    pub failures: Vec<String>,
+    /// A server name check failed.
+    pub server_name_mismatch: bool,
+    /// The attested time is outside this window, the tighter of `max_age`
+    /// and `not_before`/`not_after`.
+    pub time_out_of_range: Option<TimeWindow>,
+    /// The inflated response body of the first exchange, when `decompress`
+    /// applied and succeeded.
+    pub decompressed_body: Option<Vec<u8>>,
+    /// The leaf certificate, when a certificate check ran on it.
+    pub cert: Option<CertCheck>,
+    /// The decoded gRPC-Web response of the first exchange.
+    #[cfg(feature = "grpc-web")]
+    pub grpc_web: Option<crate::grpc_web::GrpcWebResponse>,
+    /// Each policy rule's outcome, when a policy was given.
+    pub policy_rules: Option<Vec<RuleResult>>,
+}
+
+impl CheckOutcome {
+    /// Returns true if every check passed.
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// The code to report a failed outcome with. A proof for the wrong
+    /// server, or a stale or out-of-window one, is reported as such even if
+    /// other checks failed too.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        if self.passed() {
+            None
+        } else if self.server_name_mismatch {
+            Some(ErrorCode::ServerNameMismatch)
+        } else if self.time_out_of_range.is_some() {
+            Some(ErrorCode::TimestampOutOfRange)
+        } else {
+            Some(ErrorCode::PolicyRejected)
+        }
+    }
+}
+
+impl Checks {
+    /// Returns true if the checks read the leaf certificate, which has to be
+    /// taken from the presentation before verifying it.
+    pub fn needs_leaf_cert(&self) -> bool {
+        self.verify_name_in_cert || !self.pin_spki.is_empty()
+    }
+
+    /// The connection times accepted at `now`: the tighter of `max_age` (with
+    /// its skew tolerance) and `not_before`/`not_after`.
+    pub fn allowed_window(&self, now: u64) -> TimeWindow {
+        let age_bounds = self.max_age.map(|max_age| {
+            (
+                now.saturating_sub(max_age.saturating_add(self.clock_skew)),
+                now.saturating_add(self.clock_skew),
+            )
+        });
+        TimeWindow {
+            not_before: [age_bounds.map(|b| b.0), self.not_before]
+                .into_iter()
+                .flatten()
+                .max(),
+            not_after: [age_bounds.map(|b| b.1), self.not_after]
+                .into_iter()
+                .flatten()
+                .min(),
+        }
+    }
+
+    /// Runs every check on `data`, signed by `notary_key` (hex), at `now`
+    /// (UNIX seconds). `leaf_cert` is the presentation's leaf certificate
+    /// when [`needs_leaf_cert`](Self::needs_leaf_cert).
+    ///
+    /// The request and response checks run on every [`Exchange`]. With
+    /// several, each must pass, and a failure names the exchange by its
+    /// position, e.g. `exchange 1: response body is not disclosed`.
+    pub fn run(
+        &self,
+        notary_key: &str,
+        data: &VerifiedData,
+        leaf_cert: Option<&Result<LeafCert>>,
+        now: u64,
+    ) -> CheckOutcome {
+        let mut outcome = CheckOutcome::default();
+
+        let mut time_failed = false;
+        if let Some(max_age) = self.max_age {
+            if let Err(e) = super::check_freshness(data.time, now, max_age, self.clock_skew) {
+                outcome.failures.push(e);
+                time_failed = true;
+            }
+        }
+        if let Err(e) = super::check_window(data.time, self.not_before, self.not_after) {
+            outcome.failures.push(e);
+            time_failed = true;
+        }
+        if time_failed {
+            outcome.time_out_of_range = Some(self.allowed_window(now));
+        }
+
+        let failures = &mut outcome.failures;
+        if self.require_server_name && data.server_name.is_none() {
+            failures.push("server name is not disclosed".to_string());
+        }
+        if let Some(expected) = &self.expect_server_name {
+            let expected = expected.trim_end_matches('.');
+            match data.server_name.as_deref() {
+                Some(name) if name.trim_end_matches('.').eq_ignore_ascii_case(expected) => {}
+                Some(name) => {
+                    failures.push(format!(
+                        "server name mismatch: expected {expected:?}, got {name:?}"
+                    ));
+                    outcome.server_name_mismatch = true;
+                }
+                None => {
+                    failures.push(
+                        "server name is not disclosed, cannot check --expect-server-name"
+                            .to_string(),
+                    );
+                    outcome.server_name_mismatch = true;
+                }
+            }
+        }
+        if let Some(required) = &self.require_tls_version {
+            if data.tls_version != required.as_str() {
+                failures.push(format!(
+                    "TLS version mismatch: required {required}, attested {}",
+                    data.tls_version
+                ));
+            }
+        }
+
+        let exchanges = Exchange::all(data);
+        for (i, exchange) in exchanges.iter().enumerate() {
+            let checked = self.check_exchange(exchange);
+            if exchanges.len() > 1 {
+                let failures = checked.failures.iter();
+                outcome
+                    .failures
+                    .extend(failures.map(|failure| format!("exchange {i}: {failure}")));
+            } else {
+                outcome.failures.extend(checked.failures);
+            }
+            // The output describes the first exchange.
+            if i == 0 {
+                outcome.decompressed_body = checked.decompressed_body;
+                #[cfg(feature = "grpc-web")]
+                {
+                    outcome.grpc_web = checked.grpc_web;
+                }
+            }
+        }
+
+        let failures = &mut outcome.failures;
+        match leaf_cert {
+            Some(Ok(leaf)) => {
+                let server_name = data.server_name.as_deref().unwrap_or_default();
+                let san_match = cert::matching_san(server_name, &leaf.sans).map(str::to_string);
+                if self.verify_name_in_cert && san_match.is_none() {
+                    failures.push(format!(
+                        "server name {server_name:?} is not among the certificate's \
+                         SubjectAltNames {:?}",
+                        leaf.sans
+                    ));
+                }
+                let spki_sha256 = hex::encode(leaf.spki_sha256);
+                if !self.pin_spki.is_empty()
+                    && !self
+                        .pin_spki
+                        .iter()
+                        .any(|pin| pin.eq_ignore_ascii_case(&spki_sha256))
+                {
+                    failures.push(format!(
+                        "certificate public key {spki_sha256} matches no --pin-spki"
+                    ));
+                }
+                outcome.cert = Some(CertCheck {
+                    sans: leaf.sans.clone(),
+                    san_match,
+                    spki_sha256,
+                });
+            }
+            Some(Err(e)) => failures.push(format!("cannot check the leaf certificate: {e:#}")),
+            None => {}
+        }
+
+        if let Some(policy) = &self.policy {
+            let rules = policy.check(notary_key, data, now);
+            failures.extend(rules.iter().filter_map(RuleResult::failure));
+            outcome.policy_rules = Some(rules);
+        }
+        outcome
+    }
+
+    /// Runs the request and response checks on one exchange.
+    fn check_exchange(&self, exchange: &Exchange) -> ExchangeOutcome {
+        let mut outcome = ExchangeOutcome::default();
+        let failures = &mut outcome.failures;
+
+        match (
+            exchange.user_agent(),
+            &self.expect_user_agent,
+            &self.expect_user_agent_regex,
+        ) {
+            (None, Some(_), _) | (None, _, Some(_)) => {
+                failures.push("User-Agent header is not disclosed in the presentation".into())
+            }
+            (Some(ua), Some(expected), _) if ua != expected.as_str() => failures.push(format!(
+                "User-Agent mismatch: expected {expected:?}, got {ua:?}"
+            )),
+            (Some(ua), _, Some(pattern)) if !pattern.is_match(ua) => {
+                failures.push(format!("User-Agent {ua:?} does not match /{pattern}/"))
+            }
+            _ => {}
+        }
+
+        failures.extend(assertions::check_query(
+            &self.expect_query,
+            &exchange.request_head,
+            |range| exchange.is_request_head_revealed(range),
+        ));
+        if let Some(expected) = &self.expect_location {
+            let expected_resolved = exchange
+                .request_url()
+                .and_then(|base| crate::http::resolve_location(&base, expected))
+                .unwrap_or_else(|| expected.clone());
+            match exchange.location_resolved() {
+                None => failures
+                    .push("response is not a redirect with a disclosed Location header".into()),
+                Some(actual) if actual != expected_resolved => failures.push(format!(
+                    "Location mismatch: expected {expected_resolved:?}, got {actual:?}"
+                )),
+                Some(_) => {}
+            }
+        }
+
+        let no_body = exchange.no_body();
+        if self.require_body {
+            if let Some(no_body) = &no_body {
+                failures.push(format!("{no_body} has no body"));
+            } else if !exchange.is_body_disclosed() {
+                failures.push("response body is not disclosed".to_string());
+            }
+        }
+
+        if self.decompress {
+            match exchange.decode_body() {
+                Some(Ok(inflated)) => outcome.decompressed_body = Some(inflated),
+                Some(Err(e)) => {
+                    failures.push(format!("cannot decompress the response body: {e:#}"))
+                }
+                None => {}
+            }
+        }
+        let body = outcome
+            .decompressed_body
+            .as_deref()
+            .unwrap_or(exchange.body_bytes());
+
+        #[cfg(feature = "grpc-web")]
+        if self.grpc_web {
+            if let Some(no_body) = &no_body {
+                failures.push(format!("cannot decode gRPC-Web frames from a {no_body}"));
+            } else {
+                let text = exchange
+                    .content_type()
+                    .is_some_and(crate::grpc_web::is_text);
+                match crate::grpc_web::GrpcWebResponse::decode(body, text) {
+                    Ok(response) => outcome.grpc_web = Some(response),
+                    Err(e) => failures.push(format!("invalid gRPC-Web response body: {e:#}")),
+                }
+            }
+        }
+
+        if self.require_utf8 {
+            if let Err(e) = std::str::from_utf8(body) {
+                failures.push(format!(
+                    "response body is not valid UTF-8: invalid sequence at byte offset {}",
+                    e.valid_up_to()
+                ));
+            }
+        }
+
+        let json_checks = !self.expect_json.is_empty()
+            || !self.assert_compare.is_empty()
+            || !self.assert_absent.is_empty();
+        if let (true, Some(no_body)) = (json_checks, &no_body) {
+            failures.push(format!(
+                "cannot evaluate --expect-json/--assert-compare/--assert-absent on a {no_body}"
+            ));
+        } else if json_checks {
+            match serde_json::from_slice::<Value>(body) {
+                Ok(json) => {
+                    failures.extend(
+                        self.expect_json
+                            .iter()
+                            .filter_map(|assertion| assertion.check(&json).err()),
+                    );
+                    failures.extend(
+                        self.assert_compare
+                            .iter()
+                            .filter_map(|assertion| assertion.check(&json).err()),
+                    );
+                    // Absence over a partly hidden body would prove nothing.
+                    let fully_disclosed = exchange.is_body_revealed();
+                    for path in &self.assert_absent {
+                        if !fully_disclosed {
+                            failures.push(format!(
+                                "cannot assert {path} is absent: the response body is not \
+                                 fully disclosed"
+                            ));
+                        } else if !path.select(&json).is_empty() {
+                            failures.push(format!("{path} is present in the response body"));
+                        }
+                    }
+                }
+                Err(e) => failures.push(format!(
+                    "response body is not valid JSON, cannot evaluate \
+                     --expect-json/--assert-compare/--assert-absent: {e}"
+                )),
+            }
+        }
+        outcome
+    }
+}
+
+/// What the checks on one exchange found.
+#[derive(Default)]
+struct ExchangeOutcome {
+    failures: Vec<String>,
+    decompressed_body: Option<Vec<u8>>,
+    #[cfg(feature = "grpc-web")]
+    grpc_web: Option<crate::grpc_web::GrpcWebResponse>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::REDACTED_BYTE;
+
+    const REQUEST: &[u8] = b"GET /odds?sport=nba HTTP/1.1\r\nHost: api.example.com\r\n\
+                             User-Agent: djinn/1.0\r\n\r\n";
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n{\"price\":1.5}";
+    const TIME: u64 = 1_700_000_000;
+
+    fn data() -> VerifiedData {
+        VerifiedData {
+            server_name: Some("api.example.com".to_string()),
+            time: TIME,
+            tls_version: "TLS1.3",
+            sent: REQUEST.to_vec(),
+            received: RESPONSE.to_vec(),
+            sent_authed: vec![0..REQUEST.len()],
+            received_authed: vec![0..RESPONSE.len()],
+        }
+    }
+
+    fn run(checks: &Checks, data: &VerifiedData) -> CheckOutcome {
+        checks.run("02ab", data, None, TIME + 10)
+    }
+
+    #[test]
+    fn default_checks_pass() {
+        let outcome = run(&Checks::default(), &data());
+        assert!(outcome.passed());
+        assert_eq!(outcome.error_code(), None);
+    }
+
+    #[test]
+    fn request_and_body_checks_pass_on_matching_data() {
+        let checks = Checks {
+            expect_user_agent: Some("djinn/1.0".to_string()),
+            expect_query: vec!["sport=nba".parse().unwrap()],
+            expect_server_name: Some("API.example.com.".to_string()),
+            require_tls_version: Some("TLS1.3".to_string()),
+            require_body: true,
+            require_utf8: true,
+            expect_json: vec!["$.price=1.5".parse().unwrap()],
+            assert_absent: vec!["$.error".parse().unwrap()],
+            ..Checks::default()
+        };
+        let outcome = run(&checks, &data());
+        assert!(outcome.passed(), "{:?}", outcome.failures);
+    }
+
+    #[test]
+    fn server_name_mismatch_outranks_other_failures() {
+        let checks = Checks {
+            expect_server_name: Some("other.example.com".to_string()),
+            max_age: Some(1),
+            require_tls_version: Some("TLS1.2".to_string()),
+            ..Checks::default()
+        };
+        let outcome = run(&checks, &data());
+        assert_eq!(outcome.failures.len(), 3);
+        assert_eq!(outcome.error_code(), Some(ErrorCode::ServerNameMismatch));
+    }
+
+    #[test]
+    fn stale_data_reports_the_allowed_window() {
+        let checks = Checks {
+            max_age: Some(5),
+            clock_skew: 2,
+            not_after: Some(TIME + 100),
+            ..Checks::default()
+        };
+        let outcome = run(&checks, &data());
+        assert_eq!(outcome.error_code(), Some(ErrorCode::TimestampOutOfRange));
+        assert_eq!(
+            outcome.time_out_of_range,
+            Some(TimeWindow {
+                not_before: Some(TIME + 3),
+                not_after: Some(TIME + 12),
+            })
+        );
+    }
+
+    #[test]
+    fn redacted_values_fail_their_checks() {
+        let mut data = data();
+        let ua = REQUEST.windows(9).position(|w| w == b"djinn/1.0").unwrap();
+        data.sent[ua..ua + 9].fill(REDACTED_BYTE);
+        data.sent_authed = vec![0..ua, ua + 9..REQUEST.len()];
+        let body = RESPONSE.len() - 13;
+        data.received[body..].fill(REDACTED_BYTE);
+        data.received_authed = vec![0..body];
+        let checks = Checks {
+            expect_user_agent: Some("djinn/1.0".to_string()),
+            require_body: true,
+            assert_absent: vec!["$.error".parse().unwrap()],
+            ..Checks::default()
+        };
+        let outcome = run(&checks, &data);
+        assert_eq!(outcome.error_code(), Some(ErrorCode::PolicyRejected));
+        assert_eq!(
+            outcome.failures,
+            [
+                "User-Agent header is not disclosed in the presentation",
+                "response body is not disclosed",
+                "response body is not valid JSON, cannot evaluate \
+                 --expect-json/--assert-compare/--assert-absent: expected value at line 1 column 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn pipelined_exchanges_are_each_checked() {
+        let second = b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n{\"price\":2.5}";
+        let mut data = data();
+        data.sent = [REQUEST, REQUEST].concat();
+        data.received = [RESPONSE, second].concat();
+        data.sent_authed = vec![0..data.sent.len()];
+        data.received_authed = vec![0..data.received.len()];
+        let checks = Checks {
+            expect_user_agent: Some("djinn/1.0".to_string()),
+            expect_json: vec!["$.price=1.5".parse().unwrap()],
+            ..Checks::default()
+        };
+        let outcome = run(&checks, &data);
+        assert_eq!(outcome.failures.len(), 1, "{:?}", outcome.failures);
+        assert!(outcome.failures[0].starts_with("exchange 1: "));
+    }
+
+    #[test]
+    fn certificate_checks_report_the_leaf() {
+        let leaf = Ok(LeafCert {
+            sans: vec!["*.example.com".to_string()],
+            spki_sha256: [0xab; 32],
+        });
+        let checks = Checks {
+            verify_name_in_cert: true,
+            pin_spki: vec!["00".repeat(32)],
+            ..Checks::default()
+        };
+        let outcome = checks.run("02ab", &data(), Some(&leaf), TIME);
+        let cert = outcome.cert.as_ref().unwrap();
+        assert_eq!(cert.san_match.as_deref(), Some("*.example.com"));
+        assert_eq!(cert.spki_sha256, "ab".repeat(32));
+        assert_eq!(
+            outcome.failures,
+            [format!(
+                "certificate public key {} matches no --pin-spki",
+                "ab".repeat(32)
+            )]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Machine-readable verification failure codes.
+//!
+//! Every failed verification is reported with an [`ErrorCode`] next to the
+//! human-readable message, so pipelines can tell a forged attestation from an
+//! untrusted notary or a stale proof without parsing messages. The codes are
+//! stable: new ones may be added, existing ones keep their meaning and
+//! spelling.
+//!
+//! Errors carrying a [`VerifyError`] anywhere in their chain report its code;
+//! everything else is [`ErrorCode::VerificationFailed`].
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+use tlsn::{
+    attestation::presentation::PresentationError, connection::ServerIdentityProofError,
+    transcript::TranscriptProofError,
+};
+
+/// Why a presentation was not accepted. Serialized as [`ErrorCode::as_str`],
+/// e.g. `SIGNATURE_INVALID`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The notary's signature over the attestation doesn't verify.
+    SignatureInvalid,
+    /// The server's certificate chain, handshake signature or name doesn't
+    /// verify against the trusted roots.
+    CertChainInvalid,
+    /// The disclosed transcript doesn't match the attested commitments.
+    TranscriptMismatch,
+    /// The notary key is not the expected one or not among the trusted keys.
+    NotaryKeyMismatch,
+    /// The notary key algorithm is not the required one or not a known one.
+    NotaryKeyAlgRejected,
+    /// The presentation can't be decoded or is structurally invalid.
+    MalformedPresentation,
+    /// The presentation is over the size limit.
+    PresentationTooLarge,
+    /// The presentation verified, but for a server other than the expected
+    /// one, or without disclosing which.
+    ServerNameMismatch,
+    /// The presentation verified, but its attested connection time is too
+    /// old or outside the accepted window.
+    TimestampOutOfRange,
+    /// The presentation verified, but a policy check rejected it.
+    PolicyRejected,
+    /// The verifier's own input is unusable: a missing required option or an
+    /// unreadable bundle, trust bundle or policy file.
+    InvalidInput,
+    /// Verification failed for a reason none of the other codes describe.
+    VerificationFailed,
+}
+
+impl ErrorCode {
+    /// The code's name in `SCREAMING_SNAKE_CASE`, as it is displayed and
+    /// serialized.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SignatureInvalid => "SIGNATURE_INVALID",
+            Self::CertChainInvalid => "CERT_CHAIN_INVALID",
+            Self::TranscriptMismatch => "TRANSCRIPT_MISMATCH",
+            Self::NotaryKeyMismatch => "NOTARY_KEY_MISMATCH",
+            Self::NotaryKeyAlgRejected => "NOTARY_KEY_ALG_REJECTED",
+            Self::MalformedPresentation => "MALFORMED_PRESENTATION",
+            Self::PresentationTooLarge => "PRESENTATION_TOO_LARGE",
+            Self::ServerNameMismatch => "SERVER_NAME_MISMATCH",
+            Self::TimestampOutOfRange => "TIMESTAMP_OUT_OF_RANGE",
+            Self::PolicyRejected => "POLICY_REJECTED",
+            Self::InvalidInput => "INVALID_INPUT",
+            Self::VerificationFailed => "VERIFICATION_FAILED",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// An error tagged with the [`ErrorCode`] to report for it.
+#[derive(Debug)]
+pub struct VerifyError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl VerifyError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Classifies an error from `Presentation::verify` by the proof that
+    /// failed.
+    ///
+    /// The server identity and transcript proofs fail with their own error
+    /// types, kept as the source of the presentation error. Anything else
+    /// comes from the attestation proof: the notary's signature, or the
+    /// attestation fields it signs.
+    pub fn from_presentation(err: PresentationError) -> Self {
+        let err = anyhow::Error::new(err);
+        let code = err
+            .chain()
+            .find_map(|e| {
+                if e.is::<ServerIdentityProofError>() {
+                    Some(ErrorCode::CertChainInvalid)
+                } else if e.is::<TranscriptProofError>() {
+                    Some(ErrorCode::TranscriptMismatch)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ErrorCode::SignatureInvalid);
+        Self::new(code, format!("{err:#}"))
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Context on a [`ErrorCode::TimestampOutOfRange`] error for a presentation
+/// rejected without verifying it, because the connection time it claims is
+/// already too old; see
+/// [`Checks::skip_if_claimed_stale`](super::checks::Checks::skip_if_claimed_stale).
+#[derive(Debug)]
+pub struct StaleSkipped {
+    /// The claimed, unverified connection time (UNIX seconds).
+    pub claimed_time: u64,
+}
+
+impl fmt::Display for StaleSkipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("skipped without verifying")
+    }
+}
+
+/// Returns the code to report for `err`.
+pub fn error_code_of(err: &anyhow::Error) -> ErrorCode {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<VerifyError>().map(|e| e.code))
+        .unwrap_or(ErrorCode::VerificationFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_serialize_as_their_names() {
+        for code in [
+            ErrorCode::SignatureInvalid,
+            ErrorCode::NotaryKeyAlgRejected,
+            ErrorCode::VerificationFailed,
+        ] {
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::Value::String(code.to_string())
+            );
+        }
+        assert_eq!(ErrorCode::CertChainInvalid.as_str(), "CERT_CHAIN_INVALID");
+    }
+
+    #[test]
+    fn error_code_is_found_anywhere_in_the_chain() {
+        let err = anyhow::Error::new(VerifyError::new(ErrorCode::NotaryKeyMismatch, "untrusted"))
+            .context("verification failed");
+        assert_eq!(error_code_of(&err), ErrorCode::NotaryKeyMismatch);
+        assert_eq!(
+            error_code_of(&anyhow::anyhow!("no code")),
+            ErrorCode::VerificationFailed
+        );
+    }
+}
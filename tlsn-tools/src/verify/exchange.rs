@@ -0,0 +1,231 @@
+//! The request/response exchanges of verified data.
+//!
+//! A connection may carry several requests sent one after the other, each
+//! with its response. The checks run on every [`Exchange`]; the verifier's
+//! flat output fields describe the first. Header values only count once they
+//! are disclosed; the status line and `Content-Type` are read even when the
+//! rest is hidden.
+
+use std::ops::Range;
+
+use anyhow::Result;
+
+use crate::http;
+
+use super::VerifiedData;
+
+/// One request of verified data and the final response to it, after any 1xx
+/// interim responses.
+#[derive(Debug, Clone)]
+pub struct Exchange<'a> {
+    data: &'a VerifiedData,
+    /// Start of the request in the sent transcript.
+    pub request_start: usize,
+    /// End of the request in the sent transcript.
+    pub request_end: usize,
+    /// Start of the response, interim responses included, in the received
+    /// transcript.
+    pub response_start: usize,
+    /// End of the response in the received transcript.
+    pub response_end: usize,
+    /// Head of the request, without the blank line.
+    pub request_head: String,
+    /// Head of the final response, with the blank line.
+    pub response_head: String,
+    /// Offset of `response_head` in the received transcript.
+    pub head_start: usize,
+    /// The response body in the received transcript, as sent on the wire.
+    pub body: Range<usize>,
+    /// Request method, e.g. `GET`.
+    pub method: Option<String>,
+    /// Status code of the final response.
+    pub status: Option<u16>,
+}
+
+impl<'a> Exchange<'a> {
+    /// Locates the first exchange in `data`.
+    pub fn first(data: &'a VerifiedData) -> Self {
+        let request = http::message_ranges(&data.sent)
+            .first()
+            .cloned()
+            .unwrap_or(0..data.sent.len());
+        let response = http::message_ranges(&data.received)
+            .first()
+            .cloned()
+            .unwrap_or(0..data.received.len());
+        Self::new(data, request, response)
+    }
+
+    /// Locates every exchange in `data`, in the order the requests were
+    /// sent. The n-th request is paired with the n-th response; one without
+    /// a response gets an empty one at the end of the received transcript.
+    pub fn all(data: &'a VerifiedData) -> Vec<Self> {
+        let requests = http::message_ranges(&data.sent);
+        if requests.len() < 2 {
+            return vec![Self::first(data)];
+        }
+        let responses = http::message_ranges(&data.received);
+        let end = data.received.len();
+        requests
+            .into_iter()
+            .enumerate()
+            .map(|(i, request)| {
+                let response = responses.get(i).cloned().unwrap_or(end..end);
+                Self::new(data, request, response)
+            })
+            .collect()
+    }
+
+    fn new(data: &'a VerifiedData, request: Range<usize>, response: Range<usize>) -> Self {
+        let request_text = String::from_utf8_lossy(&data.sent[request.clone()]);
+        let request_head = http::split_message(&request_text).0.to_string();
+
+        let received = &data.received[response.clone()];
+        let (head_start, body_start) =
+            http::final_response_bounds(received).unwrap_or((0, received.len()));
+        let response_head = String::from_utf8_lossy(&received[head_start..body_start]).into_owned();
+
+        Self {
+            data,
+            request_start: request.start,
+            request_end: request.end,
+            response_start: response.start,
+            response_end: response.end,
+            method: http::request_method(&request_head).map(str::to_string),
+            status: http::status_code(&response_head),
+            request_head,
+            response_head,
+            head_start: response.start + head_start,
+            body: response.start + body_start..response.end,
+        }
+    }
+
+    /// The request, as attested.
+    pub fn request(&self) -> &'a [u8] {
+        &self.data.sent[self.request_start..self.request_end]
+    }
+
+    /// The response with its interim responses, as attested.
+    pub fn response(&self) -> &'a [u8] {
+        &self.data.received[self.response_start..self.response_end]
+    }
+
+    /// The response body, as sent on the wire.
+    pub fn body_bytes(&self) -> &'a [u8] {
+        &self.data.received[self.body.clone()]
+    }
+
+    /// The request body in the sent transcript, if the request head is
+    /// complete.
+    pub fn request_body(&self) -> Option<Range<usize>> {
+        let request = String::from_utf8_lossy(self.request());
+        http::split_message(&request)
+            .1
+            .map(|_| self.request_start + self.request_head.len() + 4..self.request_end)
+    }
+
+    /// Why the response carries no body: `HEAD response`, or e.g. `204
+    /// response`. HEAD, 204 and 304 responses carry headers only.
+    pub fn no_body(&self) -> Option<String> {
+        match self.status {
+            _ if self.method.as_deref() == Some("HEAD") => Some("HEAD response".to_string()),
+            Some(code) if http::is_bodiless_status(code) => Some(format!("{code} response")),
+            _ => None,
+        }
+    }
+
+    /// Returns true if every byte of the response body was disclosed.
+    pub fn is_body_revealed(&self) -> bool {
+        self.data.is_received_revealed(&self.body)
+    }
+
+    /// Returns true if any byte of the response body was disclosed.
+    pub fn is_body_disclosed(&self) -> bool {
+        !self.body.is_empty()
+            && self
+                .data
+                .received_authed
+                .iter()
+                .any(|r| r.start < self.body.end && r.end > self.body.start)
+    }
+
+    /// The value of a request header, if disclosed.
+    pub fn sent_header(&self, name: &str) -> Option<&str> {
+        http::header_value_range(&self.request_head, name)
+            .filter(|range| self.is_request_head_revealed(range))
+            .map(|range| &self.request_head[range])
+    }
+
+    /// The value of a response header, if disclosed.
+    pub fn received_header(&self, name: &str) -> Option<&str> {
+        http::header_value_range(&self.response_head, name)
+            .filter(|range| self.is_response_head_revealed(range))
+            .map(|range| &self.response_head[range])
+    }
+
+    /// Returns true if `range` of `request_head` was disclosed.
+    pub fn is_request_head_revealed(&self, range: &Range<usize>) -> bool {
+        let start = self.request_start;
+        self.data
+            .is_sent_revealed(&(start + range.start..start + range.end))
+    }
+
+    /// Returns true if `range` of `response_head` was disclosed.
+    pub fn is_response_head_revealed(&self, range: &Range<usize>) -> bool {
+        self.data
+            .is_received_revealed(&(self.head_start + range.start..self.head_start + range.end))
+    }
+
+    /// The disclosed User-Agent of the request.
+    pub fn user_agent(&self) -> Option<&str> {
+        self.sent_header("user-agent")
+    }
+
+    /// The response Content-Type.
+    pub fn content_type(&self) -> Option<&str> {
+        http::header_value(&self.response_head, "content-type")
+    }
+
+    /// The response Content-Encoding, unless `identity`.
+    pub fn content_encoding(&self) -> Option<&str> {
+        http::header_value(&self.response_head, "content-encoding")
+            .filter(|encoding| !encoding.eq_ignore_ascii_case("identity"))
+    }
+
+    /// Inflates a compressed response body. `None` if there is nothing to
+    /// inflate.
+    ///
+    /// The attestation covers the compressed bytes, so the body is only
+    /// inflated if all of it was disclosed.
+    pub fn decode_body(&self) -> Option<Result<Vec<u8>>> {
+        let encoding = self.content_encoding()?;
+        if self.no_body().is_some() || self.body.is_empty() {
+            return None;
+        }
+        Some(if self.is_body_revealed() {
+            http::decode_content(self.body_bytes(), encoding)
+        } else {
+            Err(anyhow::anyhow!("the body is not fully disclosed"))
+        })
+    }
+
+    /// The attested request URL, on the attested server.
+    pub fn request_url(&self) -> Option<String> {
+        let server_name = self.data.server_name.as_deref().unwrap_or_default();
+        http::request_target(&self.request_head)
+            .map(|target| format!("https://{server_name}{target}"))
+    }
+
+    /// The disclosed Location of a 3xx response.
+    pub fn location(&self) -> Option<&str> {
+        self.status
+            .filter(|&code| http::is_redirect(code))
+            .and_then(|_| self.received_header("location"))
+    }
+
+    /// The Location of a 3xx response, resolved against the request URL.
+    pub fn location_resolved(&self) -> Option<String> {
+        let location = self.location()?;
+        http::resolve_location(&self.request_url()?, location)
+    }
+}
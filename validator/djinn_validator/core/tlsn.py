@@ -83,7 +83,7 @@ async def verify_proof(
         f.write(presentation_bytes)
         presentation_path = f.name
 
-    base_cmd = [VERIFIER_BINARY, "--presentation", presentation_path]
+    base_cmd = [VERIFIER_BINARY, "verify", "--presentation", presentation_path]
 
     # Try each trusted notary key until one succeeds
     keys_to_try = list(TRUSTED_NOTARY_KEYS) if TRUSTED_NOTARY_KEYS else [None]